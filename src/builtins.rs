@@ -0,0 +1,382 @@
+//! Keywords, types, and functions that WGSL predeclares.
+//! https://www.w3.org/TR/WGSL/#builtin-functions
+
+pub const KEYWORDS: &[&str] = &[
+    "alias",
+    "break",
+    "case",
+    "const",
+    "const_assert",
+    "continue",
+    "continuing",
+    "default",
+    "diagnostic",
+    "discard",
+    "else",
+    "enable",
+    "false",
+    "fn",
+    "for",
+    "if",
+    "let",
+    "loop",
+    "override",
+    "requires",
+    "return",
+    "struct",
+    "switch",
+    "true",
+    "var",
+    "while",
+];
+
+pub const TYPES: &[&str] = &[
+    "bool",
+    "f16",
+    "f32",
+    "i32",
+    "u32",
+    "vec2",
+    "vec3",
+    "vec4",
+    "vec2f",
+    "vec3f",
+    "vec4f",
+    "vec2i",
+    "vec3i",
+    "vec4i",
+    "vec2u",
+    "vec3u",
+    "vec4u",
+    "vec2h",
+    "vec3h",
+    "vec4h",
+    "mat2x2",
+    "mat2x3",
+    "mat2x4",
+    "mat3x2",
+    "mat3x3",
+    "mat3x4",
+    "mat4x2",
+    "mat4x3",
+    "mat4x4",
+    "mat2x2f",
+    "mat3x3f",
+    "mat4x4f",
+    "array",
+    "atomic",
+    "ptr",
+    "sampler",
+    "sampler_comparison",
+    "texture_1d",
+    "texture_2d",
+    "texture_2d_array",
+    "texture_3d",
+    "texture_cube",
+    "texture_cube_array",
+    "texture_multisampled_2d",
+    "texture_depth_2d",
+    "texture_depth_2d_array",
+    "texture_depth_cube",
+    "texture_depth_cube_array",
+    "texture_depth_multisampled_2d",
+    "texture_external",
+    "texture_storage_1d",
+    "texture_storage_2d",
+    "texture_storage_2d_array",
+    "texture_storage_3d",
+];
+
+/// Predeclared enumerants like address spaces, and the names that only mean something inside
+/// attributes like `@builtin(position)`.
+/// https://www.w3.org/TR/WGSL/#predeclared-enumerants
+pub const ENUMERANTS: &[&str] = &[
+    // access modes
+    "read",
+    "write",
+    "read_write",
+    // address spaces
+    "function",
+    "private",
+    "workgroup",
+    "uniform",
+    "storage",
+    "push_constant",
+    // texel formats
+    "rgba8unorm",
+    "rgba8snorm",
+    "rgba8uint",
+    "rgba8sint",
+    "bgra8unorm",
+    "rgba16uint",
+    "rgba16sint",
+    "rgba16float",
+    "r32uint",
+    "r32sint",
+    "r32float",
+    "rg32uint",
+    "rg32sint",
+    "rg32float",
+    "rgba32uint",
+    "rgba32sint",
+    "rgba32float",
+    // built-in values
+    "vertex_index",
+    "instance_index",
+    "position",
+    "front_facing",
+    "frag_depth",
+    "sample_index",
+    "sample_mask",
+    "local_invocation_id",
+    "local_invocation_index",
+    "global_invocation_id",
+    "workgroup_id",
+    "num_workgroups",
+    // interpolation
+    "perspective",
+    "linear",
+    "flat",
+    "center",
+    "centroid",
+    "sample",
+    "first",
+    "either",
+];
+
+/// Directives understood by naga_oil's preprocessor.
+pub const DIRECTIVES: &[&str] = &[
+    "import",
+    "define_import_path",
+    "ifdef",
+    "ifndef",
+    "if",
+    "else",
+    "endif",
+];
+
+pub struct BuiltinFunction {
+    pub name: &'static str,
+    /// Every overload as a declaration, using the spec's type placeholders like `T` and `vecN<T>`.
+    pub overloads: &'static [&'static str],
+}
+
+macro_rules! builtins {
+    ($($name:literal: [$($overload:literal),+ $(,)?]),+ $(,)?) => {
+        &[$(BuiltinFunction { name: $name, overloads: &[$($overload),+] }),+]
+    };
+}
+
+pub const FUNCTIONS: &[BuiltinFunction] = builtins! {
+    // bit reinterpretation
+    "bitcast": ["fn bitcast<T>(e: S) -> T"],
+    // logical
+    "all": ["fn all(e: vecN<bool>) -> bool", "fn all(e: bool) -> bool"],
+    "any": ["fn any(e: vecN<bool>) -> bool", "fn any(e: bool) -> bool"],
+    "select": [
+        "fn select(f: T, t: T, cond: bool) -> T",
+        "fn select(f: vecN<T>, t: vecN<T>, cond: vecN<bool>) -> vecN<T>",
+    ],
+    // array
+    "arrayLength": ["fn arrayLength(p: ptr<storage, array<E>, AM>) -> u32"],
+    // numeric
+    "abs": ["fn abs(e: T) -> T"],
+    "acos": ["fn acos(e: T) -> T"],
+    "acosh": ["fn acosh(e: T) -> T"],
+    "asin": ["fn asin(e: T) -> T"],
+    "asinh": ["fn asinh(e: T) -> T"],
+    "atan": ["fn atan(e: T) -> T"],
+    "atanh": ["fn atanh(e: T) -> T"],
+    "atan2": ["fn atan2(y: T, x: T) -> T"],
+    "ceil": ["fn ceil(e: T) -> T"],
+    "clamp": ["fn clamp(e: T, low: T, high: T) -> T"],
+    "cos": ["fn cos(e: T) -> T"],
+    "cosh": ["fn cosh(e: T) -> T"],
+    "countLeadingZeros": ["fn countLeadingZeros(e: T) -> T"],
+    "countOneBits": ["fn countOneBits(e: T) -> T"],
+    "countTrailingZeros": ["fn countTrailingZeros(e: T) -> T"],
+    "cross": ["fn cross(e1: vec3<T>, e2: vec3<T>) -> vec3<T>"],
+    "degrees": ["fn degrees(e1: T) -> T"],
+    "determinant": ["fn determinant(e: matCxC<T>) -> T"],
+    "distance": ["fn distance(e1: T, e2: T) -> S"],
+    "dot": ["fn dot(e1: vecN<T>, e2: vecN<T>) -> T"],
+    "dot4U8Packed": ["fn dot4U8Packed(e1: u32, e2: u32) -> u32"],
+    "dot4I8Packed": ["fn dot4I8Packed(e1: u32, e2: u32) -> i32"],
+    "exp": ["fn exp(e1: T) -> T"],
+    "exp2": ["fn exp2(e: T) -> T"],
+    "extractBits": ["fn extractBits(e: T, offset: u32, count: u32) -> T"],
+    "faceForward": ["fn faceForward(e1: T, e2: T, e3: T) -> T"],
+    "firstLeadingBit": ["fn firstLeadingBit(e: T) -> T"],
+    "firstTrailingBit": ["fn firstTrailingBit(e: T) -> T"],
+    "floor": ["fn floor(e: T) -> T"],
+    "fma": ["fn fma(e1: T, e2: T, e3: T) -> T"],
+    "fract": ["fn fract(e: T) -> T"],
+    "frexp": ["fn frexp(e: T) -> __frexp_result"],
+    "insertBits": ["fn insertBits(e: T, newbits: T, offset: u32, count: u32) -> T"],
+    "inverseSqrt": ["fn inverseSqrt(e: T) -> T"],
+    "ldexp": ["fn ldexp(e1: T, e2: I) -> T"],
+    "length": ["fn length(e: T) -> S"],
+    "log": ["fn log(e: T) -> T"],
+    "log2": ["fn log2(e: T) -> T"],
+    "max": ["fn max(e1: T, e2: T) -> T"],
+    "min": ["fn min(e1: T, e2: T) -> T"],
+    "mix": [
+        "fn mix(e1: T, e2: T, e3: T) -> T",
+        "fn mix(e1: vecN<T>, e2: vecN<T>, e3: T) -> vecN<T>",
+    ],
+    "modf": ["fn modf(e: T) -> __modf_result"],
+    "normalize": ["fn normalize(e: vecN<T>) -> vecN<T>"],
+    "pow": ["fn pow(e1: T, e2: T) -> T"],
+    "quantizeToF16": ["fn quantizeToF16(e: T) -> T"],
+    "radians": ["fn radians(e1: T) -> T"],
+    "reflect": ["fn reflect(e1: T, e2: T) -> T"],
+    "refract": ["fn refract(e1: vecN<T>, e2: vecN<T>, e3: T) -> vecN<T>"],
+    "reverseBits": ["fn reverseBits(e: T) -> T"],
+    "round": ["fn round(e: T) -> T"],
+    "saturate": ["fn saturate(e: T) -> T"],
+    "sign": ["fn sign(e: T) -> T"],
+    "sin": ["fn sin(e: T) -> T"],
+    "sinh": ["fn sinh(e: T) -> T"],
+    "smoothstep": ["fn smoothstep(low: T, high: T, x: T) -> T"],
+    "sqrt": ["fn sqrt(e: T) -> T"],
+    "step": ["fn step(edge: T, x: T) -> T"],
+    "tan": ["fn tan(e: T) -> T"],
+    "tanh": ["fn tanh(e: T) -> T"],
+    "transpose": ["fn transpose(e: matRxC<T>) -> matCxR<T>"],
+    "trunc": ["fn trunc(e: T) -> T"],
+    // derivative
+    "dpdx": ["fn dpdx(e: T) -> T"],
+    "dpdxCoarse": ["fn dpdxCoarse(e: T) -> T"],
+    "dpdxFine": ["fn dpdxFine(e: T) -> T"],
+    "dpdy": ["fn dpdy(e: T) -> T"],
+    "dpdyCoarse": ["fn dpdyCoarse(e: T) -> T"],
+    "dpdyFine": ["fn dpdyFine(e: T) -> T"],
+    "fwidth": ["fn fwidth(e: T) -> T"],
+    "fwidthCoarse": ["fn fwidthCoarse(e: T) -> T"],
+    "fwidthFine": ["fn fwidthFine(e: T) -> T"],
+    // texture
+    "textureDimensions": [
+        "fn textureDimensions(t: T) -> vecN<u32>",
+        "fn textureDimensions(t: T, level: L) -> vecN<u32>",
+    ],
+    "textureGather": [
+        "fn textureGather(component: C, t: texture_2d<T>, s: sampler, coords: vec2<f32>) -> vec4<T>",
+        "fn textureGather(component: C, t: texture_2d<T>, s: sampler, coords: vec2<f32>, offset: vec2<i32>) -> vec4<T>",
+        "fn textureGather(component: C, t: texture_2d_array<T>, s: sampler, coords: vec2<f32>, array_index: A) -> vec4<T>",
+        "fn textureGather(component: C, t: texture_cube<T>, s: sampler, coords: vec3<f32>) -> vec4<T>",
+        "fn textureGather(component: C, t: texture_cube_array<T>, s: sampler, coords: vec3<f32>, array_index: A) -> vec4<T>",
+        "fn textureGather(t: texture_depth_2d, s: sampler, coords: vec2<f32>) -> vec4<f32>",
+        "fn textureGather(t: texture_depth_cube, s: sampler, coords: vec3<f32>) -> vec4<f32>",
+    ],
+    "textureGatherCompare": [
+        "fn textureGatherCompare(t: texture_depth_2d, s: sampler_comparison, coords: vec2<f32>, depth_ref: f32) -> vec4<f32>",
+        "fn textureGatherCompare(t: texture_depth_2d_array, s: sampler_comparison, coords: vec2<f32>, array_index: A, depth_ref: f32) -> vec4<f32>",
+        "fn textureGatherCompare(t: texture_depth_cube, s: sampler_comparison, coords: vec3<f32>, depth_ref: f32) -> vec4<f32>",
+    ],
+    "textureLoad": [
+        "fn textureLoad(t: texture_1d<T>, coords: C, level: L) -> vec4<T>",
+        "fn textureLoad(t: texture_2d<T>, coords: vec2<C>, level: L) -> vec4<T>",
+        "fn textureLoad(t: texture_2d_array<T>, coords: vec2<C>, array_index: A, level: L) -> vec4<T>",
+        "fn textureLoad(t: texture_3d<T>, coords: vec3<C>, level: L) -> vec4<T>",
+        "fn textureLoad(t: texture_multisampled_2d<T>, coords: vec2<C>, sample_index: S) -> vec4<T>",
+        "fn textureLoad(t: texture_depth_2d, coords: vec2<C>, level: L) -> f32",
+        "fn textureLoad(t: texture_external, coords: vec2<C>) -> vec4<f32>",
+        "fn textureLoad(t: texture_storage_2d<F, AM>, coords: vec2<C>) -> vec4<T>",
+    ],
+    "textureNumLayers": ["fn textureNumLayers(t: T) -> u32"],
+    "textureNumLevels": ["fn textureNumLevels(t: T) -> u32"],
+    "textureNumSamples": ["fn textureNumSamples(t: T) -> u32"],
+    "textureSample": [
+        "fn textureSample(t: texture_1d<f32>, s: sampler, coords: f32) -> vec4<f32>",
+        "fn textureSample(t: texture_2d<f32>, s: sampler, coords: vec2<f32>) -> vec4<f32>",
+        "fn textureSample(t: texture_2d<f32>, s: sampler, coords: vec2<f32>, offset: vec2<i32>) -> vec4<f32>",
+        "fn textureSample(t: texture_2d_array<f32>, s: sampler, coords: vec2<f32>, array_index: A) -> vec4<f32>",
+        "fn textureSample(t: texture_2d_array<f32>, s: sampler, coords: vec2<f32>, array_index: A, offset: vec2<i32>) -> vec4<f32>",
+        "fn textureSample(t: texture_3d<f32>, s: sampler, coords: vec3<f32>) -> vec4<f32>",
+        "fn textureSample(t: texture_cube<f32>, s: sampler, coords: vec3<f32>) -> vec4<f32>",
+        "fn textureSample(t: texture_cube_array<f32>, s: sampler, coords: vec3<f32>, array_index: A) -> vec4<f32>",
+        "fn textureSample(t: texture_depth_2d, s: sampler, coords: vec2<f32>) -> f32",
+        "fn textureSample(t: texture_depth_2d_array, s: sampler, coords: vec2<f32>, array_index: A) -> f32",
+        "fn textureSample(t: texture_depth_cube, s: sampler, coords: vec3<f32>) -> f32",
+    ],
+    "textureSampleBias": [
+        "fn textureSampleBias(t: texture_2d<f32>, s: sampler, coords: vec2<f32>, bias: f32) -> vec4<f32>",
+        "fn textureSampleBias(t: texture_2d<f32>, s: sampler, coords: vec2<f32>, bias: f32, offset: vec2<i32>) -> vec4<f32>",
+        "fn textureSampleBias(t: texture_2d_array<f32>, s: sampler, coords: vec2<f32>, array_index: A, bias: f32) -> vec4<f32>",
+        "fn textureSampleBias(t: texture_3d<f32>, s: sampler, coords: vec3<f32>, bias: f32) -> vec4<f32>",
+        "fn textureSampleBias(t: texture_cube<f32>, s: sampler, coords: vec3<f32>, bias: f32) -> vec4<f32>",
+    ],
+    "textureSampleCompare": [
+        "fn textureSampleCompare(t: texture_depth_2d, s: sampler_comparison, coords: vec2<f32>, depth_ref: f32) -> f32",
+        "fn textureSampleCompare(t: texture_depth_2d, s: sampler_comparison, coords: vec2<f32>, depth_ref: f32, offset: vec2<i32>) -> f32",
+        "fn textureSampleCompare(t: texture_depth_2d_array, s: sampler_comparison, coords: vec2<f32>, array_index: A, depth_ref: f32) -> f32",
+        "fn textureSampleCompare(t: texture_depth_cube, s: sampler_comparison, coords: vec3<f32>, depth_ref: f32) -> f32",
+    ],
+    "textureSampleCompareLevel": [
+        "fn textureSampleCompareLevel(t: texture_depth_2d, s: sampler_comparison, coords: vec2<f32>, depth_ref: f32) -> f32",
+        "fn textureSampleCompareLevel(t: texture_depth_2d_array, s: sampler_comparison, coords: vec2<f32>, array_index: A, depth_ref: f32) -> f32",
+        "fn textureSampleCompareLevel(t: texture_depth_cube, s: sampler_comparison, coords: vec3<f32>, depth_ref: f32) -> f32",
+    ],
+    "textureSampleGrad": [
+        "fn textureSampleGrad(t: texture_2d<f32>, s: sampler, coords: vec2<f32>, ddx: vec2<f32>, ddy: vec2<f32>) -> vec4<f32>",
+        "fn textureSampleGrad(t: texture_2d_array<f32>, s: sampler, coords: vec2<f32>, array_index: A, ddx: vec2<f32>, ddy: vec2<f32>) -> vec4<f32>",
+        "fn textureSampleGrad(t: texture_3d<f32>, s: sampler, coords: vec3<f32>, ddx: vec3<f32>, ddy: vec3<f32>) -> vec4<f32>",
+        "fn textureSampleGrad(t: texture_cube<f32>, s: sampler, coords: vec3<f32>, ddx: vec3<f32>, ddy: vec3<f32>) -> vec4<f32>",
+    ],
+    "textureSampleLevel": [
+        "fn textureSampleLevel(t: texture_2d<f32>, s: sampler, coords: vec2<f32>, level: f32) -> vec4<f32>",
+        "fn textureSampleLevel(t: texture_2d<f32>, s: sampler, coords: vec2<f32>, level: f32, offset: vec2<i32>) -> vec4<f32>",
+        "fn textureSampleLevel(t: texture_2d_array<f32>, s: sampler, coords: vec2<f32>, array_index: A, level: f32) -> vec4<f32>",
+        "fn textureSampleLevel(t: texture_3d<f32>, s: sampler, coords: vec3<f32>, level: f32) -> vec4<f32>",
+        "fn textureSampleLevel(t: texture_cube<f32>, s: sampler, coords: vec3<f32>, level: f32) -> vec4<f32>",
+        "fn textureSampleLevel(t: texture_depth_2d, s: sampler, coords: vec2<f32>, level: L) -> f32",
+    ],
+    "textureSampleBaseClampToEdge": [
+        "fn textureSampleBaseClampToEdge(t: texture_external, s: sampler, coords: vec2<f32>) -> vec4<f32>",
+        "fn textureSampleBaseClampToEdge(t: texture_2d<f32>, s: sampler, coords: vec2<f32>) -> vec4<f32>",
+    ],
+    "textureStore": [
+        "fn textureStore(t: texture_storage_1d<F, AM>, coords: C, value: vec4<T>)",
+        "fn textureStore(t: texture_storage_2d<F, AM>, coords: vec2<C>, value: vec4<T>)",
+        "fn textureStore(t: texture_storage_2d_array<F, AM>, coords: vec2<C>, array_index: A, value: vec4<T>)",
+        "fn textureStore(t: texture_storage_3d<F, AM>, coords: vec3<C>, value: vec4<T>)",
+    ],
+    // atomic
+    "atomicLoad": ["fn atomicLoad(atomic_ptr: ptr<AS, atomic<T>, read_write>) -> T"],
+    "atomicStore": ["fn atomicStore(atomic_ptr: ptr<AS, atomic<T>, read_write>, v: T)"],
+    "atomicAdd": ["fn atomicAdd(atomic_ptr: ptr<AS, atomic<T>, read_write>, v: T) -> T"],
+    "atomicSub": ["fn atomicSub(atomic_ptr: ptr<AS, atomic<T>, read_write>, v: T) -> T"],
+    "atomicMax": ["fn atomicMax(atomic_ptr: ptr<AS, atomic<T>, read_write>, v: T) -> T"],
+    "atomicMin": ["fn atomicMin(atomic_ptr: ptr<AS, atomic<T>, read_write>, v: T) -> T"],
+    "atomicAnd": ["fn atomicAnd(atomic_ptr: ptr<AS, atomic<T>, read_write>, v: T) -> T"],
+    "atomicOr": ["fn atomicOr(atomic_ptr: ptr<AS, atomic<T>, read_write>, v: T) -> T"],
+    "atomicXor": ["fn atomicXor(atomic_ptr: ptr<AS, atomic<T>, read_write>, v: T) -> T"],
+    "atomicExchange": ["fn atomicExchange(atomic_ptr: ptr<AS, atomic<T>, read_write>, v: T) -> T"],
+    "atomicCompareExchangeWeak": [
+        "fn atomicCompareExchangeWeak(atomic_ptr: ptr<AS, atomic<T>, read_write>, cmp: T, v: T) -> __atomic_compare_exchange_result<T>",
+    ],
+    // packing
+    "pack4x8snorm": ["fn pack4x8snorm(e: vec4<f32>) -> u32"],
+    "pack4x8unorm": ["fn pack4x8unorm(e: vec4<f32>) -> u32"],
+    "pack4xI8": ["fn pack4xI8(e: vec4<i32>) -> u32"],
+    "pack4xU8": ["fn pack4xU8(e: vec4<u32>) -> u32"],
+    "pack2x16snorm": ["fn pack2x16snorm(e: vec2<f32>) -> u32"],
+    "pack2x16unorm": ["fn pack2x16unorm(e: vec2<f32>) -> u32"],
+    "pack2x16float": ["fn pack2x16float(e: vec2<f32>) -> u32"],
+    "unpack4x8snorm": ["fn unpack4x8snorm(e: u32) -> vec4<f32>"],
+    "unpack4x8unorm": ["fn unpack4x8unorm(e: u32) -> vec4<f32>"],
+    "unpack4xI8": ["fn unpack4xI8(e: u32) -> vec4<i32>"],
+    "unpack4xU8": ["fn unpack4xU8(e: u32) -> vec4<u32>"],
+    "unpack2x16snorm": ["fn unpack2x16snorm(e: u32) -> vec2<f32>"],
+    "unpack2x16unorm": ["fn unpack2x16unorm(e: u32) -> vec2<f32>"],
+    "unpack2x16float": ["fn unpack2x16float(e: u32) -> vec2<f32>"],
+    // synchronization
+    "storageBarrier": ["fn storageBarrier()"],
+    "textureBarrier": ["fn textureBarrier()"],
+    "workgroupBarrier": ["fn workgroupBarrier()"],
+    "workgroupUniformLoad": ["fn workgroupUniformLoad(p: ptr<workgroup, T>) -> T"],
+};
+
+pub fn function(name: &str) -> Option<&'static BuiltinFunction> {
+    FUNCTIONS.iter().find(|function| function.name == name)
+}
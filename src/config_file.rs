@@ -0,0 +1,415 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    ops::{self, ControlFlow},
+    path::{Path, PathBuf},
+};
+
+use lsp_types::{
+    notification::PublishDiagnostics, Diagnostic, DiagnosticSeverity, MessageType,
+    PublishDiagnosticsParams, Url,
+};
+use serde::de::IgnoredAny;
+use serde_json::{value::RawValue, Map, Value};
+use toml::Spanned;
+
+use crate::{
+    server::{NotifyResult, WgslServerState},
+    settings::Settings,
+};
+
+/// Names of the config file at the root of a workspace folder, in order of precedence.
+pub const CONFIG_FILE_NAMES: [&str; 2] = ["wgsl-lsp.toml", ".wgsl-lsp.json"];
+
+/// Settings read from the config file of a workspace folder.
+#[derive(Debug)]
+pub struct ConfigFile {
+    pub uri: Url,
+    /// The valid settings in the file, with paths made absolute.
+    pub settings: Value,
+    /// Errors in the file.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl WgslServerState {
+    /// Read the config file at the root of a project's workspace folder, if it has one.
+    ///
+    /// This doesn't apply its settings or publish its errors, see [Self::reload_settings] and
+    /// [Self::publish_config_diagnostics].
+    pub fn load_config_file(&mut self, project: usize) {
+        let Some(folder) = self.projects[project].root.clone() else {
+            return;
+        };
+        self.projects[project].config_file = None;
+        let Some(path) = CONFIG_FILE_NAMES
+            .iter()
+            .map(|name| folder.join(name))
+            .find(|path| path.is_file())
+        else {
+            return;
+        };
+        let Ok(uri) = Url::from_file_path(&path) else {
+            return;
+        };
+        let source = fs::read_to_string(&path).unwrap_or_default();
+        let (settings, errors) = read_config_file(&folder, &path, &source);
+        let index = self.line_index(&source);
+        let diagnostics = errors
+            .into_iter()
+            .map(|(range, message)| Diagnostic {
+                range: index.range(range.start, range.end),
+                severity: Some(DiagnosticSeverity::ERROR),
+                message,
+                ..Default::default()
+            })
+            .collect();
+        self.projects[project].config_file = Some(ConfigFile {
+            uri,
+            settings,
+            diagnostics,
+        });
+    }
+
+    /// Re-read the config file of a project after it changed on disk, then publish its errors and
+    /// apply its settings.
+    pub fn reload_config_file(&mut self, project: usize) -> NotifyResult {
+        let old_uri = self.projects[project]
+            .config_file
+            .as_ref()
+            .map(|c| c.uri.clone());
+        self.load_config_file(project);
+        if let ControlFlow::Break(result) = self.publish_config_diagnostics(project, old_uri) {
+            return ControlFlow::Break(result);
+        }
+        self.reload_settings()
+    }
+
+    /// The project a file is the config file of, if it's one.
+    pub fn config_file_project(&self, path: &Path) -> Option<usize> {
+        let name = path.file_name()?.to_str()?;
+        if !CONFIG_FILE_NAMES.contains(&name) {
+            return None;
+        }
+        self.folder_project_index(path.parent()?)
+    }
+
+    /// Publish the errors in the config file of a project, clearing the errors of `old_uri` if it
+    /// was the project's config file before.
+    pub fn publish_config_diagnostics(&self, project: usize, old_uri: Option<Url>) -> NotifyResult {
+        let config_file = self.projects[project].config_file.as_ref();
+        if let Some(old_uri) = old_uri.filter(|old| Some(old) != config_file.map(|c| &c.uri)) {
+            if let ControlFlow::Break(result) =
+                self.notify::<PublishDiagnostics>(PublishDiagnosticsParams {
+                    uri: old_uri,
+                    diagnostics: Vec::new(),
+                    version: None,
+                })
+            {
+                return ControlFlow::Break(result);
+            }
+        }
+        let Some(config_file) = config_file else {
+            return ControlFlow::Continue(());
+        };
+        self.notify::<PublishDiagnostics>(PublishDiagnosticsParams {
+            uri: config_file.uri.clone(),
+            diagnostics: config_file.diagnostics.clone(),
+            version: None,
+        })
+    }
+
+    /// Apply the client's settings to every project, with the project's config file on top.
+    pub fn reload_settings(&mut self) -> NotifyResult {
+        for project in 0..self.projects.len() {
+            let mut settings = self.client_settings.clone();
+            if !settings.is_object() {
+                settings = Value::Object(Map::new());
+            }
+            if let Some(config_file) = &self.projects[project].config_file {
+                merge_settings(&mut settings, &config_file.settings);
+            }
+            let result = match Settings::from_value(&settings) {
+                Ok(settings) => self.apply_settings(project, settings),
+                Err(e) => self.log(MessageType::ERROR, &e),
+            };
+            if let ControlFlow::Break(result) = result {
+                return ControlFlow::Break(result);
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Whether a file is excluded from loading by the `exclude` setting of its project, because it
+    /// or a directory containing it matches one of the globs.
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        let project = &self.projects[self.path_project_index(path)];
+        if project.settings.exclude.is_empty() {
+            return false;
+        }
+        // globs from the client are relative to the workspace folder, and those from config files
+        // were made absolute
+        let relative_path = project
+            .root
+            .as_ref()
+            .and_then(|root| path.strip_prefix(root).ok());
+        relative_path
+            .into_iter()
+            .chain([path])
+            .flat_map(|path| path.ancestors().filter(|path| !path.as_os_str().is_empty()))
+            .map(|path| path.to_string_lossy().replace('\\', "/"))
+            .any(|path| project.settings.exclude.is_match(&path))
+    }
+}
+
+/// The settings in a config file, with the byte range to report their errors at.
+type ConfigValues = BTreeMap<String, (Value, ops::Range<usize>)>;
+
+/// An error at a byte range of a config file.
+type ConfigError = (ops::Range<usize>, String);
+
+/// Read the settings in a config file, leaving out any that are invalid.
+///
+/// Returns the settings and the errors in the file as byte ranges and messages.
+fn read_config_file(folder: &Path, path: &Path, source: &str) -> (Value, Vec<ConfigError>) {
+    let values = if path.extension().is_some_and(|ext| ext == "json") {
+        read_json(source)
+    } else {
+        read_toml(source)
+    };
+    let values = match values {
+        Ok(values) => values,
+        Err(error) => return (Value::Null, vec![error]),
+    };
+
+    // check every setting by itself so the errors are reported on the right line
+    let mut settings = Map::new();
+    let mut errors = Vec::new();
+    for (key, (value, range)) in values {
+        let setting = Value::Object(Map::from_iter([(key.clone(), value.clone())]));
+        match Settings::from_value(&setting) {
+            Ok(_) => {
+                settings.insert(key, value);
+            }
+            Err(e) => errors.push((range, format!("{e} (in `{key}`)"))),
+        }
+    }
+
+    if let Some(Value::Array(paths)) = settings.get_mut("includePaths") {
+        for path in paths {
+            if let Value::String(path) = path {
+                *path = absolute_path(folder, path);
+            }
+        }
+    }
+    if let Some(Value::Array(globs)) = settings.get_mut("exclude") {
+        for glob in globs {
+            if let Value::String(glob) = glob {
+                *glob = absolute_glob(folder, glob);
+            }
+        }
+    }
+    (Value::Object(settings), errors)
+}
+
+/// The settings in a JSON config file, with the range of each value.
+fn read_json(source: &str) -> Result<ConfigValues, ConfigError> {
+    let settings = serde_json::from_str::<BTreeMap<String, &RawValue>>(source).map_err(|e| {
+        let offset = json_error_offset(source, &e);
+        (offset..offset, e.to_string())
+    })?;
+    Ok(settings
+        .into_iter()
+        .map(|(key, raw)| {
+            // raw values borrow the text they were parsed from
+            let start = raw.get().as_ptr() as usize - source.as_ptr() as usize;
+            let range = start..start + raw.get().len();
+            let value = serde_json::from_str(raw.get()).unwrap_or_default();
+            (key, (value, range))
+        })
+        .collect())
+}
+
+/// The settings in a TOML config file read as JSON, with the range of each key, since the value
+/// of a table can be spread across the file.
+fn read_toml(source: &str) -> Result<ConfigValues, ConfigError> {
+    let error = |e: toml::de::Error| (e.span().unwrap_or(0..0), e.message().to_owned());
+    let settings = toml::from_str::<toml::Table>(source).map_err(error)?;
+    let keys = toml::from_str::<BTreeMap<Spanned<String>, IgnoredAny>>(source).map_err(error)?;
+    Ok(keys
+        .into_keys()
+        .filter_map(|key| {
+            let value = serde_json::to_value(settings.get(key.get_ref())?).ok()?;
+            Some((key.get_ref().clone(), (value, key.span())))
+        })
+        .collect())
+}
+
+/// A path relative to a workspace folder made absolute, with `/` separators.
+fn absolute_path(folder: &Path, path: &str) -> String {
+    let path = if Path::new(path).is_absolute() {
+        PathBuf::from(path)
+    } else {
+        folder.join(path)
+    };
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// A glob relative to a workspace folder made absolute, escaping the folder so that it only
+/// matches itself.
+fn absolute_glob(folder: &Path, glob: &str) -> String {
+    if Path::new(glob).is_absolute() {
+        return glob.to_owned();
+    }
+    let folder = folder.to_string_lossy().replace('\\', "/");
+    format!("{}/{}", globset::escape(folder.trim_end_matches('/')), glob)
+}
+
+/// Put the settings of a config file on top of others. Lists of paths are combined, tables like
+/// the shader defs are merged, and everything else is replaced.
+fn merge_settings(settings: &mut Value, overrides: &Value) {
+    let (Some(settings), Some(overrides)) = (settings.as_object_mut(), overrides.as_object())
+    else {
+        return;
+    };
+    for (key, value) in overrides {
+        match (settings.get_mut(key), value) {
+            (Some(Value::Array(existing)), Value::Array(values))
+                if key == "includePaths" || key == "exclude" =>
+            {
+                existing.extend(values.iter().cloned());
+            }
+            (Some(Value::Object(existing)), Value::Object(values)) => {
+                existing.extend(values.clone());
+            }
+            _ => {
+                settings.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// The byte offset of a JSON syntax error.
+fn json_error_offset(source: &str, e: &serde_json::Error) -> usize {
+    let line_start: usize = source
+        .split_inclusive('\n')
+        .take(e.line().saturating_sub(1))
+        .map(str::len)
+        .sum();
+    (line_start + e.column().saturating_sub(1)).min(source.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn read(name: &str, source: &str) -> (Value, Vec<ConfigError>) {
+        read_config_file(
+            Path::new("/project"),
+            &Path::new("/project").join(name),
+            source,
+        )
+    }
+
+    #[test]
+    fn toml_settings() {
+        let source = r#"
+logLevel = 'info'
+includePaths = [
+    "../shared",
+    'sub\dir',
+]
+exclude = ["target/**", "gen\u0065rated"]
+shaderDefs = { MAX_LIGHTS = 4, SKINNED = true }
+
+[shaderDefPermutations.skinned]
+SKINNED = true
+"#;
+        let (settings, errors) = read("wgsl-lsp.toml", source);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(
+            settings,
+            json!({
+                "logLevel": "info",
+                "includePaths": ["/project/../shared", "/project/sub/dir"],
+                "exclude": ["/project/target/**", "/project/generated"],
+                "shaderDefs": { "MAX_LIGHTS": 4, "SKINNED": true },
+                "shaderDefPermutations": { "skinned": { "SKINNED": true } },
+            })
+        );
+    }
+
+    #[test]
+    fn json_settings() {
+        let source = r#"{ "logLevel": "warning", "shaderDefs": { "A": "3u" } }"#;
+        let (settings, errors) = read(".wgsl-lsp.json", source);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(
+            settings,
+            json!({ "logLevel": "warning", "shaderDefs": { "A": "3u" } })
+        );
+    }
+
+    #[test]
+    fn invalid_settings_are_reported_where_they_are() {
+        // the key also appears earlier in a string, which mustn't be mistaken for it
+        let source = r#"{ "exclude": ["logLevel"], "logLevel": "loud" }"#;
+        let (settings, errors) = read(".wgsl-lsp.json", source);
+        assert_eq!(settings, json!({ "exclude": ["/project/logLevel"] }));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(&source[errors[0].0.clone()], r#""loud""#);
+
+        let source = "exclude = [\"logLevel\"]\n\n[logLevel]\nlevel = \"loud\"\n";
+        let (_, errors) = read("wgsl-lsp.toml", source);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0.start, source.find("[logLevel]").unwrap() + 1);
+    }
+
+    #[test]
+    fn invalid_globs_are_reported() {
+        let source = r#"exclude = ["{a,b"]"#;
+        let (settings, errors) = read("wgsl-lsp.toml", source);
+        assert_eq!(settings, json!({}));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(&source[errors[0].0.clone()], "exclude");
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let source = "logLevel = \"info\"\nexclude = [\n";
+        let (settings, errors) = read("wgsl-lsp.toml", source);
+        assert_eq!(settings, Value::Null);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].0.start >= source.find('[').unwrap());
+
+        let source = "{ \"logLevel\": }";
+        let (settings, errors) = read(".wgsl-lsp.json", source);
+        assert_eq!(settings, Value::Null);
+        assert_eq!(errors[0].0, 14..14);
+
+        let (_, errors) = read(".wgsl-lsp.json", "[]");
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn config_files_merge_on_top_of_client_settings() {
+        let mut settings = json!({
+            "exclude": ["a"],
+            "shaderDefs": { "A": true, "B": true },
+            "logLevel": "info",
+        });
+        merge_settings(
+            &mut settings,
+            &json!({ "exclude": ["b"], "shaderDefs": { "B": false }, "logLevel": "error" }),
+        );
+        assert_eq!(
+            settings,
+            json!({
+                "exclude": ["a", "b"],
+                "shaderDefs": { "A": true, "B": false },
+                "logLevel": "error",
+            })
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use lsp_types::Url;
+
+/// Which documents import which modules, for finding every document affected by a change.
+#[derive(Debug, Default)]
+pub struct DependencyGraph {
+    /// The module name each document is registered under.
+    modules: HashMap<Url, String>,
+    /// The modules each document imports.
+    imports: HashMap<Url, Vec<String>>,
+    /// The documents importing each module.
+    importers: HashMap<String, HashSet<Url>>,
+}
+
+impl DependencyGraph {
+    /// Record the module a document declares and the modules it imports, replacing what was
+    /// recorded for it before.
+    pub fn update(&mut self, uri: &Url, module_name: String, dependencies: Vec<String>) {
+        self.remove(uri);
+        for dependency in &dependencies {
+            self.importers
+                .entry(dependency.clone())
+                .or_default()
+                .insert(uri.clone());
+        }
+        self.modules.insert(uri.clone(), module_name);
+        self.imports.insert(uri.clone(), dependencies);
+    }
+
+    pub fn remove(&mut self, uri: &Url) {
+        self.modules.remove(uri);
+        for dependency in self.imports.remove(uri).unwrap_or_default() {
+            if let Some(importers) = self.importers.get_mut(&dependency) {
+                importers.remove(uri);
+                if importers.is_empty() {
+                    self.importers.remove(&dependency);
+                }
+            }
+        }
+    }
+
+    pub fn module(&self, uri: &Url) -> Option<&str> {
+        self.modules.get(uri).map(String::as_str)
+    }
+
+    /// Modules the document imports directly.
+    pub fn imports(&self, uri: &Url) -> &[String] {
+        self.imports.get(uri).map_or(&[], Vec::as_slice)
+    }
+
+    /// Documents that import the module directly.
+    pub fn importers(&self, module_name: &str) -> impl Iterator<Item = &Url> {
+        self.importers.get(module_name).into_iter().flatten()
+    }
+
+    /// Documents that import any of the modules directly or through other modules, ordered so
+    /// that modules come before the modules importing them.
+    pub fn transitive_importers<'a>(
+        &self,
+        module_names: impl IntoIterator<Item = &'a str>,
+    ) -> Vec<Url> {
+        let mut seen: HashSet<&Url> = HashSet::new();
+        let mut found = Vec::new();
+        let mut queue: VecDeque<&str> = module_names.into_iter().collect();
+        while let Some(module_name) = queue.pop_front() {
+            for importer in self.importers(module_name) {
+                if seen.insert(importer) {
+                    found.push(importer);
+                    if let Some(name) = self.module(importer) {
+                        queue.push_back(name);
+                    }
+                }
+            }
+        }
+
+        // a document can import both a module and another module importing it, so it has to
+        // wait for every import that's also being visited
+        fn visit<'a>(
+            graph: &'a DependencyGraph,
+            uri: &'a Url,
+            found: &HashMap<&str, &'a Url>,
+            visited: &mut HashSet<&'a Url>,
+            order: &mut Vec<Url>,
+        ) {
+            if !visited.insert(uri) {
+                return;
+            }
+            for import in graph.imports(uri) {
+                if let Some(&dependency) = found.get(import.as_str()) {
+                    visit(graph, dependency, found, visited, order);
+                }
+            }
+            order.push(uri.clone());
+        }
+        let by_module: HashMap<&str, &Url> = found
+            .iter()
+            .filter_map(|&uri| Some((self.module(uri)?, uri)))
+            .collect();
+        let mut visited = HashSet::new();
+        let mut order = Vec::new();
+        for uri in found {
+            visit(self, uri, &by_module, &mut visited, &mut order);
+        }
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri(name: &str) -> Url {
+        Url::parse(&format!("file:///{name}.wgsl")).unwrap()
+    }
+
+    fn graph(documents: &[(&str, &[&str])]) -> DependencyGraph {
+        let mut graph = DependencyGraph::default();
+        for (name, imports) in documents {
+            graph.update(
+                &uri(name),
+                name.to_string(),
+                imports.iter().map(|import| import.to_string()).collect(),
+            );
+        }
+        graph
+    }
+
+    #[test]
+    fn transitive_importers_are_ordered_by_distance() {
+        let graph = graph(&[
+            ("common", &[]),
+            ("lighting", &["common"]),
+            ("pbr", &["lighting", "common"]),
+            ("main", &["pbr"]),
+            ("unrelated", &[]),
+        ]);
+        assert_eq!(
+            graph.transitive_importers(["common"]),
+            [uri("lighting"), uri("pbr"), uri("main")]
+        );
+        assert_eq!(graph.transitive_importers(["main"]), Vec::<Url>::new());
+    }
+
+    #[test]
+    fn import_cycles_terminate() {
+        let graph = graph(&[("a", &["b"]), ("b", &["a"])]);
+        let mut importers = graph.transitive_importers(["a"]);
+        importers.sort();
+        assert_eq!(importers, [uri("a"), uri("b")]);
+    }
+
+    #[test]
+    fn updating_replaces_imports() {
+        let mut graph = graph(&[("common", &[]), ("main", &["common"])]);
+        graph.update(&uri("main"), "main".to_owned(), vec!["other".to_owned()]);
+        assert_eq!(graph.imports(&uri("main")), ["other"]);
+        assert_eq!(graph.importers("common").count(), 0);
+        assert_eq!(graph.importers("other").collect::<Vec<_>>(), [&uri("main")]);
+
+        graph.remove(&uri("main"));
+        assert_eq!(graph.module(&uri("main")), None);
+        assert_eq!(graph.importers("other").count(), 0);
+    }
+}
@@ -4,33 +4,66 @@ use async_lsp::{ErrorCode, ResponseError};
 use lsp_types::{MessageType, Url};
 use ropey::Rope;
 
-use crate::server::WgslServerState;
+use crate::{
+    server::{NotifyResult, WgslServerState},
+    syntax_tree::SyntaxTree,
+};
 
 #[derive(Debug)]
 pub enum OpenDocument {
     /// Client-owned documents expect to be edited, so they use a [Rope].
+    /// The client's version of the document and its syntax tree are kept alongside it.
     ///
     /// It's unclear if this is helpful because the [Rope] will be written to a
     /// string whenever it needs to be validated anyway.
-    ClientOwned(Rope),
-    /// Server-owned documents are read-only and are stored as strings.
-    ServerOwned(String),
+    ClientOwned(Rope, i32, SyntaxTree),
+    /// Server-owned documents are read-only and are stored as strings, along with their syntax
+    /// tree.
+    ServerOwned(String, SyntaxTree),
 }
 
 impl OpenDocument {
+    pub fn client_owned(text: &str, version: i32) -> Self {
+        OpenDocument::ClientOwned(Rope::from_str(text), version, SyntaxTree::parse(text))
+    }
+
+    pub fn server_owned(text: String) -> Self {
+        let tree = SyntaxTree::parse(&text);
+        OpenDocument::ServerOwned(text, tree)
+    }
+
     pub fn source(&self) -> String {
         let mut vec = Vec::new();
         match self {
-            OpenDocument::ClientOwned(source) => {
+            OpenDocument::ClientOwned(source, ..) => {
                 source.write_to(&mut vec).unwrap();
                 String::from_utf8(vec).unwrap()
             }
-            OpenDocument::ServerOwned(source) => source.clone(),
+            OpenDocument::ServerOwned(source, _) => source.clone(),
+        }
+    }
+
+    /// The syntax tree of the current source, which is kept up to date with every change.
+    pub fn syntax_tree(&self) -> &SyntaxTree {
+        match self {
+            OpenDocument::ClientOwned(.., tree) | OpenDocument::ServerOwned(_, tree) => tree,
+        }
+    }
+
+    /// The version the client last sent, or [None] for server-owned documents.
+    pub fn version(&self) -> Option<i32> {
+        match self {
+            OpenDocument::ClientOwned(_, version, _) => Some(*version),
+            OpenDocument::ServerOwned(..) => None,
         }
     }
 }
 
 impl WgslServerState {
+    pub fn document_version(&self, uri: &Url) -> Option<i32> {
+        self.open_documents.get(uri).and_then(OpenDocument::version)
+    }
+
     /// Opens the document as server-owned if it's not already open. Does not preprocess.
     ///
     /// The document is guaranteed to exist in `open_documents` after a [Result::Ok].
@@ -45,7 +78,7 @@ impl WgslServerState {
             .is_ok()
         {
             self.open_documents
-                .insert(uri.clone(), OpenDocument::ServerOwned(text));
+                .insert(uri.clone(), OpenDocument::server_owned(text));
             Ok(())
         } else {
             Err(ResponseError::new(
@@ -56,25 +89,32 @@ impl WgslServerState {
     }
 
     /// Opens the document as server-owned and preprocesses it.
-    pub fn server_open(&mut self, uri: Url) {
+    pub fn server_open(&mut self, uri: Url) -> NotifyResult {
         let mut text = String::new();
         match File::open(uri.to_file_path().unwrap())
             .and_then(|mut file| file.read_to_string(&mut text))
         {
             Ok(_) => {
                 self.open_documents
-                    .insert(uri.clone(), OpenDocument::ServerOwned(text));
+                    .insert(uri.clone(), OpenDocument::server_owned(text));
                 self.preprocess(&uri);
-                self.log(MessageType::INFO, &format!("Opened document: {}", uri));
-            }
-            Err(e) => {
-                self.log(
-                    MessageType::ERROR,
-                    &format!("Failed to open document: {}", e),
-                );
+                self.update_workspace_symbols(&uri);
+                self.log(MessageType::INFO, &format!("Opened document: {}", uri))
             }
+            Err(e) => self.log(
+                MessageType::ERROR,
+                &format!("Failed to open document: {}", e),
+            ),
         }
     }
+
+    /// Unloads a server-owned document and the modules it registered.
+    pub fn server_close(&mut self, uri: &Url) -> NotifyResult {
+        self.unregister_document(uri);
+        self.open_documents.remove(uri);
+        self.update_workspace_symbols(uri);
+        self.log(MessageType::INFO, &format!("Closed document: {}", uri))
+    }
 }
 
 /// Normalize file paths so that drive letter casing and colon encoding is consistent.
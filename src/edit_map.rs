@@ -0,0 +1,146 @@
+use std::ops::Range;
+
+/// Maps offsets in an old version of a document to its current version and back, by replaying
+/// the edits made since.
+///
+/// This lets a module built from an old version of a document stand in for the current one while
+/// it doesn't build. Text that was edited since has no equivalent and doesn't map.
+#[derive(Debug, Default)]
+pub struct EditMap {
+    /// The range each edit replaced, in the document as it was right before the edit, and the
+    /// length of the text it was replaced with.
+    edits: Vec<(Range<usize>, usize)>,
+}
+
+impl EditMap {
+    /// Record that `range` of the document was replaced with `len` bytes of text.
+    pub fn push(&mut self, range: Range<usize>, len: usize) {
+        self.edits.push((range, len));
+    }
+
+    /// Record that the whole document was replaced, keeping the text that stayed the same at the
+    /// start and end.
+    pub fn replace_all(&mut self, old: &str, new: &str) {
+        let mut prefix = common_len(old.bytes(), new.bytes());
+        // characters that only start the same were still edited
+        while !old.is_char_boundary(prefix) || !new.is_char_boundary(prefix) {
+            prefix -= 1;
+        }
+        let mut suffix = common_len(
+            old.as_bytes()[prefix..].iter().rev().copied(),
+            new.as_bytes()[prefix..].iter().rev().copied(),
+        );
+        while !old.is_char_boundary(old.len() - suffix) || !new.is_char_boundary(new.len() - suffix)
+        {
+            suffix -= 1;
+        }
+        self.push(prefix..old.len() - suffix, new.len() - suffix - prefix);
+    }
+
+    /// Map a range of the old document to the current one.
+    ///
+    /// Returns [None] if an edit was made inside the range. Text inserted right before or after
+    /// it doesn't count.
+    pub fn map_range(&self, range: Range<usize>) -> Option<Range<usize>> {
+        let mut range = range;
+        for (edit, len) in &self.edits {
+            if edit.end <= range.start {
+                range = range.start - edit.len() + len..range.end - edit.len() + len;
+            } else if edit.start < range.end {
+                return None;
+            }
+        }
+        Some(range)
+    }
+
+    /// Map an offset in the current document to the old one.
+    ///
+    /// Returns [None] if the offset is in text that was inserted since.
+    pub fn unmap(&self, offset: usize) -> Option<usize> {
+        let mut offset = offset;
+        for (edit, len) in self.edits.iter().rev() {
+            if offset <= edit.start {
+                continue;
+            }
+            if offset < edit.start + len {
+                return None;
+            }
+            offset = offset - len + edit.len();
+        }
+        Some(offset)
+    }
+}
+
+fn common_len(a: impl Iterator<Item = u8>, b: impl Iterator<Item = u8>) -> usize {
+    a.zip(b).take_while(|(a, b)| a == b).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edits(edits: &[(Range<usize>, usize)]) -> EditMap {
+        let mut map = EditMap::default();
+        for (range, len) in edits {
+            map.push(range.clone(), *len);
+        }
+        map
+    }
+
+    #[test]
+    fn ranges_after_edits_move() {
+        // "let a = 1;" -> "let abc = 1;" -> "// x\nlet abc = 1;"
+        let map = edits(&[(5..5, 2), (0..0, 5)]);
+        assert_eq!(map.map_range(0..3), Some(5..8));
+        assert_eq!(map.map_range(8..9), Some(15..16));
+        assert_eq!(map.map_range(4..6), None);
+        // text inserted right after a range doesn't touch it
+        assert_eq!(map.map_range(2..5), Some(7..10));
+    }
+
+    #[test]
+    fn ranges_after_deletions_move_back() {
+        // "fn main() {}" -> "fn m() {}"
+        let map = edits(&[(4..7, 0)]);
+        assert_eq!(map.map_range(0..2), Some(0..2));
+        assert_eq!(map.map_range(7..9), Some(4..6));
+        assert_eq!(map.map_range(3..7), None);
+    }
+
+    #[test]
+    fn unmap_undoes_map_range() {
+        let map = edits(&[(5..5, 2), (0..0, 5), (10..12, 3)]);
+        for offset in [0, 3, 8, 9, 14] {
+            let Some(current) = map.map_range(offset..offset).map(|r| r.start) else {
+                continue;
+            };
+            assert_eq!(map.unmap(current), Some(offset));
+        }
+        // inside the inserted text
+        assert_eq!(map.unmap(2), None);
+        assert_eq!(map.unmap(11), None);
+    }
+
+    #[test]
+    fn replace_all_keeps_the_same_start_and_end() {
+        let mut map = EditMap::default();
+        map.replace_all("let a = 1;", "let b = 12;");
+        assert_eq!(map.edits, [(4..9, 6)]);
+        assert_eq!(map.map_range(0..3), Some(0..3));
+        assert_eq!(map.map_range(9..10), Some(10..11));
+    }
+
+    #[test]
+    fn replace_all_splits_on_characters() {
+        // "é" and "è" share their first byte, and "ä" and "ü" their last
+        let mut map = EditMap::default();
+        map.replace_all("a é b", "a è b");
+        assert_eq!(map.edits, [(2..4, 2)]);
+        let mut map = EditMap::default();
+        map.replace_all("xä", "yü");
+        assert_eq!(map.edits, [(0..3, 3)]);
+        let mut map = EditMap::default();
+        map.replace_all("ä", "äü");
+        assert_eq!(map.edits, [(2..2, 2)]);
+    }
+}
@@ -0,0 +1,72 @@
+/// Score how well `pattern` fuzzily matches `candidate`, or [None] if it doesn't match.
+///
+/// Every character of the pattern has to appear in the candidate in order, ignoring case.
+/// Matches score higher when they're consecutive, start a word, or have the same case,
+/// and shorter candidates score higher than longer ones with the same matches.
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<i32> {
+    let candidate: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous_match: Option<usize> = None;
+
+    for p in pattern.chars() {
+        let index = (next..candidate.len()).find(|&i| eq_ignore_case(candidate[i], p))?;
+        let c = candidate[index];
+        score += 1;
+        if c == p {
+            score += 1;
+        }
+        if previous_match.is_some_and(|previous| previous + 1 == index) {
+            score += 4;
+        }
+        let word_start = index == 0
+            || candidate[index - 1] == '_'
+            || (candidate[index - 1].is_lowercase() && c.is_uppercase());
+        if word_start {
+            score += 3;
+        }
+        previous_match = Some(index);
+        next = index + 1;
+    }
+
+    Some(score * 8 - candidate.len() as i32)
+}
+
+fn eq_ignore_case(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_must_match_in_order() {
+        assert!(fuzzy_score("vtx", "vertex").is_some());
+        assert!(fuzzy_score("VTX", "vertex").is_some());
+        assert_eq!(fuzzy_score("xtv", "vertex"), None);
+        assert_eq!(fuzzy_score("vertexx", "vertex"), None);
+        assert!(fuzzy_score("", "vertex").is_some());
+    }
+
+    #[test]
+    fn consecutive_matches_score_higher() {
+        assert!(fuzzy_score("ver", "vertex") > fuzzy_score("ver", "v_e_r"));
+    }
+
+    #[test]
+    fn word_starts_score_higher() {
+        assert!(fuzzy_score("vo", "vertex_output") > fuzzy_score("vo", "avocado"));
+        assert!(fuzzy_score("vo", "vertexOutput") > fuzzy_score("vo", "vxxxxxoxxxxx"));
+    }
+
+    #[test]
+    fn matching_case_scores_higher() {
+        assert!(fuzzy_score("Light", "Light") > fuzzy_score("Light", "light"));
+    }
+
+    #[test]
+    fn shorter_candidates_score_higher() {
+        assert!(fuzzy_score("light", "light") > fuzzy_score("light", "lights"));
+    }
+}
@@ -0,0 +1,98 @@
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{de::Error, Deserialize, Deserializer};
+
+/// Glob patterns matched against `/`-separated paths, like `["target/**", "**/{generated,gen}"]`.
+///
+/// `*` and `?` don't match `/`, and `**` matches any number of directories, including none when
+/// it's followed by `/`.
+#[derive(Debug, Clone, Default)]
+pub struct Globs {
+    patterns: Vec<String>,
+    set: GlobSet,
+}
+
+impl Globs {
+    pub fn new(patterns: Vec<String>) -> Result<Self, globset::Error> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &patterns {
+            builder.add(glob(pattern)?);
+        }
+        Ok(Self {
+            set: builder.build()?,
+            patterns,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        self.set.is_match(path)
+    }
+}
+
+impl PartialEq for Globs {
+    fn eq(&self, other: &Self) -> bool {
+        self.patterns == other.patterns
+    }
+}
+
+impl<'de> Deserialize<'de> for Globs {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        Globs::new(Vec::deserialize(d)?).map_err(D::Error::custom)
+    }
+}
+
+fn glob(pattern: &str) -> Result<Glob, globset::Error> {
+    GlobBuilder::new(pattern).literal_separator(true).build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Globs::new(vec![pattern.to_owned()]).unwrap().is_match(path)
+    }
+
+    #[test]
+    fn single_stars_stay_in_one_directory() {
+        assert!(matches("*.wgsl", "a.wgsl"));
+        assert!(!matches("*.wgsl", "dir/a.wgsl"));
+        assert!(matches("dir/?.wgsl", "dir/a.wgsl"));
+        assert!(!matches("dir?a.wgsl", "dir/a.wgsl"));
+    }
+
+    #[test]
+    fn double_stars_cross_directories() {
+        assert!(matches("target/**", "target/debug/a.wgsl"));
+        assert!(matches("**/generated", "generated"));
+        assert!(matches("**/generated", "src/shaders/generated"));
+        assert!(matches("src/**/*.wgsl", "src/a.wgsl"));
+        assert!(matches("src/**/*.wgsl", "src/a/b/c.wgsl"));
+        assert!(!matches("src/**/*.wgsl", "other/a.wgsl"));
+    }
+
+    #[test]
+    fn alternatives_and_classes() {
+        assert!(matches("**/{generated,gen}", "src/gen"));
+        assert!(matches("**/{generated,gen}", "generated"));
+        assert!(!matches("**/{generated,gen}", "src/general"));
+        assert!(matches("shader[0-9].wgsl", "shader4.wgsl"));
+        assert!(!matches("shader[0-9].wgsl", "shaderx.wgsl"));
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        assert!(Globs::new(vec!["[unclosed".to_owned()]).is_err());
+        assert!(Globs::new(vec!["{a,b".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn patterns_compare_by_text() {
+        let globs = Globs::new(vec!["a/**".to_owned()]).unwrap();
+        assert_eq!(globs, Globs::new(vec!["a/**".to_owned()]).unwrap());
+        assert_ne!(globs, Globs::default());
+    }
+}
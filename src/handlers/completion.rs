@@ -0,0 +1,561 @@
+use std::{
+    collections::HashSet,
+    future::{ready, Future},
+    ops::Range,
+};
+
+use lsp_types::{
+    request::{Completion, ResolveCompletionItem},
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    CompletionTextEdit, Documentation, MarkupContent, MarkupKind, Position, TextEdit, Url,
+};
+use naga::{proc::TypeResolution, Function, Module, Scalar, TypeInner, VectorSize};
+use serde_json::{json, Value};
+
+use crate::{
+    builtins,
+    document::normalize_uri,
+    lexer::{is_ident_continue, prev_significant, token_at, tokenize, Token, TokenKind},
+    server::{Result, WgslServerState},
+    symbols::{
+        expression_type, function_by_name, module_item_by_name, module_item_name, path_at,
+        resolution_name, resolve_import_path, type_name, Symbol,
+    },
+    syntax::{self, Declaration, DeclarationKind, Import},
+};
+
+use super::hover::describe;
+
+/// Keywords after which the next identifier declares a new name, so there's nothing to complete.
+const DECLARING_KEYWORDS: &[&str] = &["fn", "struct", "alias", "let", "var", "const", "override"];
+
+/// An access following the root of an expression like `a.b[i].c`.
+enum Access {
+    Member(String),
+    Index,
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#completionOptions
+pub fn completion_capability() -> CompletionOptions {
+    CompletionOptions {
+        resolve_provider: Some(true),
+        trigger_characters: Some(vec![".".to_owned(), ":".to_owned(), "#".to_owned()]),
+        ..Default::default()
+    }
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_completion
+pub fn completion(
+    st: &mut WgslServerState,
+    params: CompletionParams,
+) -> impl Future<Output = Result<Completion>> {
+    let uri = normalize_uri(params.text_document_position.text_document.uri);
+    st.ensure_module(&uri);
+    let items = st.completions(&uri, params.text_document_position.position);
+    ready(Ok(items.map(CompletionResponse::Array)))
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#completionItem_resolve
+pub fn completion_resolve(
+    st: &mut WgslServerState,
+    mut item: CompletionItem,
+) -> impl Future<Output = Result<ResolveCompletionItem>> {
+    if item.documentation.is_none() {
+        let description = item
+            .data
+            .as_ref()
+            .and_then(|data| st.completion_description(data));
+        item.documentation = description.map(|description| {
+            Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```wgsl\n{description}\n```"),
+            })
+        });
+    }
+    ready(Ok(item))
+}
+
+impl WgslServerState {
+    /// Completions at a position, or [None] if nothing can be written there.
+    ///
+    /// Types are looked up in the last module built from the document, even if it's out of date,
+    /// since the source is rarely valid in the middle of typing.
+    fn completions(&self, uri: &Url, position: Position) -> Option<Vec<CompletionItem>> {
+        let source = self.open_documents.get(uri)?.source();
+        let offset = self.line_index(&source).offset(position);
+
+        let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        if let Some(directive) = source[line_start..offset].trim_start().strip_prefix('#') {
+            return self.directive_completions(uri, &source, directive, offset);
+        }
+
+        let tokens = tokenize(&source);
+        let mut word_start = offset;
+        if let Some(index) = token_at(&tokens, offset) {
+            let token = &tokens[index];
+            match token.kind {
+                TokenKind::Ident => word_start = token.range.start,
+                TokenKind::Comment | TokenKind::String | TokenKind::Number
+                    if token.range.start < offset =>
+                {
+                    return None
+                }
+                _ => {}
+            }
+        }
+        let declarations = syntax::declarations(&source, &tokens);
+        let imports = syntax::imports(&source, &tokens);
+
+        let previous = tokens
+            .iter()
+            .rposition(|t| t.range.end <= word_start && !t.is_trivia());
+        let Some(previous) = previous else {
+            return Some(self.identifier_completions(uri, &declarations, &imports, offset));
+        };
+        let previous_token = &tokens[previous];
+        if previous_token.is_punct(&source, ".") {
+            Some(self.member_completions(uri, &source, &tokens, &declarations, &imports, previous))
+        } else if previous_token.is_punct(&source, "::") {
+            let prefix = path_before(&source, &tokens, previous)?;
+            let module = resolve_import_path(&imports, &prefix);
+            let mut items = self.module_items(uri, &module);
+            items.extend(self.submodules(uri, &module));
+            Some(items)
+        } else if DECLARING_KEYWORDS.contains(&previous_token.text(&source)) {
+            None
+        } else {
+            Some(self.identifier_completions(uri, &declarations, &imports, offset))
+        }
+    }
+
+    /// Completions in a naga_oil directive, where `directive` is the text between the `#` and the cursor.
+    fn directive_completions(
+        &self,
+        uri: &Url,
+        source: &str,
+        directive: &str,
+        offset: usize,
+    ) -> Option<Vec<CompletionItem>> {
+        let keyword_len = directive
+            .find(|c: char| !is_ident_continue(c))
+            .unwrap_or(directive.len());
+        let (keyword, rest) = directive.split_at(keyword_len);
+        if rest.is_empty() {
+            return Some(
+                builtins::DIRECTIVES
+                    .iter()
+                    .map(|name| simple_item(name, CompletionItemKind::KEYWORD, 0))
+                    .collect(),
+            );
+        }
+        if keyword != "import" {
+            return None;
+        }
+
+        let path = rest.trim_start();
+        if let Some(open) = path.rfind('{') {
+            if path[open..].contains('}') {
+                return None;
+            }
+            let module = path[..open].trim_end().trim_end_matches("::");
+            return Some(self.module_items(uri, module));
+        }
+        // anything after whitespace is an `as` alias
+        if path.contains(char::is_whitespace) {
+            return None;
+        }
+        let mut items = self.module_names(uri, source, offset - path.len()..offset);
+        if let Some((module, _)) = path.rsplit_once("::") {
+            items.extend(self.module_items(uri, module));
+        }
+        Some(items)
+    }
+
+    /// Every module importable from the document at `uri`, replacing the partial path in `range`.
+    fn module_names(&self, uri: &Url, source: &str, range: Range<usize>) -> Vec<CompletionItem> {
+        let range = self.line_index(source).range(range.start, range.end);
+        self.project(uri)
+            .module_lookup
+            .keys()
+            // modules without a `#define_import_path` are keyed by their URL
+            .filter(|name| !name.contains("://"))
+            .map(|name| CompletionItem {
+                filter_text: Some(name.clone()),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: name.clone(),
+                })),
+                ..simple_item(name, CompletionItemKind::MODULE, 1)
+            })
+            .collect()
+    }
+
+    /// The next segment of every module in the project of `uri` whose path starts with `module::`.
+    fn submodules(&self, uri: &Url, module: &str) -> Vec<CompletionItem> {
+        let prefix = format!("{module}::");
+        let segments: HashSet<_> = self
+            .project(uri)
+            .module_lookup
+            .keys()
+            .filter_map(|name| name.strip_prefix(&prefix))
+            .filter_map(|rest| rest.split("::").next())
+            .collect();
+        segments
+            .into_iter()
+            .map(|segment| simple_item(segment, CompletionItemKind::MODULE, 1))
+            .collect()
+    }
+
+    /// The module-scope items a module exports, as completions in the document at `uri`.
+    fn module_items(&self, uri: &Url, module: &str) -> Vec<CompletionItem> {
+        let Some(document) = self
+            .project(uri)
+            .module_lookup
+            .get(module)
+            .and_then(|uri| self.open_documents.get(uri))
+        else {
+            return Vec::new();
+        };
+        let source = document.source();
+        let tokens = tokenize(&source);
+        syntax::declarations(&source, &tokens)
+            .iter()
+            .filter(|d| !matches!(d.kind, DeclarationKind::Function(Some(_))))
+            .map(|d| item_completion(uri, d, &format!("{module}::{}", d.name)))
+            .collect()
+    }
+
+    /// Members or swizzles of the expression before the `.` at token index `dot`.
+    fn member_completions(
+        &self,
+        uri: &Url,
+        source: &str,
+        tokens: &[Token],
+        declarations: &[Declaration],
+        imports: &[Import],
+        dot: usize,
+    ) -> Vec<CompletionItem> {
+        let Some(cached) = self.cached_modules.get(uri) else {
+            return Vec::new();
+        };
+        let module = &cached.module;
+        let Some((root, accesses)) = access_chain(source, tokens, dot) else {
+            return Vec::new();
+        };
+        let function = enclosing_function(declarations, tokens[dot].range.start)
+            .and_then(|d| function_by_name(module, &d.name))
+            .map(|function| function.get(module));
+        let ty = root_type(module, function, imports, &root).and_then(|root| {
+            let root = root.inner_with(&module.types).clone();
+            accesses
+                .iter()
+                .try_fold(root, |ty, access| access_type(module, ty, access))
+        });
+
+        match ty.map(|ty| deref(module, ty)) {
+            Some(TypeInner::Struct { members, .. }) => members
+                .iter()
+                .filter_map(|member| {
+                    let name = member.name.as_deref()?;
+                    Some(CompletionItem {
+                        detail: Some(type_name(module, member.ty)),
+                        ..simple_item(name, CompletionItemKind::FIELD, 0)
+                    })
+                })
+                .collect(),
+            Some(TypeInner::Vector { size, scalar }) => swizzles(module, size, scalar),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Everything an identifier can name at `offset`: locals, module-scope items, imports,
+    /// and builtins.
+    fn identifier_completions(
+        &self,
+        uri: &Url,
+        declarations: &[Declaration],
+        imports: &[Import],
+        offset: usize,
+    ) -> Vec<CompletionItem> {
+        let mut items = Vec::new();
+
+        if let Some(declaration) = enclosing_function(declarations, offset) {
+            let module = self.cached_modules.get(uri).map(|cached| &cached.module);
+            let function = module.and_then(|module| {
+                function_by_name(module, &declaration.name).map(|f| f.get(module))
+            });
+            let mut seen = HashSet::new();
+            // locals are visible once their statement ends, and the closest declaration shadows
+            // earlier ones with the same name
+            for local in declaration
+                .children
+                .iter()
+                .rev()
+                .filter(|c| c.kind == DeclarationKind::Argument || c.range.end <= offset)
+                .filter(|c| seen.insert(&c.name))
+            {
+                let detail = module.zip(function).and_then(|(module, function)| {
+                    let ty = root_type(module, Some(function), &[], &local.name)?;
+                    Some(resolution_name(module, &ty))
+                });
+                items.push(CompletionItem {
+                    detail,
+                    ..simple_item(&local.name, CompletionItemKind::VARIABLE, 0)
+                });
+            }
+        }
+
+        items.extend(
+            declarations
+                .iter()
+                .filter(|d| !matches!(d.kind, DeclarationKind::Function(Some(_))))
+                .map(|d| item_completion(uri, d, &d.name)),
+        );
+        for import in imports {
+            if self.project(uri).module_lookup.contains_key(&import.path) {
+                items.push(CompletionItem {
+                    detail: Some(import.path.clone()),
+                    ..simple_item(&import.name, CompletionItemKind::MODULE, 1)
+                });
+            } else if let Some((_, _, declaration)) = self.item_declaration(uri, &import.path) {
+                items.push(CompletionItem {
+                    label: import.name.clone(),
+                    sort_text: Some(format!("1{}", import.name)),
+                    ..item_completion(uri, &declaration, &import.path)
+                });
+            }
+        }
+
+        items.extend(builtins::FUNCTIONS.iter().map(|function| CompletionItem {
+            detail: Some(function.overloads[0].to_owned()),
+            data: Some(json!({ "builtin": function.name })),
+            ..simple_item(function.name, CompletionItemKind::FUNCTION, 2)
+        }));
+        items.extend(
+            builtins::TYPES
+                .iter()
+                .map(|name| simple_item(name, CompletionItemKind::TYPE_PARAMETER, 2)),
+        );
+        items.extend(
+            builtins::KEYWORDS
+                .iter()
+                .map(|name| simple_item(name, CompletionItemKind::KEYWORD, 3)),
+        );
+        items
+    }
+
+    /// Describe the item a completion refers to from the `data` it was created with.
+    fn completion_description(&self, data: &Value) -> Option<String> {
+        if let Some(name) = data.get("builtin").and_then(Value::as_str) {
+            return Some(builtins::function(name)?.overloads.join("\n"));
+        }
+        let uri = Url::parse(data.get("uri")?.as_str()?).ok()?;
+        let path = data.get("path")?.as_str()?;
+
+        // imported items are in the requesting module under their decorated name
+        if let Some(cached) = self.cached_modules.get(&uri) {
+            if let Some(symbol) = module_item_by_name(&cached.module, &module_item_name(path)) {
+                return describe(&cached.module, symbol);
+            }
+        }
+        let (declaring, _, declaration) = self.item_declaration(&uri, path)?;
+        let module = &self.cached_modules.get(&declaring)?.module;
+        describe(module, module_item_by_name(module, &declaration.name)?)
+    }
+}
+
+/// A completion item sorted into a group by `priority`, with lower priorities listed first.
+fn simple_item(label: &str, kind: CompletionItemKind, priority: u8) -> CompletionItem {
+    CompletionItem {
+        label: label.to_owned(),
+        kind: Some(kind),
+        sort_text: Some(format!("{priority}{label}")),
+        ..Default::default()
+    }
+}
+
+/// A completion for a module-scope declaration, resolved later by its path from the document at `uri`.
+fn item_completion(uri: &Url, declaration: &Declaration, path: &str) -> CompletionItem {
+    let kind = match declaration.kind {
+        DeclarationKind::Function(_) => CompletionItemKind::FUNCTION,
+        DeclarationKind::Struct => CompletionItemKind::STRUCT,
+        DeclarationKind::Member => CompletionItemKind::FIELD,
+        DeclarationKind::Constant | DeclarationKind::Override => CompletionItemKind::CONSTANT,
+        DeclarationKind::Alias => CompletionItemKind::TYPE_PARAMETER,
+        DeclarationKind::Global | DeclarationKind::Argument | DeclarationKind::Local => {
+            CompletionItemKind::VARIABLE
+        }
+    };
+    CompletionItem {
+        data: Some(json!({ "uri": uri, "path": path })),
+        ..simple_item(&declaration.name, kind, 1)
+    }
+}
+
+fn enclosing_function(declarations: &[Declaration], offset: usize) -> Option<&Declaration> {
+    declarations.iter().find(|d| {
+        matches!(d.kind, DeclarationKind::Function(_))
+            && d.range.start <= offset
+            && offset <= d.range.end
+    })
+}
+
+/// The path written before the `::` at token index `separator`, e.g. `a::b` in `a::b::`.
+fn path_before(source: &str, tokens: &[Token], separator: usize) -> Option<String> {
+    let mut segments = Vec::new();
+    let mut separator = separator;
+    loop {
+        let segment = prev_significant(tokens, separator)
+            .filter(|&i| matches!(tokens[i].kind, TokenKind::Ident | TokenKind::String))?;
+        segments.push(tokens[segment].text(source).trim_matches('"'));
+        match prev_significant(tokens, segment) {
+            Some(i) if tokens[i].is_punct(source, "::") => separator = i,
+            _ => break,
+        }
+    }
+    segments.reverse();
+    Some(segments.join("::"))
+}
+
+/// Split the expression before the `.` at token index `dot` into the name or path it starts
+/// with and the accesses after it.
+fn access_chain(source: &str, tokens: &[Token], dot: usize) -> Option<(String, Vec<Access>)> {
+    let mut accesses = Vec::new();
+    let mut index = prev_significant(tokens, dot)?;
+    loop {
+        if tokens[index].is_punct(source, "]") {
+            let mut depth = 0;
+            index = (0..=index).rev().find(|&i| {
+                if tokens[i].is_punct(source, "]") {
+                    depth += 1;
+                } else if tokens[i].is_punct(source, "[") {
+                    depth -= 1;
+                }
+                depth == 0
+            })?;
+            accesses.push(Access::Index);
+            index = prev_significant(tokens, index)?;
+            continue;
+        }
+        if tokens[index].kind != TokenKind::Ident {
+            return None;
+        }
+        match prev_significant(tokens, index) {
+            Some(previous) if tokens[previous].is_punct(source, ".") => {
+                accesses.push(Access::Member(tokens[index].text(source).to_owned()));
+                index = prev_significant(tokens, previous)?;
+            }
+            _ => {
+                let path = path_at(source, tokens, tokens[index].range.end)?;
+                accesses.reverse();
+                return Some((path.text(), accesses));
+            }
+        }
+    }
+}
+
+/// The type of a local, argument, `let` binding, global, or constant by the name it's written as.
+fn root_type(
+    module: &Module,
+    function: Option<&Function>,
+    imports: &[Import],
+    name: &str,
+) -> Option<TypeResolution> {
+    if let Some(fun) = function {
+        if let Some((_, local)) = fun
+            .local_variables
+            .iter()
+            .find(|(_, v)| v.name.as_deref() == Some(name))
+        {
+            return Some(TypeResolution::Handle(local.ty));
+        }
+        if let Some((&handle, _)) = fun.named_expressions.iter().find(|(_, n)| *n == name) {
+            return expression_type(module, fun, handle);
+        }
+        if let Some(argument) = fun
+            .arguments
+            .iter()
+            .find(|arg| arg.name.as_deref() == Some(name))
+        {
+            return Some(TypeResolution::Handle(argument.ty));
+        }
+    }
+    let full_path = resolve_import_path(imports, name);
+    let ty = match module_item_by_name(module, &module_item_name(&full_path))? {
+        Symbol::Global(handle) => module.global_variables[handle].ty,
+        Symbol::Constant(handle) => module.constants[handle].ty,
+        _ => return None,
+    };
+    Some(TypeResolution::Handle(ty))
+}
+
+/// Look through a pointer to the type it points to.
+fn deref(module: &Module, ty: TypeInner) -> TypeInner {
+    match ty {
+        TypeInner::Pointer { base, .. } => module.types[base].inner.clone(),
+        TypeInner::ValuePointer {
+            size: Some(size),
+            scalar,
+            ..
+        } => TypeInner::Vector { size, scalar },
+        TypeInner::ValuePointer { scalar, .. } => TypeInner::Scalar(scalar),
+        ty => ty,
+    }
+}
+
+/// The type of a member access, swizzle, or index into `ty`.
+fn access_type(module: &Module, ty: TypeInner, access: &Access) -> Option<TypeInner> {
+    let types = &module.types;
+    match (deref(module, ty), access) {
+        (TypeInner::Struct { members, .. }, Access::Member(name)) => members
+            .iter()
+            .find(|member| member.name.as_deref() == Some(name))
+            .map(|member| types[member.ty].inner.clone()),
+        (TypeInner::Vector { scalar, .. }, Access::Member(swizzle)) => {
+            Some(vector_or_scalar(swizzle.len(), scalar)?)
+        }
+        (TypeInner::Vector { scalar, .. }, Access::Index) => Some(TypeInner::Scalar(scalar)),
+        (TypeInner::Matrix { rows, scalar, .. }, Access::Index) => {
+            Some(TypeInner::Vector { size: rows, scalar })
+        }
+        (TypeInner::Array { base, .. } | TypeInner::BindingArray { base, .. }, Access::Index) => {
+            Some(types[base].inner.clone())
+        }
+        _ => None,
+    }
+}
+
+fn vector_or_scalar(len: usize, scalar: Scalar) -> Option<TypeInner> {
+    let size = match len {
+        1 => return Some(TypeInner::Scalar(scalar)),
+        2 => VectorSize::Bi,
+        3 => VectorSize::Tri,
+        4 => VectorSize::Quad,
+        _ => return None,
+    };
+    Some(TypeInner::Vector { size, scalar })
+}
+
+/// Every swizzle of a vector that keeps its components in order, in both `xyzw` and `rgba` form.
+fn swizzles(module: &Module, size: VectorSize, scalar: Scalar) -> Vec<CompletionItem> {
+    let count = size as usize;
+    let mut items = Vec::new();
+    for (set, components) in ["xyzw", "rgba"].iter().enumerate() {
+        for mask in 1u32..1 << count {
+            let label: String = components
+                .chars()
+                .take(count)
+                .enumerate()
+                .filter(|(i, _)| mask & (1 << i) != 0)
+                .map(|(_, c)| c)
+                .collect();
+            let ty = vector_or_scalar(label.len(), scalar).unwrap();
+            items.push(CompletionItem {
+                detail: Some(resolution_name(module, &TypeResolution::Value(ty))),
+                sort_text: Some(format!("{set}{}{mask:02}", label.len())),
+                ..simple_item(&label, CompletionItemKind::FIELD, 0)
+            });
+        }
+    }
+    items
+}
@@ -0,0 +1,136 @@
+use std::ops::ControlFlow;
+
+use lsp_types::{
+    notification::LogMessage, request::WorkspaceConfiguration, ConfigurationItem,
+    ConfigurationParams, DidChangeConfigurationParams, LogMessageParams, MessageType, Url,
+};
+use naga_oil::compose::Composer;
+use serde_json::Value;
+
+use crate::{
+    document::OpenDocument,
+    server::{NotifyResult, WgslServerState},
+    settings::{Settings, SECTION},
+    validate::validate_document,
+};
+
+/// Settings pulled from the client with `workspace/configuration`.
+pub struct ConfigurationReceived(Value);
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspace_didChangeConfiguration
+pub fn did_change_configuration(
+    st: &mut WgslServerState,
+    params: DidChangeConfigurationParams,
+) -> NotifyResult {
+    // clients that can be asked for configuration usually don't send it here
+    if st.supports_configuration_request {
+        st.request_configuration();
+        return ControlFlow::Continue(());
+    }
+    // clients may send all of their settings or just ours
+    let settings = params.settings.get(SECTION).unwrap_or(&params.settings);
+    st.load_settings(settings)
+}
+
+/// Load the settings the client sent in response to `workspace/configuration`.
+pub fn configuration_received(
+    st: &mut WgslServerState,
+    ConfigurationReceived(settings): ConfigurationReceived,
+) -> NotifyResult {
+    st.load_settings(&settings)
+}
+
+impl WgslServerState {
+    /// Ask the client for our section of its configuration, loading it once it's received.
+    ///
+    /// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspace_configuration
+    pub fn request_configuration(&self) {
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            let params = ConfigurationParams {
+                items: vec![ConfigurationItem {
+                    scope_uri: None,
+                    section: Some(SECTION.to_owned()),
+                }],
+            };
+            match client.request::<WorkspaceConfiguration>(params).await {
+                Ok(mut settings) if !settings.is_empty() => {
+                    // this only fails if the main loop stopped
+                    let _ = client.emit(ConfigurationReceived(settings.swap_remove(0)));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    let _ = client.notify::<LogMessage>(LogMessageParams {
+                        typ: MessageType::ERROR,
+                        message: format!("Failed to request configuration: {e}"),
+                    });
+                }
+            }
+        });
+    }
+
+    /// Apply settings sent by the client to every project, under those of its config file. They're
+    /// logged if they're invalid.
+    pub fn load_settings(&mut self, settings: &Value) -> NotifyResult {
+        match Settings::from_value(settings) {
+            Ok(client_settings) => {
+                self.settings = client_settings;
+                self.client_settings = settings.clone();
+                self.reload_settings()
+            }
+            Err(e) => self.log(MessageType::ERROR, &e),
+        }
+    }
+
+    /// Switch a project to new settings. If anything changed, its composer is rebuilt, and its
+    /// documents open in the client are re-validated.
+    ///
+    /// Files that are now excluded are unloaded, and files in new include paths are loaded.
+    pub fn apply_settings(&mut self, project: usize, settings: Settings) -> NotifyResult {
+        let current = &self.projects[project].settings;
+        if settings == *current {
+            return ControlFlow::Continue(());
+        }
+        let paths_changed =
+            settings.include_paths != current.include_paths || settings.exclude != current.exclude;
+        let capabilities = settings.capabilities.capabilities();
+        let project_state = &mut self.projects[project];
+        project_state.composer = Composer::non_validating().with_capabilities(capabilities);
+        project_state.settings = settings;
+        if paths_changed && project_state.files_loaded {
+            let excluded: Vec<Url> = self
+                .open_documents
+                .iter()
+                .filter(|(_, document)| matches!(document, OpenDocument::ServerOwned(..)))
+                .map(|(uri, _)| uri.clone())
+                .filter(|uri| uri.to_file_path().is_ok_and(|path| self.is_excluded(&path)))
+                .collect();
+            for uri in excluded {
+                if let ControlFlow::Break(result) = self.server_close(&uri) {
+                    return ControlFlow::Break(result);
+                }
+            }
+            self.reassign_documents();
+            if let ControlFlow::Break(result) = self.load_project_files(project) {
+                return ControlFlow::Break(result);
+            }
+        }
+
+        if !self.should_validate {
+            return ControlFlow::Continue(());
+        }
+        let documents: Vec<Url> = self
+            .open_documents
+            .iter()
+            .filter(|(_, document)| matches!(document, OpenDocument::ClientOwned(..)))
+            .map(|(uri, _)| uri.clone())
+            .filter(|uri| self.project_index(uri) == project)
+            .collect();
+        for uri in documents {
+            if let ControlFlow::Break(result) = validate_document(self, uri) {
+                return ControlFlow::Break(result);
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
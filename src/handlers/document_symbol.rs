@@ -0,0 +1,192 @@
+use std::future::{ready, Future};
+
+use lsp_types::{
+    request::DocumentSymbolRequest, DocumentSymbol, DocumentSymbolOptions, DocumentSymbolParams,
+    DocumentSymbolResponse, OneOf, SymbolKind,
+};
+use naga::TypeInner;
+
+use crate::{
+    document::normalize_uri,
+    line_index::LineIndex,
+    server::{Result, WgslServerState},
+    symbols::{function_by_name, module_item_by_name, stage_name, type_name, FunctionRef, Symbol},
+    syntax::{Declaration, DeclarationKind},
+    validate::CachedModule,
+};
+
+use super::hover::function_signature;
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#documentSymbolOptions
+pub fn document_symbol_capability() -> OneOf<bool, DocumentSymbolOptions> {
+    OneOf::Left(true)
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_documentSymbol
+pub fn document_symbol(
+    st: &mut WgslServerState,
+    params: DocumentSymbolParams,
+) -> impl Future<Output = Result<DocumentSymbolRequest>> {
+    let uri = normalize_uri(params.text_document.uri);
+    st.ensure_module(&uri);
+    let Some(document) = st.open_documents.get(&uri) else {
+        return ready(Ok(None));
+    };
+    let source = document.source();
+    let declarations = document.syntax_tree().declarations(&source);
+    let index = st.line_index(&source);
+    let symbols = match st.current_module(&uri) {
+        Some(cached) => module_symbols(cached, &declarations, &index),
+        // scan the source so the outline doesn't disappear while the module can't be built
+        None => declarations
+            .iter()
+            .map(|declaration| declaration_symbol(&index, declaration, None, Vec::new()))
+            .collect(),
+    };
+    ready(Ok(Some(DocumentSymbolResponse::Nested(symbols))))
+}
+
+/// Outline the items in the module, using the declarations scanned from the source for ranges.
+///
+/// Declarations that aren't in the module, like those in inactive `#ifdef` blocks, are left out.
+fn module_symbols(
+    cached: &CachedModule,
+    declarations: &[Declaration],
+    index: &LineIndex,
+) -> Vec<DocumentSymbol> {
+    let module = &cached.module;
+    let is_local = |span: naga::Span| cached.source_map.span_to_source(span).is_some();
+
+    declarations
+        .iter()
+        .filter_map(|declaration| match declaration.kind {
+            DeclarationKind::Function(_) => {
+                let function = function_by_name(module, &declaration.name)?;
+                if let FunctionRef::Function(handle) = function {
+                    if !is_local(module.functions.get_span(handle)) {
+                        return None;
+                    }
+                }
+                let fun = function.get(module);
+                let arguments = declaration
+                    .children
+                    .iter()
+                    .filter(|c| c.kind == DeclarationKind::Argument)
+                    .zip(&fun.arguments)
+                    .map(|(argument, arg)| {
+                        declaration_symbol(
+                            index,
+                            argument,
+                            Some(type_name(module, arg.ty)),
+                            Vec::new(),
+                        )
+                    })
+                    .collect();
+                let detail = match function {
+                    FunctionRef::EntryPoint(index) => {
+                        format!("@{}", stage_name(module.entry_points[index].stage))
+                    }
+                    FunctionRef::Function(_) => function_signature(module, function),
+                };
+                Some(declaration_symbol(
+                    index,
+                    declaration,
+                    Some(detail),
+                    arguments,
+                ))
+            }
+            DeclarationKind::Struct => {
+                let Symbol::Type(handle) = module_item_by_name(module, &declaration.name)? else {
+                    return None;
+                };
+                if !is_local(module.types.get_span(handle)) {
+                    return None;
+                }
+                let TypeInner::Struct { members, .. } = &module.types[handle].inner else {
+                    return None;
+                };
+                let members = declaration
+                    .children
+                    .iter()
+                    .zip(members)
+                    .map(|(declaration, member)| {
+                        declaration_symbol(
+                            index,
+                            declaration,
+                            Some(type_name(module, member.ty)),
+                            Vec::new(),
+                        )
+                    })
+                    .collect();
+                Some(declaration_symbol(index, declaration, None, members))
+            }
+            DeclarationKind::Constant => {
+                let Symbol::Constant(handle) = module_item_by_name(module, &declaration.name)?
+                else {
+                    return None;
+                };
+                is_local(module.constants.get_span(handle)).then(|| {
+                    let ty = type_name(module, module.constants[handle].ty);
+                    declaration_symbol(index, declaration, Some(ty), Vec::new())
+                })
+            }
+            DeclarationKind::Global => {
+                let Symbol::Global(handle) = module_item_by_name(module, &declaration.name)? else {
+                    return None;
+                };
+                is_local(module.global_variables.get_span(handle)).then(|| {
+                    let ty = type_name(module, module.global_variables[handle].ty);
+                    declaration_symbol(index, declaration, Some(ty), Vec::new())
+                })
+            }
+            // naga doesn't keep overrides or aliases in the module
+            DeclarationKind::Override | DeclarationKind::Alias => {
+                Some(declaration_symbol(index, declaration, None, Vec::new()))
+            }
+            DeclarationKind::Member | DeclarationKind::Argument | DeclarationKind::Local => None,
+        })
+        .collect()
+}
+
+/// Make a symbol from a scanned declaration. Children of functions and structs are included
+/// if none are given.
+#[allow(deprecated)]
+fn declaration_symbol(
+    index: &LineIndex,
+    declaration: &Declaration,
+    detail: Option<String>,
+    mut children: Vec<DocumentSymbol>,
+) -> DocumentSymbol {
+    if children.is_empty() {
+        children = declaration
+            .children
+            .iter()
+            .filter(|c| c.kind != DeclarationKind::Local)
+            .map(|c| declaration_symbol(index, c, None, Vec::new()))
+            .collect();
+    }
+    let (kind, detail) = match declaration.kind {
+        DeclarationKind::Function(Some(stage)) => (
+            SymbolKind::FUNCTION,
+            detail.or_else(|| Some(format!("@{}", stage_name(stage)))),
+        ),
+        DeclarationKind::Function(None) => (SymbolKind::FUNCTION, detail),
+        DeclarationKind::Struct => (SymbolKind::STRUCT, detail),
+        DeclarationKind::Member => (SymbolKind::FIELD, detail),
+        DeclarationKind::Argument | DeclarationKind::Local => (SymbolKind::VARIABLE, detail),
+        DeclarationKind::Constant => (SymbolKind::CONSTANT, detail),
+        DeclarationKind::Override => (SymbolKind::CONSTANT, Some("override".to_owned())),
+        DeclarationKind::Global => (SymbolKind::VARIABLE, detail),
+        DeclarationKind::Alias => (SymbolKind::TYPE_PARAMETER, Some("alias".to_owned())),
+    };
+    DocumentSymbol {
+        name: declaration.name.clone(),
+        detail,
+        kind,
+        tags: None,
+        deprecated: None,
+        range: index.range(declaration.range.start, declaration.range.end),
+        selection_range: index.range(declaration.name_range.start, declaration.name_range.end),
+        children: (!children.is_empty()).then_some(children),
+    }
+}
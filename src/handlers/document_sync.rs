@@ -8,8 +8,10 @@ use ropey::Rope;
 
 use crate::{
     document::{normalize_uri, OpenDocument},
+    line_index::LineIndex,
     server::{NotifyResult, WgslServerState},
-    validate::validate_document,
+    syntax_tree::SyntaxTree,
+    validate::validate_document_and_dependents,
 };
 
 /// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocumentSyncOptions
@@ -32,11 +34,22 @@ pub fn did_open_document(
     let uri = normalize_uri(params.text_document.uri);
     st.open_documents.insert(
         uri.clone(),
-        OpenDocument::ClientOwned(Rope::from_str(&params.text_document.text)),
+        OpenDocument::client_owned(&params.text_document.text, params.text_document.version),
     );
-    st.log(MessageType::INFO, &format!("Opened document: {}", uri));
+    let old_module = st
+        .project(&uri)
+        .dependency_graph
+        .module(&uri)
+        .map(str::to_owned);
+    st.preprocess(&uri);
+    st.update_workspace_symbols(&uri);
+    if let ControlFlow::Break(result) =
+        st.log(MessageType::INFO, &format!("Opened document: {}", uri))
+    {
+        return ControlFlow::Break(result);
+    }
     if st.should_validate {
-        validate_document(st, uri)
+        validate_document_and_dependents(st, uri, old_module)
     } else {
         ControlFlow::Continue(())
     }
@@ -52,21 +65,38 @@ pub fn did_change_document(
         Ok(_) => (),
         Err(e) => return st.log(MessageType::ERROR, &e.message),
     }
+    let encoding = st.position_encoding;
     if let Some(doc) = st.open_documents.get_mut(&uri) {
-        if let OpenDocument::ClientOwned(text) = doc {
+        if let OpenDocument::ClientOwned(text, version, tree) = doc {
+            // the last module that built keeps track of the edits, so it can stand in for the
+            // document until it builds again
+            let mut edits = st
+                .cached_modules
+                .get_mut(&uri)
+                .map(|cached| &mut cached.edits);
             for change in params.content_changes {
+                let source = text.to_string();
                 if let Some(range) = change.range {
-                    let start = text.line_to_char(range.start.line as usize)
-                        + range.start.character as usize;
-                    let end =
-                        text.line_to_char(range.end.line as usize) + range.end.character as usize;
+                    let index = LineIndex::new(&source, encoding);
+                    let (start, end) = (index.offset(range.start), index.offset(range.end));
+                    if let Some(edits) = &mut edits {
+                        edits.push(start..end, change.text.len());
+                    }
+                    let (start, end) = (text.byte_to_char(start), text.byte_to_char(end));
                     text.remove(start..end);
                     text.insert(start, &change.text);
                 } else {
+                    if let Some(edits) = &mut edits {
+                        edits.replace_all(&source, &change.text);
+                    }
                     *text = Rope::from_str(&change.text);
                 }
             }
-            validate_document(st, uri)
+            *version = params.text_document.version;
+            *tree = SyntaxTree::parse(&text.to_string());
+            st.update_workspace_symbols(&uri);
+            st.schedule_validation(uri);
+            ControlFlow::Continue(())
         } else {
             st.log(
                 MessageType::ERROR,
@@ -87,13 +117,29 @@ pub fn did_close_document(
     params: DidCloseTextDocumentParams,
 ) -> NotifyResult {
     let uri = normalize_uri(params.text_document.uri);
-    if st.open_documents.contains_key(&uri) {
-        st.server_open(uri);
-    } else {
+    let Some(document) = st.open_documents.remove(&uri) else {
         return st.log(
             MessageType::ERROR,
             "Closed document was not found in the open documents list. This is a bug.",
         );
+    };
+    // the client forgets the semantic tokens it had for the document
+    st.semantic_tokens.remove(&uri);
+    if let ControlFlow::Break(result) = st.server_open(uri.clone()) {
+        return ControlFlow::Break(result);
+    }
+    // the file on disk replaces the client's text, so the module stands in for it until it's
+    // built from it
+    match st.open_documents.get(&uri).map(OpenDocument::source) {
+        Some(source) => {
+            if let Some(cached) = st.cached_modules.get_mut(&uri) {
+                cached.edits.replace_all(&document.source(), &source);
+            }
+            st.schedule_validation(uri);
+        }
+        None => {
+            st.cached_modules.remove(&uri);
+        }
     }
     ControlFlow::Continue(())
 }
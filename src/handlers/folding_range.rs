@@ -0,0 +1,127 @@
+use std::future::{ready, Future};
+
+use lsp_types::{
+    request::FoldingRangeRequest, FoldingRange, FoldingRangeKind, FoldingRangeParams,
+    FoldingRangeProviderCapability,
+};
+
+use crate::{
+    document::normalize_uri,
+    lexer::TokenKind,
+    server::{Result, WgslServerState},
+    syntax_tree::{last_token, NodeKind},
+};
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#foldingRangeOptions
+pub fn folding_range_capability() -> FoldingRangeProviderCapability {
+    FoldingRangeProviderCapability::Simple(true)
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_foldingRange
+///
+/// Ranges come from the syntax tree, so they're still there while the document doesn't build.
+pub fn folding_range(
+    st: &mut WgslServerState,
+    params: FoldingRangeParams,
+) -> impl Future<Output = Result<FoldingRangeRequest>> {
+    let uri = normalize_uri(params.text_document.uri);
+    let Some(document) = st.open_documents.get(&uri) else {
+        return ready(Ok(None));
+    };
+    let (source, tree) = (document.source(), document.syntax_tree());
+    let index = st.line_index(&source);
+    let line = |offset: usize| index.position(offset).line;
+
+    let mut ranges = Vec::new();
+    let mut fold = |start_line: u32, end_line: u32, kind: Option<FoldingRangeKind>| {
+        if end_line > start_line {
+            ranges.push(FoldingRange {
+                start_line,
+                end_line,
+                kind,
+                ..Default::default()
+            });
+        }
+    };
+
+    // lines of the `#if`, `#ifdef`, or `#else` starting each open branch
+    let mut branches = Vec::new();
+    let mut imports: Option<(u32, u32)> = None;
+    for node in tree.root.descendants() {
+        match node.kind {
+            NodeKind::Block | NodeKind::MemberList => {
+                // the closing brace stays visible
+                let closed =
+                    last_token(node).is_some_and(|t| tree.tokens[t].is_punct(&source, "}"));
+                let end_line = line(node.range.end);
+                fold(
+                    line(node.range.start),
+                    end_line.saturating_sub(closed as u32),
+                    None,
+                );
+            }
+            NodeKind::Directive => {
+                let (start_line, end_line) = (line(node.range.start), line(node.range.end));
+                let keyword = node
+                    .child_tokens()
+                    .find(|&t| tree.tokens[t].kind == TokenKind::Ident)
+                    .map(|t| tree.tokens[t].text(&source));
+                if keyword == Some("import") {
+                    imports = match imports {
+                        Some((start, end)) if end + 1 >= start_line => Some((start, end_line)),
+                        _ => {
+                            if let Some((start, end)) = imports {
+                                fold(start, end, Some(FoldingRangeKind::Imports));
+                            }
+                            Some((start_line, end_line))
+                        }
+                    };
+                    continue;
+                }
+                match keyword {
+                    Some("if" | "ifdef" | "ifndef") => branches.push(start_line),
+                    Some("else") => {
+                        if let Some(start) = branches.pop() {
+                            fold(start, start_line - 1, Some(FoldingRangeKind::Region));
+                        }
+                        branches.push(start_line);
+                    }
+                    Some("endif") => {
+                        if let Some(start) = branches.pop() {
+                            fold(start, start_line - 1, Some(FoldingRangeKind::Region));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some((start, end)) = imports {
+        fold(start, end, Some(FoldingRangeKind::Imports));
+    }
+
+    // block comments, and runs of line comments
+    let mut comments: Option<(u32, u32)> = None;
+    for token in tree.tokens.iter().filter(|t| t.kind == TokenKind::Comment) {
+        let (start_line, end_line) = (line(token.range.start), line(token.range.end));
+        if token.text(&source).starts_with("/*") {
+            fold(start_line, end_line, Some(FoldingRangeKind::Comment));
+            continue;
+        }
+        comments = match comments {
+            Some((start, end)) if end + 1 == start_line => Some((start, end_line)),
+            _ => {
+                if let Some((start, end)) = comments {
+                    fold(start, end, Some(FoldingRangeKind::Comment));
+                }
+                Some((start_line, end_line))
+            }
+        };
+    }
+    if let Some((start, end)) = comments {
+        fold(start, end, Some(FoldingRangeKind::Comment));
+    }
+
+    ready(Ok(Some(ranges)))
+}
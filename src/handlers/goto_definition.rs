@@ -0,0 +1,209 @@
+use std::{
+    future::{ready, Future},
+    ops::Range,
+};
+
+use lsp_types::{
+    request::GotoDefinition, DefinitionOptions, GotoDefinitionParams, GotoDefinitionResponse,
+    Location, OneOf, Position, Url,
+};
+
+use crate::{
+    document::normalize_uri,
+    lexer::tokenize,
+    line_index::{LineIndex, PositionEncoding},
+    server::{Result, WgslServerState},
+    symbols::{item_name, path_at, resolve, resolve_import_path, undecorate, Symbol},
+    syntax::{self, Declaration, DeclarationKind},
+};
+
+/// Where a name is declared.
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub uri: Url,
+    /// Source of the document the declaration is in.
+    pub source: String,
+    /// Range of the declared name in `source`.
+    pub name_range: Range<usize>,
+}
+
+impl Definition {
+    pub fn location(&self, encoding: PositionEncoding) -> Location {
+        let index = LineIndex::new(&self.source, encoding);
+        Location::new(
+            self.uri.clone(),
+            index.range(self.name_range.start, self.name_range.end),
+        )
+    }
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#definitionOptions
+pub fn definition_capability() -> OneOf<bool, DefinitionOptions> {
+    OneOf::Left(true)
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_definition
+pub fn goto_definition(
+    st: &mut WgslServerState,
+    params: GotoDefinitionParams,
+) -> impl Future<Output = Result<GotoDefinition>> {
+    let uri = normalize_uri(params.text_document_position_params.text_document.uri);
+    let definition = st.find_definition(&uri, params.text_document_position_params.position);
+    ready(Ok(definition.map(|definition| {
+        GotoDefinitionResponse::Scalar(definition.location(st.position_encoding))
+    })))
+}
+
+impl WgslServerState {
+    /// Find where the name at `position` is declared, following imports into other documents.
+    ///
+    /// This uses the last module that built, even if the document was edited since, and falls
+    /// back to scanning the source for names that aren't in it.
+    pub fn find_definition(&mut self, uri: &Url, position: Position) -> Option<Definition> {
+        self.ensure_module(uri);
+        let source = self.open_documents.get(uri)?.source();
+        let offset = self.line_index(&source).offset(position);
+        let tokens = tokenize(&source);
+        let imports = syntax::imports(&source, &tokens);
+        let declarations = syntax::declarations(&source, &tokens);
+
+        // paths in `#import` directives lead to the module or item they name
+        for import in &imports {
+            if let Some(index) = import
+                .segments
+                .iter()
+                .position(|(_, range)| range.start <= offset && offset <= range.end)
+            {
+                let path = syntax::join_path(&import.segments[..=index]);
+                return self
+                    .module_definition(uri, &path)
+                    .or_else(|| self.item_definition(uri, &path));
+            }
+            if let Some((_, range)) = &import.alias {
+                if range.start <= offset && offset <= range.end {
+                    return self.item_definition(uri, &syntax::join_path(&import.segments));
+                }
+            }
+        }
+
+        // the module is the last one that built, which may be from before the latest edits
+        let cached = self.cached_modules.get(uri);
+        let resolved = cached.and_then(|cached| {
+            let (name_range, symbol) = resolve(cached, cached.edits.unmap(offset)?)?;
+            // the name must not have been edited since
+            Some((cached.edits.map_range(name_range)?, symbol))
+        });
+        if let Some((name_range, symbol)) = resolved {
+            let module = &cached.unwrap().module;
+            return match symbol {
+                Symbol::Argument(..) | Symbol::Local(..) | Symbol::Let(..) => {
+                    local_declaration(&declarations, offset, &source[name_range]).map(|d| {
+                        Definition {
+                            uri: uri.clone(),
+                            source: source.clone(),
+                            name_range: d.name_range.clone(),
+                        }
+                    })
+                }
+                Symbol::Member(_, index) => {
+                    let path = undecorate(item_name(module, symbol)?);
+                    let (uri, source, declaration) = self.item_declaration(uri, &path)?;
+                    let member = declaration.children.get(index as usize)?;
+                    Some(Definition {
+                        uri,
+                        source,
+                        name_range: member.name_range.clone(),
+                    })
+                }
+                Symbol::Expression(..) => None,
+                _ => {
+                    let path = undecorate(item_name(module, symbol)?);
+                    self.item_definition(uri, &path)
+                }
+            };
+        }
+
+        let path = path_at(&source, &tokens, offset)?;
+        if path.is_member {
+            // finding the member needs the type of the expression, which needs the module
+            return None;
+        }
+        if path.active + 1 != path.segments.len() {
+            let prefix = syntax::join_path(&path.segments[..=path.active]);
+            return self.module_definition(uri, &resolve_import_path(&imports, &prefix));
+        }
+        if path.segments.len() == 1 {
+            let declaration = local_declaration(&declarations, offset, &path.text())
+                .or_else(|| declarations.iter().find(|d| d.name == path.text()));
+            if let Some(declaration) = declaration {
+                return Some(Definition {
+                    uri: uri.clone(),
+                    source,
+                    name_range: declaration.name_range.clone(),
+                });
+            }
+        }
+        self.item_definition(uri, &resolve_import_path(&imports, &path.text()))
+    }
+
+    /// Find the module-scope declaration of an item by its full path, e.g. `my::module::item`.
+    /// Paths without a module refer to items declared in the document at `uri`.
+    ///
+    /// Returns the document the item is declared in, its source, and the declaration.
+    pub fn item_declaration(&self, uri: &Url, path: &str) -> Option<(Url, String, Declaration)> {
+        let (uri, item) = match path.rsplit_once("::") {
+            Some((module, item)) => (self.project(uri).module_lookup.get(module)?, item),
+            None => (uri, path),
+        };
+        let source = self.open_documents.get(uri)?.source();
+        let tokens = tokenize(&source);
+        let declaration = syntax::declarations(&source, &tokens)
+            .into_iter()
+            .find(|d| d.name == item)?;
+        Some((uri.clone(), source, declaration))
+    }
+
+    fn item_definition(&self, uri: &Url, path: &str) -> Option<Definition> {
+        let (uri, source, declaration) = self.item_declaration(uri, path)?;
+        Some(Definition {
+            uri,
+            source,
+            name_range: declaration.name_range,
+        })
+    }
+
+    /// The `#define_import_path` of a module in the project of `uri`, or the start of its document
+    /// if it's named by path.
+    fn module_definition(&self, uri: &Url, module: &str) -> Option<Definition> {
+        let uri = self.project(uri).module_lookup.get(module)?;
+        let source = self.open_documents.get(uri)?.source();
+        let tokens = tokenize(&source);
+        let name_range =
+            syntax::define_import_path(&source, &tokens).map_or(0..0, |(_, range)| range);
+        Some(Definition {
+            uri: uri.clone(),
+            source,
+            name_range,
+        })
+    }
+}
+
+/// Find the argument or local declaration of `name` visible at `offset`, approximating
+/// shadowing by taking the closest declaration before it.
+pub fn local_declaration<'a>(
+    declarations: &'a [Declaration],
+    offset: usize,
+    name: &str,
+) -> Option<&'a Declaration> {
+    let function = declarations.iter().find(|d| {
+        matches!(d.kind, DeclarationKind::Function(_))
+            && d.range.start <= offset
+            && offset <= d.range.end
+    })?;
+    let mut candidates = function.children.iter().filter(|c| c.name == name);
+    candidates
+        .clone()
+        .rev()
+        .find(|c| c.name_range.start <= offset)
+        .or_else(|| candidates.next())
+}
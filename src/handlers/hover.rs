@@ -0,0 +1,219 @@
+use std::future::{ready, Future};
+
+use lsp_types::{
+    request::HoverRequest, Hover, HoverContents, HoverParams, HoverProviderCapability,
+    MarkupContent, MarkupKind,
+};
+use naga::{AddressSpace, Module, StorageAccess, TypeInner};
+
+use crate::{
+    document::normalize_uri,
+    server::{Result, WgslServerState},
+    symbols::{
+        expression_type, resolution_name, resolve, stage_name, type_name, undecorate, FunctionRef,
+        Symbol,
+    },
+};
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#hoverOptions
+pub fn hover_capability() -> HoverProviderCapability {
+    HoverProviderCapability::Simple(true)
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_hover
+pub fn hover(
+    st: &mut WgslServerState,
+    params: HoverParams,
+) -> impl Future<Output = Result<HoverRequest>> {
+    let uri = normalize_uri(params.text_document_position_params.text_document.uri);
+    st.ensure_module(&uri);
+
+    // the module is the last one that built, which may be from before the latest edits
+    let (Some(document), Some(cached)) = (st.open_documents.get(&uri), st.cached_modules.get(&uri))
+    else {
+        return ready(Ok(None));
+    };
+    let source = document.source();
+    let index = st.line_index(&source);
+    let offset = index.offset(params.text_document_position_params.position);
+    let Some(offset) = cached.edits.unmap(offset) else {
+        return ready(Ok(None));
+    };
+    let (mut range, symbol) = match resolve(cached, offset) {
+        // the name must not have been edited since
+        Some(resolved) if cached.edits.map_range(resolved.0.clone()).is_some() => resolved,
+        _ => return ready(Ok(None)),
+    };
+    // highlight the whole expression when we can only describe its type
+    if let Symbol::Expression(function, handle) = symbol {
+        let span = function.get(&cached.module).expressions.get_span(handle);
+        if let Some(expression_range) = cached.source_map.span_to_source(span) {
+            range = expression_range;
+        }
+    }
+    let description = match describe(&cached.module, symbol) {
+        Some(description) => description,
+        None => return ready(Ok(None)),
+    };
+
+    ready(Ok(Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("```wgsl\n{description}\n```"),
+        }),
+        range: cached
+            .edits
+            .map_range(range)
+            .map(|range| index.range(range.start, range.end)),
+    })))
+}
+
+/// Format a symbol the way it would be declared in WGSL.
+pub fn describe(module: &Module, symbol: Symbol) -> Option<String> {
+    let name = |name: &Option<String>| undecorate(name.as_deref().unwrap_or("_"));
+
+    Some(match symbol {
+        Symbol::Function(handle) => function_signature(module, FunctionRef::Function(handle)),
+        Symbol::EntryPoint(index) => {
+            let ep = &module.entry_points[index];
+            let stage = stage_name(ep.stage);
+            let workgroup_size = match ep.stage {
+                naga::ShaderStage::Compute => format!(
+                    " @workgroup_size({}, {}, {})",
+                    ep.workgroup_size[0], ep.workgroup_size[1], ep.workgroup_size[2]
+                ),
+                _ => String::new(),
+            };
+            format!(
+                "@{stage}{workgroup_size}\n{}",
+                function_signature(module, FunctionRef::EntryPoint(index))
+            )
+        }
+        Symbol::Type(handle) => {
+            let ty = &module.types[handle];
+            match &ty.inner {
+                TypeInner::Struct { members, span } => {
+                    let mut text = format!("struct {} {{\n", name(&ty.name));
+                    for member in members {
+                        text.push_str(&format!(
+                            "    {}: {}, // offset {}\n",
+                            name(&member.name),
+                            type_name(module, member.ty),
+                            member.offset
+                        ));
+                    }
+                    text.push_str(&format!("}} // size {span}"));
+                    text
+                }
+                _ => type_name(module, handle),
+            }
+        }
+        Symbol::Member(handle, index) => match &module.types[handle].inner {
+            TypeInner::Struct { members, .. } => {
+                let member = members.get(index as usize)?;
+                format!(
+                    "{}.{}: {}",
+                    name(&module.types[handle].name),
+                    name(&member.name),
+                    type_name(module, member.ty)
+                )
+            }
+            _ => return None,
+        },
+        Symbol::Global(handle) => {
+            let var = &module.global_variables[handle];
+            let binding = match &var.binding {
+                Some(binding) => {
+                    format!("@group({}) @binding({})\n", binding.group, binding.binding)
+                }
+                None => String::new(),
+            };
+            format!(
+                "{binding}var{} {}: {}",
+                address_space(var.space),
+                name(&var.name),
+                type_name(module, var.ty)
+            )
+        }
+        Symbol::Constant(handle) => {
+            let constant = &module.constants[handle];
+            format!(
+                "const {}: {}",
+                name(&constant.name),
+                type_name(module, constant.ty)
+            )
+        }
+        Symbol::Argument(function, index) => {
+            let arg = function.get(module).arguments.get(index as usize)?;
+            format!("{}: {}", name(&arg.name), type_name(module, arg.ty))
+        }
+        Symbol::Local(function, handle) => {
+            let var = &function.get(module).local_variables[handle];
+            format!("var {}: {}", name(&var.name), type_name(module, var.ty))
+        }
+        Symbol::Let(function, handle) => {
+            let fun = function.get(module);
+            let ty = expression_type(module, fun, handle)?;
+            format!(
+                "let {}: {}",
+                fun.named_expressions.get(&handle)?,
+                resolution_name(module, &ty)
+            )
+        }
+        Symbol::Expression(function, handle) => {
+            let ty = expression_type(module, function.get(module), handle)?;
+            resolution_name(module, &ty)
+        }
+    })
+}
+
+/// Format a function's declaration without its body, e.g. `fn f(a: f32) -> vec4<f32>`.
+pub fn function_signature(module: &Module, function: FunctionRef) -> String {
+    let (name, fun) = match function {
+        FunctionRef::Function(handle) => (
+            undecorate(module.functions[handle].name.as_deref().unwrap_or("_")),
+            &module.functions[handle],
+        ),
+        FunctionRef::EntryPoint(index) => (
+            module.entry_points[index].name.clone(),
+            &module.entry_points[index].function,
+        ),
+    };
+    let arguments = fun
+        .arguments
+        .iter()
+        .map(|arg| {
+            format!(
+                "{}: {}",
+                arg.name.as_deref().unwrap_or("_"),
+                type_name(module, arg.ty)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    match &fun.result {
+        Some(result) => format!("fn {name}({arguments}) -> {}", type_name(module, result.ty)),
+        None => format!("fn {name}({arguments})"),
+    }
+}
+
+/// The template list for a `var` in the given address space, e.g. `<storage, read_write>`.
+fn address_space(space: AddressSpace) -> String {
+    match space {
+        AddressSpace::Function => "<function>".to_owned(),
+        AddressSpace::Private => "<private>".to_owned(),
+        AddressSpace::WorkGroup => "<workgroup>".to_owned(),
+        AddressSpace::Uniform => "<uniform>".to_owned(),
+        AddressSpace::Storage { access } => {
+            if access.contains(StorageAccess::LOAD | StorageAccess::STORE) {
+                "<storage, read_write>".to_owned()
+            } else if access.contains(StorageAccess::STORE) {
+                "<storage, write>".to_owned()
+            } else {
+                "<storage, read>".to_owned()
+            }
+        }
+        AddressSpace::Handle => String::new(),
+        AddressSpace::PushConstant => "<push_constant>".to_owned(),
+    }
+}
@@ -1,15 +1,25 @@
-use std::future::{ready, Future};
+use std::{
+    future::{ready, Future},
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+};
 
+use async_lsp::{ErrorCode, ResponseError};
 use lsp_types::{
     notification::LogMessage,
     request::{Initialize, RegisterCapability, Shutdown},
     DidChangeWatchedFilesRegistrationOptions, FileSystemWatcher, InitializeParams,
     InitializeResult, InitializedParams, LogMessageParams, MessageType, Registration,
-    RegistrationParams, Url,
+    RegistrationParams, ServerCapabilities, Url,
 };
 use walkdir::WalkDir;
 
-use crate::server::{get_server_info, NotifyResult, Result, WgslServerState};
+use crate::{
+    config_file::CONFIG_FILE_NAMES,
+    line_index::PositionEncoding,
+    project::Project,
+    server::{get_server_info, NotifyResult, Result, WgslServerState},
+};
 
 use super::get_server_capabilities;
 
@@ -18,45 +28,55 @@ pub fn initialize(
     st: &mut WgslServerState,
     params: InitializeParams,
 ) -> impl Future<Output = Result<Initialize>> {
-    // load .wgsl files from workspace folders
-    let workspace_paths = params
-        .workspace_folders
-        .unwrap_or_default()
-        .into_iter()
-        .filter_map(|f| f.uri.to_file_path().ok())
-        .filter_map(|p| p.into_os_string().into_string().ok());
+    st.position_encoding = PositionEncoding::negotiate(
+        params
+            .capabilities
+            .general
+            .as_ref()
+            .and_then(|general| general.position_encodings.as_deref())
+            .unwrap_or_default(),
+    );
 
-    // load .wgsl files from additional include paths
-    let include_paths = params
-        .initialization_options
+    st.supports_configuration_request = params
+        .capabilities
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.configuration)
+        .unwrap_or(false);
+    st.supports_semantic_tokens_refresh = params
+        .capabilities
+        .workspace
         .as_ref()
-        .and_then(|v| v.as_object())
-        .and_then(|opts| opts.get("includePaths"))
-        .and_then(|paths| paths.as_array())
-        .cloned()
-        .unwrap_or(vec![])
+        .and_then(|workspace| workspace.semantic_tokens.as_ref())
+        .and_then(|semantic_tokens| semantic_tokens.refresh_support)
+        .unwrap_or(false);
+    let folders = params
+        .workspace_folders
+        .unwrap_or_default()
         .into_iter()
-        .filter_map(|p| p.as_str().map(str::to_owned));
-
-    for path in workspace_paths.chain(include_paths) {
-        for path in WalkDir::new(&path)
-            .into_iter()
-            .filter_map(|f| f.ok())
-            .map(|f| f.into_path())
-            .filter(|p| p.extension().map(|ex| ex == "wgsl").unwrap_or(false) && p.is_file())
-        {
-            st.log(
-                MessageType::INFO,
-                &format!("Loading .wgsl file: {}", path.display()),
-            );
-            let uri = Url::from_file_path(path).unwrap();
-            st.server_open(uri);
-        }
+        .filter_map(|f| f.uri.to_file_path().ok());
+    for folder in folders {
+        st.projects
+            .push(Project::new(Some(folder), st.settings.clone()));
+        st.load_config_file(st.projects.len() - 1);
+    }
+    // settings sent here are replaced by `workspace/configuration` if the client supports it
+    if st
+        .load_settings(&params.initialization_options.unwrap_or_default())
+        .is_break()
+    {
+        return ready(Err(ResponseError::new(
+            ErrorCode::INTERNAL_ERROR,
+            "The connection to the client was closed",
+        )));
     }
 
     ready(Ok(InitializeResult {
         server_info: Some(get_server_info()),
-        capabilities: get_server_capabilities(),
+        capabilities: ServerCapabilities {
+            position_encoding: Some(st.position_encoding.kind()),
+            ..get_server_capabilities()
+        },
     }))
 }
 
@@ -65,8 +85,7 @@ pub fn initialized(st: &mut WgslServerState, _: InitializedParams) -> NotifyResu
     let client = st.client.clone();
 
     tokio::spawn(async move {
-        // this allows us to be notified about .wgsl files being created or deleted in the workspace
-        // TODO: add handler for this notification
+        // this allows us to be notified about .wgsl and config files being changed in the workspace
         match client
             .request::<RegisterCapability>(RegistrationParams {
                 registrations: vec![Registration {
@@ -74,10 +93,14 @@ pub fn initialized(st: &mut WgslServerState, _: InitializedParams) -> NotifyResu
                     method: "workspace/didChangeWatchedFiles".to_string(),
                     register_options: Some(
                         serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
-                            watchers: vec![FileSystemWatcher {
-                                glob_pattern: "**/*.wgsl".to_string().into(),
-                                kind: None,
-                            }],
+                            watchers: ["*.wgsl"]
+                                .into_iter()
+                                .chain(CONFIG_FILE_NAMES)
+                                .map(|name| FileSystemWatcher {
+                                    glob_pattern: format!("**/{name}").into(),
+                                    kind: None,
+                                })
+                                .collect(),
                         })
                         .unwrap(),
                     ),
@@ -100,9 +123,65 @@ pub fn initialized(st: &mut WgslServerState, _: InitializedParams) -> NotifyResu
         };
     });
 
+    if st.supports_configuration_request {
+        st.request_configuration();
+    }
+    for project in 0..st.projects.len() {
+        // files are only loaded now that the settings, with config files on top, are final
+        if let ControlFlow::Break(result) = st.load_project_files(project) {
+            return ControlFlow::Break(result);
+        }
+        // errors in config files can't be published before the client is initialized
+        if let ControlFlow::Break(result) = st.publish_config_diagnostics(project, None) {
+            return ControlFlow::Break(result);
+        }
+    }
+
     st.log(MessageType::INFO, "server_initialized!")
 }
 
+impl WgslServerState {
+    /// Open the .wgsl files in a project's workspace folder and additional include paths.
+    pub fn load_project_files(&mut self, project: usize) -> NotifyResult {
+        self.projects[project].files_loaded = true;
+        let project = &self.projects[project];
+        let include_paths = project.settings.include_paths.iter().map(PathBuf::from);
+        let paths: Vec<PathBuf> = project.root.iter().cloned().chain(include_paths).collect();
+        for path in paths {
+            if let ControlFlow::Break(result) = self.load_wgsl_files(&path) {
+                return ControlFlow::Break(result);
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Open every .wgsl file in a directory and its subdirectories as server-owned, unless it's
+    /// already open or excluded.
+    pub fn load_wgsl_files(&mut self, path: &Path) -> NotifyResult {
+        for path in WalkDir::new(path)
+            .into_iter()
+            .filter_map(|f| f.ok())
+            .map(|f| f.into_path())
+            .filter(|p| p.extension().map(|ex| ex == "wgsl").unwrap_or(false) && p.is_file())
+        {
+            let uri = Url::from_file_path(&path).unwrap();
+            if self.open_documents.contains_key(&uri) || self.is_excluded(&path) {
+                continue;
+            }
+            if let ControlFlow::Break(result) = self.log(
+                MessageType::INFO,
+                &format!("Loading .wgsl file: {}", path.display()),
+            ) {
+                return ControlFlow::Break(result);
+            }
+            if let ControlFlow::Break(result) = self.server_open(uri) {
+                return ControlFlow::Break(result);
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
 /// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#shutdown
 pub fn shutdown(_: &mut WgslServerState, _: ()) -> impl Future<Output = Result<Shutdown>> {
     ready(Ok(()))
@@ -1,17 +1,50 @@
 use lsp_types::ServerCapabilities;
 
 use self::{
-    document_sync::text_document_sync_capability, semantic_tokens::semantic_tokens_capabilies,
+    completion::completion_capability,
+    document_symbol::document_symbol_capability,
+    document_sync::text_document_sync_capability,
+    folding_range::folding_range_capability,
+    goto_definition::definition_capability,
+    hover::hover_capability,
+    references::{document_highlight_capability, references_capability},
+    rename::rename_capability,
+    semantic_tokens::semantic_tokens_capabilies,
+    signature_help::signature_help_capability,
+    workspace_symbol::workspace_symbol_capability,
+    workspace_sync::workspace_capability,
 };
 
+pub mod completion;
+pub mod configuration;
+pub mod document_symbol;
 pub mod document_sync;
+pub mod folding_range;
+pub mod goto_definition;
+pub mod hover;
 pub mod lifecycle;
+pub mod references;
+pub mod rename;
 pub mod semantic_tokens;
+pub mod signature_help;
+pub mod workspace_symbol;
+pub mod workspace_sync;
 
 pub fn get_server_capabilities() -> ServerCapabilities {
     ServerCapabilities {
         text_document_sync: Some(text_document_sync_capability()),
         semantic_tokens_provider: Some(semantic_tokens_capabilies()),
+        hover_provider: Some(hover_capability()),
+        definition_provider: Some(definition_capability()),
+        references_provider: Some(references_capability()),
+        document_highlight_provider: Some(document_highlight_capability()),
+        rename_provider: Some(rename_capability()),
+        document_symbol_provider: Some(document_symbol_capability()),
+        workspace_symbol_provider: Some(workspace_symbol_capability()),
+        folding_range_provider: Some(folding_range_capability()),
+        completion_provider: Some(completion_capability()),
+        signature_help_provider: Some(signature_help_capability()),
+        workspace: Some(workspace_capability()),
         ..Default::default()
     }
 }
@@ -0,0 +1,143 @@
+use std::{
+    future::{ready, Future},
+    ops::Range,
+};
+
+use lsp_types::{
+    request::{DocumentHighlightRequest, References},
+    DocumentHighlight, DocumentHighlightKind, DocumentHighlightOptions, DocumentHighlightParams,
+    Location, OneOf, Position, ReferenceParams, ReferencesOptions, Url,
+};
+
+use crate::{
+    document::normalize_uri,
+    server::{Result, WgslServerState},
+    symbol_index::SymbolKey,
+    validate::{validate_document_inner, CachedModule},
+};
+
+/// A name referring to a symbol.
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub uri: Url,
+    pub range: Range<usize>,
+    pub is_declaration: bool,
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#referenceOptions
+pub fn references_capability() -> OneOf<bool, ReferencesOptions> {
+    OneOf::Left(true)
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#documentHighlightOptions
+pub fn document_highlight_capability() -> OneOf<bool, DocumentHighlightOptions> {
+    OneOf::Left(true)
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_references
+pub fn references(
+    st: &mut WgslServerState,
+    params: ReferenceParams,
+) -> impl Future<Output = Result<References>> {
+    let uri = normalize_uri(params.text_document_position.text_document.uri);
+    let position = params.text_document_position.position;
+    let include_declaration = params.context.include_declaration;
+
+    let locations = st.find_references(&uri, position).map(|references| {
+        references
+            .into_iter()
+            .filter(|r| include_declaration || !r.is_declaration)
+            .filter_map(|r| {
+                let source = st.open_documents.get(&r.uri)?.source();
+                let range = st.line_index(&source).range(r.range.start, r.range.end);
+                Some(Location::new(r.uri, range))
+            })
+            .collect()
+    });
+    ready(Ok(locations))
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_documentHighlight
+pub fn document_highlight(
+    st: &mut WgslServerState,
+    params: DocumentHighlightParams,
+) -> impl Future<Output = Result<DocumentHighlightRequest>> {
+    let uri = normalize_uri(params.text_document_position_params.text_document.uri);
+    let position = params.text_document_position_params.position;
+    st.ensure_module(&uri);
+
+    // the module is the last one that built, which may be from before the latest edits
+    let (Some(document), Some(cached)) = (st.open_documents.get(&uri), st.cached_modules.get(&uri))
+    else {
+        return ready(Ok(None));
+    };
+    let source = document.source();
+    let index = st.line_index(&source);
+    let highlights = occurrence_key(cached, index.offset(position)).map(|key| {
+        cached
+            .index
+            .occurrences_of(key)
+            .filter_map(|o| {
+                let range = cached.edits.map_range(o.range.clone())?;
+                Some(DocumentHighlight {
+                    range: index.range(range.start, range.end),
+                    kind: Some(if o.is_declaration {
+                        DocumentHighlightKind::WRITE
+                    } else {
+                        DocumentHighlightKind::READ
+                    }),
+                })
+            })
+            .collect()
+    });
+    ready(Ok(highlights))
+}
+
+/// The symbol named at `offset` in the current source of a document, if the name hasn't been
+/// edited since its module was built.
+fn occurrence_key(cached: &CachedModule, offset: usize) -> Option<&SymbolKey> {
+    let occurrence = cached.index.occurrence_at(cached.edits.unmap(offset)?)?;
+    cached.edits.map_range(occurrence.range.clone())?;
+    Some(&occurrence.key)
+}
+
+impl WgslServerState {
+    /// Find every name that refers to the same symbol as the name at `position`.
+    ///
+    /// Module-scope items are searched for in the module that declares them and every module
+    /// that imports it, building modules that haven't been built yet. Ranges are in the current
+    /// source of each document, and names edited since their module last built are left out.
+    pub fn find_references(&mut self, uri: &Url, position: Position) -> Option<Vec<Reference>> {
+        self.ensure_module(uri);
+        let source = self.open_documents.get(uri)?.source();
+        let offset = self.line_index(&source).offset(position);
+        let key = occurrence_key(self.cached_modules.get(uri)?, offset)?.clone();
+
+        let mut documents = vec![uri.clone()];
+        if let Some(module) = key.module() {
+            if let Some(declaring) = self.project(uri).module_lookup.get(module) {
+                documents.push(declaring.clone());
+            }
+            documents.extend(self.dependents(uri, module));
+        }
+        documents.sort();
+        documents.dedup();
+
+        let mut references = Vec::new();
+        for document in documents {
+            if !self.cached_modules.contains_key(&document) {
+                let _ = validate_document_inner(self, document.clone());
+            }
+            if let Some(cached) = self.cached_modules.get(&document) {
+                references.extend(cached.index.occurrences_of(&key).filter_map(|o| {
+                    Some(Reference {
+                        uri: document.clone(),
+                        range: cached.edits.map_range(o.range.clone())?,
+                        is_declaration: o.is_declaration,
+                    })
+                }));
+            }
+        }
+        Some(references)
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    collections::HashMap,
+    future::{ready, Future},
+    ops::Range,
+};
+
+use async_lsp::{ErrorCode, ResponseError};
+use lsp_types::{
+    request::{PrepareRenameRequest, Rename},
+    OneOf, Position, PrepareRenameResponse, RenameOptions, RenameParams,
+    TextDocumentPositionParams, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::{
+    document::normalize_uri,
+    lexer::{is_ident_continue, is_ident_start, tokenize},
+    server::{Result, WgslServerState},
+    symbols::paths,
+    syntax::{self, join_path},
+};
+
+/// What renaming at a position would change.
+enum RenameTarget {
+    /// A module, by its `#define_import_path` and the range of the path under the cursor.
+    Module(String, Range<usize>),
+    /// A symbol, by the range of the name under the cursor.
+    Symbol(Range<usize>),
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#renameOptions
+pub fn rename_capability() -> OneOf<bool, RenameOptions> {
+    OneOf::Right(RenameOptions {
+        prepare_provider: Some(true),
+        work_done_progress_options: Default::default(),
+    })
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_prepareRename
+pub fn prepare_rename(
+    st: &mut WgslServerState,
+    params: TextDocumentPositionParams,
+) -> impl Future<Output = Result<PrepareRenameRequest>> {
+    let uri = normalize_uri(params.text_document.uri);
+    let response = st.rename_target(&uri, params.position).and_then(|target| {
+        let source = st.open_documents.get(&uri)?.source();
+        let range = match target {
+            RenameTarget::Module(_, range) | RenameTarget::Symbol(range) => range,
+        };
+        Some(PrepareRenameResponse::RangeWithPlaceholder {
+            range: st.line_index(&source).range(range.start, range.end),
+            placeholder: source[range].to_owned(),
+        })
+    });
+    ready(Ok(response))
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_rename
+pub fn rename(
+    st: &mut WgslServerState,
+    params: RenameParams,
+) -> impl Future<Output = Result<Rename>> {
+    let uri = normalize_uri(params.text_document_position.text_document.uri);
+    let position = params.text_document_position.position;
+    let new_name = params.new_name;
+
+    let edit = match st.rename_target(&uri, position) {
+        Some(RenameTarget::Module(old_name, _)) => {
+            if !new_name.split("::").all(is_identifier) {
+                return ready(Err(ResponseError::new(
+                    ErrorCode::INVALID_PARAMS,
+                    format!("`{new_name}` is not a valid module path"),
+                )));
+            }
+            st.rename_module(&uri, &old_name, &new_name)
+        }
+        Some(RenameTarget::Symbol(_)) => {
+            if !is_identifier(&new_name) {
+                return ready(Err(ResponseError::new(
+                    ErrorCode::INVALID_PARAMS,
+                    format!("`{new_name}` is not a valid identifier"),
+                )));
+            }
+            st.rename_symbol(&uri, position, &new_name)
+        }
+        None => None,
+    };
+    ready(Ok(edit))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(is_ident_start) && chars.all(is_ident_continue)
+}
+
+impl WgslServerState {
+    fn rename_target(&mut self, uri: &Url, position: Position) -> Option<RenameTarget> {
+        let source = self.open_documents.get(uri)?.source();
+        let offset = self.line_index(&source).offset(position);
+        let tokens = tokenize(&source);
+        let contains = |range: &Range<usize>| range.start <= offset && offset <= range.end;
+
+        if let Some((name, range)) = syntax::define_import_path(&source, &tokens) {
+            if contains(&range) {
+                return Some(RenameTarget::Module(name, range));
+            }
+        }
+        // module paths in `#import` directives
+        for import in syntax::imports(&source, &tokens) {
+            let module = (1..import.segments.len()).find(|&end| {
+                contains(&import.segments[end - 1].1)
+                    && self
+                        .project(uri)
+                        .module_lookup
+                        .contains_key(&join_path(&import.segments[..end]))
+            });
+            if let Some(end) = module {
+                let range = import.segments[0].1.start..import.segments[end - 1].1.end;
+                return Some(RenameTarget::Module(
+                    join_path(&import.segments[..end]),
+                    range,
+                ));
+            }
+        }
+
+        // the module is the last one that built, which may be from before the latest edits
+        self.ensure_module(uri);
+        let cached = self.cached_modules.get(uri)?;
+        let occurrence = cached.index.occurrence_at(cached.edits.unmap(offset)?)?;
+        let range = cached.edits.map_range(occurrence.range.clone())?;
+        Some(RenameTarget::Symbol(range))
+    }
+
+    /// Rename the symbol at `position` everywhere it's referred to by name.
+    fn rename_symbol(
+        &mut self,
+        uri: &Url,
+        position: Position,
+        new_name: &str,
+    ) -> Option<WorkspaceEdit> {
+        let references = self.find_references(uri, position)?;
+        let mut sources = HashMap::new();
+        for reference in &references {
+            if !sources.contains_key(&reference.uri) {
+                let source = self.open_documents.get(&reference.uri)?.source();
+                sources.insert(reference.uri.clone(), source);
+            }
+        }
+        let old_name = references
+            .iter()
+            .find(|r| r.is_declaration)
+            .or_else(|| references.first())
+            .map(|r| &sources[&r.uri][r.range.clone()])?;
+
+        let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+        for reference in &references {
+            let source = &sources[&reference.uri];
+            // names given by an `#import ... as alias` keep their alias
+            if source[reference.range.clone()] != *old_name {
+                continue;
+            }
+            changes
+                .entry(reference.uri.clone())
+                .or_default()
+                .push(TextEdit::new(
+                    self.line_index(source)
+                        .range(reference.range.start, reference.range.end),
+                    new_name.to_owned(),
+                ));
+        }
+        Some(WorkspaceEdit::new(changes))
+    }
+
+    /// Change a module's `#define_import_path` and every path that refers to it.
+    fn rename_module(&self, uri: &Url, old_name: &str, new_name: &str) -> Option<WorkspaceEdit> {
+        let declaring = self.project(uri).module_lookup.get(old_name)?.clone();
+        let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+
+        let source = self.open_documents.get(&declaring)?.source();
+        let tokens = tokenize(&source);
+        if let Some((_, range)) = syntax::define_import_path(&source, &tokens) {
+            changes.insert(
+                declaring.clone(),
+                vec![TextEdit::new(
+                    self.line_index(&source).range(range.start, range.end),
+                    new_name.to_owned(),
+                )],
+            );
+        }
+
+        for importer in self.dependents(uri, old_name) {
+            let Some(document) = self.open_documents.get(&importer) else {
+                continue;
+            };
+            let source = document.source();
+            let index = self.line_index(&source);
+            let edits = module_path_edits(&source, old_name, new_name)
+                .into_iter()
+                .map(|(range, text)| TextEdit::new(index.range(range.start, range.end), text));
+            changes.entry(importer).or_default().extend(edits);
+        }
+        Some(WorkspaceEdit::new(changes))
+    }
+}
+
+/// Edits to a document that imports the module `old_name` so that it refers to `new_name`.
+///
+/// This covers paths in `#import` directives, qualified `module::item` paths, and paths starting
+/// with the last segment of a module that's imported as a whole, e.g. `lib::item` after
+/// `#import my::lib`. Module paths split across an import list can't be renamed in place and are
+/// left alone.
+fn module_path_edits(source: &str, old_name: &str, new_name: &str) -> Vec<(Range<usize>, String)> {
+    let tokens = tokenize(source);
+    let old_segments: Vec<_> = old_name.split("::").collect();
+    let old_last = *old_segments.last().unwrap();
+    let new_last = new_name.rsplit("::").next().unwrap_or(new_name);
+    let mut edits = Vec::new();
+
+    // the range of the old module path at the start of a path, if it's written contiguously
+    let module_prefix = |segments: &[(String, Range<usize>)]| {
+        if segments.len() < old_segments.len()
+            || join_path(&segments[..old_segments.len()]) != old_name
+        {
+            return None;
+        }
+        let range = segments[0].1.start..segments[old_segments.len() - 1].1.end;
+        let written: String = source[range.clone()]
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        (written.trim_matches('"') == old_name).then_some(range)
+    };
+
+    let imports = syntax::imports(source, &tokens);
+    for import in &imports {
+        if let Some(range) = module_prefix(&import.segments) {
+            edits.push((range, new_name.to_owned()));
+        }
+    }
+
+    let whole_module = imports
+        .iter()
+        .any(|import| import.path == old_name && import.alias.is_none());
+    let directives = syntax::directives(source, &tokens);
+    for path in paths(source, &tokens) {
+        let range = path.range();
+        if directives
+            .iter()
+            .any(|d| d.start <= range.start && range.end <= d.end)
+        {
+            continue;
+        }
+        if let Some(range) = module_prefix(&path.segments) {
+            edits.push((range, new_name.to_owned()));
+        } else if whole_module
+            && old_last != new_last
+            && path.segments.len() > 1
+            && path.segments[0].0 == old_last
+        {
+            edits.push((path.segments[0].1.clone(), new_last.to_owned()));
+        }
+    }
+
+    // import lists repeat the same module path for every item
+    edits.sort_by_key(|(range, _)| range.start);
+    edits.dedup_by_key(|(range, _)| range.start);
+    edits
+}
@@ -5,29 +5,43 @@ use std::{
 
 use bitflags::bitflags;
 use lsp_types::{
-    request::SemanticTokensFullRequest, MessageType, Position, SemanticToken,
-    SemanticTokenModifier, SemanticTokenType, SemanticTokens, SemanticTokensFullOptions,
-    SemanticTokensLegend, SemanticTokensOptions, SemanticTokensParams, SemanticTokensResult,
-    SemanticTokensServerCapabilities,
+    request::{
+        SemanticTokensFullDeltaRequest, SemanticTokensFullRequest, SemanticTokensRangeRequest,
+        SemanticTokensRefresh,
+    },
+    Position, SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokens,
+    SemanticTokensDelta, SemanticTokensDeltaParams, SemanticTokensEdit,
+    SemanticTokensFullDeltaResult, SemanticTokensFullOptions, SemanticTokensLegend,
+    SemanticTokensOptions, SemanticTokensParams, SemanticTokensRangeParams,
+    SemanticTokensRangeResult, SemanticTokensResult, SemanticTokensServerCapabilities, Url,
 };
-use naga::{AddressSpace, Expression, Function, StorageAccess};
+use naga::{AddressSpace, GlobalVariable, Module, StorageAccess};
 
 use crate::{
-    document::normalize_uri,
+    builtins,
+    document::{normalize_uri, OpenDocument},
+    lexer::{self, next_significant, prev_significant, TokenKind},
+    line_index::LineIndex,
     server::{Result, WgslServerState},
-    validate::{calc_position, validate_document},
+    symbols::Symbol,
+    syntax::{self, DeclarationKind},
+    syntax_tree::SyntaxTree,
+    validate::CachedModule,
 };
 
 bitflags! {
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Copy)]
     struct TokenModifiers: u32 {
         const READONLY = 1;
         const STATIC = 2;
         const DEFAULT_LIBRARY = 4;
+        /// Left out by the preprocessor under the current shader defs.
+        const INACTIVE = 8;
+        const DECLARATION = 16;
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum TokenType {
     Type,
     Struct,
@@ -35,17 +49,29 @@ enum TokenType {
     Variable,
     Parameter,
     Number,
+    Comment,
+    Property,
+    EnumMember,
+    Decorator,
+    Keyword,
+    Namespace,
 }
 
-impl Into<u32> for &TokenType {
-    fn into(self) -> u32 {
-        match self {
+impl From<&TokenType> for u32 {
+    fn from(ty: &TokenType) -> Self {
+        match ty {
             TokenType::Type => 0,
             TokenType::Struct => 1,
             TokenType::Function => 2,
             TokenType::Variable => 3,
             TokenType::Parameter => 4,
             TokenType::Number => 5,
+            TokenType::Comment => 6,
+            TokenType::Property => 7,
+            TokenType::EnumMember => 8,
+            TokenType::Decorator => 9,
+            TokenType::Keyword => 10,
+            TokenType::Namespace => 11,
         }
     }
 }
@@ -71,7 +97,7 @@ struct Token {
 pub fn semantic_tokens_capabilies() -> SemanticTokensServerCapabilities {
     SemanticTokensOptions {
         full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
-        range: Some(false),
+        range: Some(true),
         legend: SemanticTokensLegend {
             token_types: Vec::from([
                 SemanticTokenType::TYPE,
@@ -80,11 +106,19 @@ pub fn semantic_tokens_capabilies() -> SemanticTokensServerCapabilities {
                 SemanticTokenType::VARIABLE,
                 SemanticTokenType::PARAMETER,
                 SemanticTokenType::NUMBER,
+                SemanticTokenType::COMMENT,
+                SemanticTokenType::PROPERTY,
+                SemanticTokenType::ENUM_MEMBER,
+                SemanticTokenType::DECORATOR,
+                SemanticTokenType::KEYWORD,
+                SemanticTokenType::NAMESPACE,
             ]),
             token_modifiers: Vec::from([
                 SemanticTokenModifier::READONLY,
                 SemanticTokenModifier::STATIC,
                 SemanticTokenModifier::DEFAULT_LIBRARY,
+                SemanticTokenModifier::new("inactive"),
+                SemanticTokenModifier::DECLARATION,
             ]),
         },
         ..Default::default()
@@ -92,196 +126,379 @@ pub fn semantic_tokens_capabilies() -> SemanticTokensServerCapabilities {
     .into()
 }
 
-/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_semanticTokens
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#semanticTokens_fullRequest
 pub fn semantic_tokens_full(
     st: &mut WgslServerState,
     params: SemanticTokensParams,
 ) -> impl Future<Output = Result<SemanticTokensFullRequest>> {
     let uri = normalize_uri(params.text_document.uri);
-    validate_document(st, uri.clone());
+    let Some((source, tokens)) = document_tokens(st, &uri) else {
+        return ready(Ok(None));
+    };
+    let data = encode_tokens(&st.line_index(&source), tokens);
+    let tokens = remember_tokens(st, uri, data);
+    ready(Ok(Some(SemanticTokensResult::Tokens(tokens))))
+}
 
-    let cached = match st.cached_modules.get(&uri) {
-        Some(cached) => cached,
-        None => return ready(Ok(None)),
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#semanticTokens_deltaRequest
+pub fn semantic_tokens_full_delta(
+    st: &mut WgslServerState,
+    params: SemanticTokensDeltaParams,
+) -> impl Future<Output = Result<SemanticTokensFullDeltaRequest>> {
+    let uri = normalize_uri(params.text_document.uri);
+    let Some((source, tokens)) = document_tokens(st, &uri) else {
+        return ready(Ok(None));
+    };
+    let data = encode_tokens(&st.line_index(&source), tokens);
+    let previous = st
+        .semantic_tokens
+        .remove(&uri)
+        .filter(|previous| previous.result_id.as_ref() == Some(&params.previous_result_id));
+    let tokens = remember_tokens(st, uri, data);
+    // the client sends the full tokens again if it doesn't have the previous result anymore
+    let result = match previous {
+        Some(previous) => SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta {
+            edits: token_edits(&previous.data, &tokens.data),
+            result_id: tokens.result_id,
+        }),
+        None => SemanticTokensFullDeltaResult::Tokens(tokens),
     };
+    ready(Ok(Some(result)))
+}
 
-    let module = &cached.module;
-    let source = &st
-        .composer
-        .module_sets
-        .get(&cached.module_name)
-        .unwrap()
-        .sanitized_source;
-    let mut tokens = Vec::new();
-
-    // Collect all the tokens we can.
-    for (handle, constant) in module.constants.iter() {
-        if let Some(range) = module.constants.get_span(handle).to_range() {
-            let src = &source[range.start..range.end];
-            if let Some(name) = &constant.name {
-                let start = range.start + src.find(name).unwrap();
-                tokens.push(Token {
-                    offset: start,
-                    length: name.len(),
-                    ty: TokenType::Variable,
-                    modifiers: TokenModifiers::READONLY,
-                });
-            }
-        }
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#semanticTokens_rangeRequest
+///
+/// Clients ask for the visible range first so that large documents highlight sooner, so this
+/// only classifies the tokens in the range, and doesn't wait for the document to build.
+pub fn semantic_tokens_range(
+    st: &mut WgslServerState,
+    params: SemanticTokensRangeParams,
+) -> impl Future<Output = Result<SemanticTokensRangeRequest>> {
+    let uri = normalize_uri(params.text_document.uri);
+    let Some(source) = st.open_documents.get(&uri).map(OpenDocument::source) else {
+        return ready(Ok(None));
+    };
+    let index = st.line_index(&source);
+    let range = index.offset(params.range.start)..index.offset(params.range.end);
+    let Some(tokens) = tokens_in(st, &uri, &source, range) else {
+        return ready(Ok(None));
+    };
+    ready(Ok(Some(SemanticTokensRangeResult::Tokens(
+        SemanticTokens {
+            result_id: None,
+            data: encode_tokens(&index, tokens),
+        },
+    ))))
+}
+
+/// Tokens for the current source of a document, which is returned along with them.
+fn document_tokens(st: &mut WgslServerState, uri: &Url) -> Option<(String, Vec<Token>)> {
+    st.ensure_module(uri);
+    let source = st.open_documents.get(uri)?.source();
+    let tokens = tokens_in(st, uri, &source, 0..source.len())?;
+    Some((source, tokens))
+}
+
+/// Tokens overlapping `range` of the current source of a document.
+fn tokens_in(
+    st: &WgslServerState,
+    uri: &Url,
+    source: &str,
+    range: Range<usize>,
+) -> Option<Vec<Token>> {
+    let tree = st.open_documents.get(uri)?.syntax_tree();
+    let mut tokens = match st.cached_modules.get(uri) {
+        Some(cached) => module_tokens(cached, source, &tree.tokens, range.clone()),
+        // highlight what can be told from the syntax alone until the module builds
+        None => syntactic_tokens(tree, source, range.clone()),
+    };
+
+    // Grey out lines excluded by #ifdef and friends
+    let inactive: Vec<_> = st
+        .inactive_lines(uri, source)
+        .into_iter()
+        .filter(|line| line.start < range.end && range.start < line.end)
+        .collect();
+    tokens.retain(|token| {
+        !inactive
+            .iter()
+            .any(|range| range.start <= token.offset && token.offset < range.end)
+    });
+    tokens.extend(inactive.into_iter().map(|range| Token {
+        offset: range.start,
+        length: range.len(),
+        ty: TokenType::Comment,
+        modifiers: TokenModifiers::INACTIVE,
+    }));
+    Some(tokens)
+}
+
+/// Give tokens sent for a document a new result id, and keep them to compute the next delta
+/// against.
+fn remember_tokens(st: &mut WgslServerState, uri: Url, data: Vec<SemanticToken>) -> SemanticTokens {
+    st.next_semantic_tokens_id += 1;
+    let tokens = SemanticTokens {
+        result_id: Some(st.next_semantic_tokens_id.to_string()),
+        data,
+    };
+    st.semantic_tokens.insert(uri, tokens.clone());
+    tokens
+}
+
+/// The edit turning `old` into `new`, replacing everything between the tokens they start and end
+/// with. Edits index into the flattened data, which has 5 numbers per token.
+fn token_edits(old: &[SemanticToken], new: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let deleted = old.len() - prefix - suffix;
+    let inserted = &new[prefix..new.len() - suffix];
+    if deleted == 0 && inserted.is_empty() {
+        return Vec::new();
     }
+    vec![SemanticTokensEdit {
+        start: 5 * prefix as u32,
+        delete_count: 5 * deleted as u32,
+        data: (!inserted.is_empty()).then(|| inserted.to_vec()),
+    }]
+}
 
-    for (handle, ty) in module.types.iter() {
-        if let Some(range) = module.types.get_span(handle).to_range() {
-            if let Some(name) = &ty.name {
-                let src = &source[range.start..range.end];
-                let start = range.start + src.find(name).unwrap();
-                tokens.push(Token {
-                    offset: start,
-                    length: name.len(),
-                    ty: TokenType::Type,
-                    modifiers: TokenModifiers::empty(),
-                });
-            }
+impl WgslServerState {
+    /// Ask the client to request semantic tokens again, after modules were rebuilt.
+    pub fn refresh_semantic_tokens(&self) {
+        if !self.supports_semantic_tokens_refresh {
+            return;
         }
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            // the client keeps its tokens if this fails
+            let _ = client.request::<SemanticTokensRefresh>(()).await;
+        });
     }
+}
 
-    for (handle, var) in module.global_variables.iter() {
-        if let Some(range) = module.global_variables.get_span(handle).to_range() {
-            let src = &source[range.start..range.end];
-            if let Some(name) = &var.name {
-                let start = range.start + src.find(name).unwrap();
-                let modifiers = match var.space {
-                    AddressSpace::Handle | AddressSpace::PushConstant | AddressSpace::Uniform => {
-                        TokenModifiers::READONLY
-                    }
-                    AddressSpace::Storage { access } if !access.contains(StorageAccess::STORE) => {
-                        TokenModifiers::READONLY
-                    }
-                    _ => TokenModifiers::empty(),
-                };
-                tokens.push(Token {
-                    offset: start,
-                    length: name.len(),
-                    ty: TokenType::Variable,
-                    modifiers: modifiers | TokenModifiers::STATIC,
-                });
-            }
+/// Tokens overlapping `range` of the current source of a document, with names classified by the
+/// symbols the index of its last module resolved them to.
+///
+/// The module may be from before the latest edits, so names are looked up where they were when it
+/// was built. Names that were edited since don't refer to its symbols anymore.
+fn module_tokens(
+    cached: &CachedModule,
+    source: &str,
+    tokens: &[lexer::Token],
+    range: Range<usize>,
+) -> Vec<Token> {
+    let occurrences = &cached.index.occurrences;
+    let mut next = 0;
+    lexical_tokens(source, tokens, range, |index| {
+        let range = &tokens[index].range;
+        let old = cached.edits.unmap(range.start)?;
+        // names are classified in source order, which is the order of the occurrences
+        next += occurrences[next..].partition_point(|o| o.range.start < old);
+        let occurrence = occurrences.get(next).filter(|o| o.range.start == old)?;
+        if cached.edits.map_range(occurrence.range.clone()).as_ref() != Some(range) {
+            return None;
         }
-    }
+        let (ty, modifiers) = symbol_token(&cached.module, occurrence.symbol?)?;
+        if occurrence.is_declaration {
+            return Some((ty, modifiers | TokenModifiers::DECLARATION));
+        }
+        Some((ty, modifiers))
+    })
+}
 
-    let get_expression_token = |range: Range<usize>,
-                                expr: &Expression,
-                                fun: Option<&Function>|
-     -> Option<Token> {
-        let offset = range.start;
-        let length = range.end - range.start;
-        match expr {
-            Expression::Constant(_) => Some(Token {
-                offset,
-                length,
-                ty: TokenType::Variable,
-                modifiers: TokenModifiers::READONLY,
-            }),
-            Expression::FunctionArgument(_) => Some(Token {
-                offset,
-                length,
-                ty: TokenType::Parameter,
-                modifiers: TokenModifiers::empty(),
-            }),
-            Expression::GlobalVariable(global) => {
-                let var = module.global_variables.try_get(*global).unwrap();
-                let modifiers = match var.space {
-                    AddressSpace::Handle | AddressSpace::PushConstant | AddressSpace::Uniform => {
-                        TokenModifiers::READONLY
-                    }
-                    AddressSpace::Storage { access } if !access.contains(StorageAccess::STORE) => {
-                        TokenModifiers::READONLY
-                    }
-                    _ => TokenModifiers::empty(),
-                };
-                Some(Token {
-                    offset,
-                    length,
-                    ty: TokenType::Variable,
-                    modifiers: modifiers | TokenModifiers::STATIC,
-                })
-            }
-            Expression::Literal(_) => Some(Token {
-                offset,
-                length,
-                ty: TokenType::Number,
-                modifiers: TokenModifiers::empty(),
-            }),
-            Expression::LocalVariable(var) => {
-                let span = fun.unwrap().local_variables.get_span(*var);
-                st.log(
-                    MessageType::LOG,
-                    format!("Local variable found: {range:?} {span:?}").as_str(),
-                );
-                Some(Token {
-                    offset,
-                    length,
-                    ty: TokenType::Variable,
-                    modifiers: TokenModifiers::empty(),
-                })
-            }
-            Expression::CallResult(fun) => {
-                let fun = module.functions.try_get(*fun).unwrap();
-                let name = fun.name.as_ref().unwrap();
-                let src = &source[range.start..range.end];
-                let start = range.start + src.find(name).unwrap();
-                Some(Token {
-                    offset: start,
-                    length: name.len(),
-                    ty: TokenType::Function,
-                    modifiers: TokenModifiers::empty(),
-                })
-            }
-            _ => None,
+fn symbol_token(module: &Module, symbol: Symbol) -> Option<(TokenType, TokenModifiers)> {
+    Some(match symbol {
+        Symbol::Function(_) | Symbol::EntryPoint(_) => {
+            (TokenType::Function, TokenModifiers::empty())
         }
+        Symbol::Type(handle) => ((&module.types[handle]).into(), TokenModifiers::empty()),
+        Symbol::Member(..) => (TokenType::Property, TokenModifiers::empty()),
+        Symbol::Global(handle) => (
+            TokenType::Variable,
+            global_modifiers(&module.global_variables[handle]),
+        ),
+        Symbol::Constant(_) => (TokenType::Variable, TokenModifiers::READONLY),
+        Symbol::Argument(..) => (TokenType::Parameter, TokenModifiers::empty()),
+        Symbol::Local(..) => (TokenType::Variable, TokenModifiers::empty()),
+        Symbol::Let(..) => (TokenType::Variable, TokenModifiers::READONLY),
+        Symbol::Expression(..) => return None,
+    })
+}
+
+fn global_modifiers(var: &GlobalVariable) -> TokenModifiers {
+    let readonly = match var.space {
+        AddressSpace::Handle | AddressSpace::PushConstant | AddressSpace::Uniform => true,
+        AddressSpace::Storage { access } => !access.contains(StorageAccess::STORE),
+        _ => false,
     };
+    if readonly {
+        TokenModifiers::STATIC | TokenModifiers::READONLY
+    } else {
+        TokenModifiers::STATIC
+    }
+}
 
-    for (handle, expr) in module.const_expressions.iter() {
-        if let Some(range) = module.const_expressions.get_span(handle).to_range() {
-            if let Some(token) = get_expression_token(range, expr, None) {
-                tokens.push(token);
-            }
+/// Tokens overlapping `range` of a document without a module, with names classified by the
+/// declarations in its syntax tree.
+fn syntactic_tokens(tree: &SyntaxTree, source: &str, range: Range<usize>) -> Vec<Token> {
+    let declarations = tree.declarations(source);
+    let members: Vec<_> = declarations
+        .iter()
+        .filter(|d| d.kind == DeclarationKind::Struct)
+        .flat_map(|d| &d.children)
+        .collect();
+
+    lexical_tokens(source, &tree.tokens, range, |index| {
+        let token = &tree.tokens[index];
+        let (name, offset) = (token.text(source), token.range.start);
+        let after_dot = prev_significant(&tree.tokens, index)
+            .is_some_and(|prev| tree.tokens[prev].is_punct(source, "."));
+        if after_dot {
+            // without types this can only guess that it's not a swizzle
+            return members
+                .iter()
+                .any(|member| member.name == name)
+                .then_some((TokenType::Property, TokenModifiers::empty()));
         }
-    }
 
-    for (handle, fun) in module.functions.iter() {
-        if let Some(range) = module.functions.get_span(handle).to_range() {
-            if let Some(name) = &fun.name {
-                let src = &source[range.start..range.end];
-                let start = range.start + src.find(name).unwrap();
-                tokens.push(Token {
-                    offset: start,
-                    length: name.len(),
-                    ty: TokenType::Function,
-                    modifiers: TokenModifiers::empty(),
-                });
+        // arguments and locals declared before the name in the same function shadow the
+        // module-scope declarations
+        let function = declarations
+            .iter()
+            .find(|d| matches!(d.kind, DeclarationKind::Function(_)) && d.range.contains(&offset));
+        let local = function.and_then(|function| {
+            function.children.iter().rev().find(|child| {
+                child.name == name
+                    && (child.kind == DeclarationKind::Argument || child.name_range.start <= offset)
+            })
+        });
+        let declaration = local
+            .or_else(|| declarations.iter().find(|d| d.name == name))
+            .or_else(|| {
+                members
+                    .iter()
+                    .copied()
+                    .find(|m| m.name_range == token.range)
+            })?;
+
+        let (ty, modifiers) = match declaration.kind {
+            DeclarationKind::Function(_) => (TokenType::Function, TokenModifiers::empty()),
+            DeclarationKind::Struct => (TokenType::Struct, TokenModifiers::empty()),
+            DeclarationKind::Alias => (TokenType::Type, TokenModifiers::empty()),
+            DeclarationKind::Member => (TokenType::Property, TokenModifiers::empty()),
+            DeclarationKind::Argument => (TokenType::Parameter, TokenModifiers::empty()),
+            DeclarationKind::Local => (TokenType::Variable, TokenModifiers::empty()),
+            DeclarationKind::Constant | DeclarationKind::Override => {
+                (TokenType::Variable, TokenModifiers::READONLY)
             }
+            DeclarationKind::Global => (TokenType::Variable, TokenModifiers::STATIC),
+        };
+        if declaration.name_range == token.range {
+            return Some((ty, modifiers | TokenModifiers::DECLARATION));
         }
-        for (handle, expr) in fun.expressions.iter() {
-            if let Some(range) = fun.expressions.get_span(handle).to_range() {
-                st.log(
-                    MessageType::LOG,
-                    format!("Expression found: {range:?} {expr:?}").as_str(),
-                );
-                if let Some(token) = get_expression_token(range, expr, Some(fun)) {
-                    tokens.push(token);
+        Some((ty, modifiers))
+    })
+}
+
+/// Tokens for the attributes, keywords, numbers, and names overlapping `range` of a source,
+/// outside of preprocessor directives.
+///
+/// `name_token` classifies the identifier at a token index by what it's declared as, and is called
+/// in source order. Declared names shadow predeclared types, functions, and enumerants.
+fn lexical_tokens(
+    source: &str,
+    tokens: &[lexer::Token],
+    range: Range<usize>,
+    mut name_token: impl FnMut(usize) -> Option<(TokenType, TokenModifiers)>,
+) -> Vec<Token> {
+    let directives = syntax::directives(source, tokens);
+    let first = tokens.partition_point(|token| token.range.end <= range.start);
+    let last = tokens.partition_point(|token| token.range.start < range.end);
+    let mut output = Vec::new();
+    let mut attribute_name = None;
+    for (i, token) in tokens.iter().enumerate().take(last).skip(first) {
+        if attribute_name == Some(i)
+            || directives
+                .iter()
+                .any(|d| d.start <= token.range.start && token.range.end <= d.end)
+        {
+            continue;
+        }
+        let text = token.text(source);
+        let next = next_significant(tokens, i);
+        let (ty, modifiers) = match token.kind {
+            TokenKind::Number => (TokenType::Number, TokenModifiers::empty()),
+            // the `@` and the name of an attribute are one token, unless something's between them
+            TokenKind::Punct if text == "@" => {
+                let Some(name) = next.filter(|&n| tokens[n].kind == TokenKind::Ident) else {
+                    continue;
+                };
+                attribute_name = Some(name);
+                let decorator = |range: Range<usize>| Token {
+                    offset: range.start,
+                    length: range.len(),
+                    ty: TokenType::Decorator,
+                    modifiers: TokenModifiers::empty(),
+                };
+                if tokens[name].range.start == token.range.end {
+                    output.push(decorator(token.range.start..tokens[name].range.end));
+                } else {
+                    output.push(decorator(token.range.clone()));
+                    output.push(decorator(tokens[name].range.clone()));
                 }
+                continue;
             }
-        }
+            // `virtual` is a naga_oil function modifier
+            TokenKind::Ident if builtins::KEYWORDS.contains(&text) || text == "virtual" => {
+                (TokenType::Keyword, TokenModifiers::empty())
+            }
+            TokenKind::Ident => match name_token(i) {
+                Some(classified) => classified,
+                None if next.is_some_and(|n| tokens[n].is_punct(source, "::")) => {
+                    (TokenType::Namespace, TokenModifiers::empty())
+                }
+                None if builtins::TYPES.contains(&text) => {
+                    (TokenType::Type, TokenModifiers::DEFAULT_LIBRARY)
+                }
+                None if builtins::function(text).is_some() => {
+                    (TokenType::Function, TokenModifiers::DEFAULT_LIBRARY)
+                }
+                None if builtins::ENUMERANTS.contains(&text) => {
+                    (TokenType::EnumMember, TokenModifiers::DEFAULT_LIBRARY)
+                }
+                None => continue,
+            },
+            _ => continue,
+        };
+        output.push(Token {
+            offset: token.range.start,
+            length: token.range.len(),
+            ty,
+            modifiers,
+        });
     }
+    output
+}
 
-    // Calculate the relative positions of the tokens
+/// Encode tokens as positions relative to the previous token.
+///
+/// Tokens can't span lines, so any that would are left out.
+fn encode_tokens(index: &LineIndex, mut tokens: Vec<Token>) -> Vec<SemanticToken> {
     tokens.sort_by_key(|token| token.offset);
 
     let mut semantic_tokens = Vec::new();
     let mut last_pos = Position::new(0, 0);
     for token in &tokens {
-        let pos = calc_position(&source, token.offset);
+        let pos = index.position(token.offset);
+        let end = index.position(token.offset + token.length);
+        if end.line != pos.line {
+            continue;
+        }
         semantic_tokens.push(SemanticToken {
             delta_line: pos.line - last_pos.line,
             delta_start: if pos.line == last_pos.line {
@@ -289,15 +506,132 @@ pub fn semantic_tokens_full(
             } else {
                 pos.character
             },
-            length: token.length as u32,
+            length: end.character - pos.character,
             token_type: (&token.ty).into(),
             token_modifiers_bitset: token.modifiers.bits(),
         });
         last_pos = pos;
     }
 
-    ready(Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
-        result_id: None,
-        data: semantic_tokens,
-    }))))
+    semantic_tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_index::PositionEncoding;
+
+    fn token(delta_line: u32, length: u32) -> SemanticToken {
+        SemanticToken {
+            delta_line,
+            delta_start: 0,
+            length,
+            token_type: 0,
+            token_modifiers_bitset: 0,
+        }
+    }
+
+    #[test]
+    fn unchanged_tokens_have_no_edits() {
+        let tokens = [token(0, 1), token(1, 2)];
+        assert!(token_edits(&tokens, &tokens).is_empty());
+        assert!(token_edits(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn changed_tokens_are_replaced() {
+        let old = [token(0, 1), token(1, 2), token(1, 3)];
+        let new = [token(0, 1), token(1, 4), token(2, 4), token(1, 3)];
+        let edits = token_edits(&old, &new);
+        assert_eq!(
+            edits,
+            [SemanticTokensEdit {
+                start: 5,
+                delete_count: 5,
+                data: Some(vec![token(1, 4), token(2, 4)]),
+            }]
+        );
+    }
+
+    #[test]
+    fn removed_tokens_are_deleted() {
+        let old = [token(0, 1), token(1, 2), token(1, 3)];
+        let new = [token(0, 1)];
+        let edits = token_edits(&old, &new);
+        assert_eq!(
+            edits,
+            [SemanticTokensEdit {
+                start: 5,
+                delete_count: 10,
+                data: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn added_tokens_are_inserted() {
+        let old = [token(0, 1)];
+        let new = [token(0, 1), token(0, 1)];
+        let edits = token_edits(&old, &new);
+        assert_eq!(
+            edits,
+            [SemanticTokensEdit {
+                start: 5,
+                delete_count: 0,
+                data: Some(vec![token(0, 1)]),
+            }]
+        );
+    }
+
+    fn token_texts<'a>(source: &'a str, tokens: &[Token]) -> Vec<&'a str> {
+        tokens
+            .iter()
+            .map(|token| &source[token.offset..token.offset + token.length])
+            .collect()
+    }
+
+    #[test]
+    fn attributes_split_from_their_name_are_two_tokens() {
+        let source = "@vertex fn a() {}\n@\n  fragment fn b() {}\n@ /* x */ compute fn c() {}\n";
+        let tree = SyntaxTree::parse(source);
+        let tokens = syntactic_tokens(&tree, source, 0..source.len());
+        let decorators: Vec<_> = tokens
+            .into_iter()
+            .filter(|token| matches!(token.ty, TokenType::Decorator))
+            .collect();
+        assert_eq!(
+            token_texts(source, &decorators),
+            ["@vertex", "@", "fragment", "@", "compute"]
+        );
+
+        let index = LineIndex::new(source, PositionEncoding::Utf16);
+        let encoded = encode_tokens(&index, decorators);
+        assert_eq!(encoded.len(), 5);
+        assert_eq!((encoded[2].delta_line, encoded[2].length), (1, 8));
+    }
+
+    #[test]
+    fn tokens_spanning_lines_are_left_out() {
+        let source = "a\nb\n";
+        let index = LineIndex::new(source, PositionEncoding::Utf16);
+        let token = |offset, length| Token {
+            offset,
+            length,
+            ty: TokenType::Comment,
+            modifiers: TokenModifiers::empty(),
+        };
+        let encoded = encode_tokens(&index, vec![token(0, 3), token(2, 1)]);
+        assert_eq!(encoded.len(), 1);
+        assert_eq!((encoded[0].delta_line, encoded[0].length), (1, 1));
+    }
+
+    #[test]
+    fn only_tokens_in_the_range_are_classified() {
+        let source = "fn a() {}\nfn b() {}\nfn c() {}\n";
+        let tree = SyntaxTree::parse(source);
+        let line = source.find("fn b").unwrap()..source.find("fn c").unwrap();
+        let tokens = syntactic_tokens(&tree, source, line.clone());
+        assert_eq!(token_texts(source, &tokens), ["fn", "b"]);
+        assert!(tokens.iter().all(|token| line.contains(&token.offset)));
+    }
 }
@@ -0,0 +1,233 @@
+use std::{
+    future::{ready, Future},
+    ops::Range,
+};
+
+use lsp_types::{
+    request::SignatureHelpRequest, ParameterInformation, ParameterLabel, Position, SignatureHelp,
+    SignatureHelpOptions, SignatureHelpParams, SignatureInformation, Url,
+};
+
+use crate::{
+    builtins,
+    document::normalize_uri,
+    lexer::{prev_significant, tokenize, Token, TokenKind},
+    server::{Result, WgslServerState},
+    symbols::{function_by_name, module_item_name, path_at, resolve_import_path},
+    syntax,
+};
+
+use super::hover::function_signature;
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#signatureHelpOptions
+pub fn signature_help_capability() -> SignatureHelpOptions {
+    SignatureHelpOptions {
+        trigger_characters: Some(vec!["(".to_owned(), ",".to_owned()]),
+        retrigger_characters: None,
+        work_done_progress_options: Default::default(),
+    }
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_signatureHelp
+pub fn signature_help(
+    st: &mut WgslServerState,
+    params: SignatureHelpParams,
+) -> impl Future<Output = Result<SignatureHelpRequest>> {
+    let uri = normalize_uri(params.text_document_position_params.text_document.uri);
+    st.ensure_module(&uri);
+    ready(Ok(st.signature_help(
+        &uri,
+        params.text_document_position_params.position,
+    )))
+}
+
+impl WgslServerState {
+    /// Signatures of the function called around a position, with the argument under the cursor active.
+    ///
+    /// User functions are looked up in the last module built from the document, even if it's out
+    /// of date, since calls are rarely complete while they're being typed.
+    fn signature_help(&self, uri: &Url, position: Position) -> Option<SignatureHelp> {
+        let source = self.open_documents.get(uri)?.source();
+        let offset = self.line_index(&source).offset(position);
+        let tokens = tokenize(&source);
+        let (callee, active_parameter) = call_at(&source, &tokens, offset)?;
+
+        let user_function = self.cached_modules.get(uri).and_then(|cached| {
+            let imports = syntax::imports(&source, &tokens);
+            let full_path = resolve_import_path(&imports, &callee);
+            let function = function_by_name(&cached.module, &module_item_name(&full_path))?;
+            Some(function_signature(&cached.module, function))
+        });
+        let labels = match user_function {
+            Some(signature) => vec![signature],
+            None => builtins::function(&callee)?
+                .overloads
+                .iter()
+                .map(|overload| overload.to_string())
+                .collect(),
+        };
+
+        let signatures: Vec<_> = labels
+            .into_iter()
+            .map(|label| {
+                // offsets in the label are encoded like positions
+                let index = self.line_index(&label);
+                let parameters = parameter_ranges(&label)
+                    .into_iter()
+                    .map(|range| ParameterInformation {
+                        label: ParameterLabel::LabelOffsets([
+                            index.position(range.start).character,
+                            index.position(range.end).character,
+                        ]),
+                        documentation: None,
+                    })
+                    .collect();
+                SignatureInformation {
+                    label,
+                    documentation: None,
+                    parameters: Some(parameters),
+                    active_parameter: None,
+                }
+            })
+            .collect();
+        // pick the first overload that takes enough arguments
+        let active_signature = signatures
+            .iter()
+            .position(|signature| {
+                signature
+                    .parameters
+                    .as_ref()
+                    .is_some_and(|p| p.len() > active_parameter)
+            })
+            .unwrap_or(0);
+
+        Some(SignatureHelp {
+            signatures,
+            active_signature: Some(active_signature as u32),
+            active_parameter: Some(active_parameter as u32),
+        })
+    }
+}
+
+/// Find the call whose argument list contains `offset`, returning the path of the function
+/// called and the index of the argument the cursor is in.
+fn call_at(source: &str, tokens: &[Token], offset: usize) -> Option<(String, usize)> {
+    let before = tokens.partition_point(|t| t.range.end <= offset);
+    let mut depth = 0;
+    let mut commas = 0;
+    for index in (0..before).rev() {
+        let token = &tokens[index];
+        if token.kind != TokenKind::Punct {
+            continue;
+        }
+        match token.text(source) {
+            ")" | "]" => depth += 1,
+            "(" | "[" if depth > 0 => depth -= 1,
+            "(" => return Some((callee(source, tokens, index)?, commas)),
+            "," if depth == 0 => commas += 1,
+            // arguments can't contain statements, so we're not in a call
+            "[" | ";" | "{" | "}" => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The path of the function called with the `(` at token index `open`, skipping a template
+/// list like the one in `bitcast<u32>(x)`.
+fn callee(source: &str, tokens: &[Token], open: usize) -> Option<String> {
+    let mut index = prev_significant(tokens, open)?;
+    if tokens[index].is_punct(source, ">") {
+        let mut depth = 0;
+        let less = (0..=index).rev().find(|&i| {
+            if tokens[i].is_punct(source, ">") {
+                depth += 1;
+            } else if tokens[i].is_punct(source, "<") {
+                depth -= 1;
+            }
+            depth == 0
+        })?;
+        index = prev_significant(tokens, less)?;
+    }
+    if tokens[index].kind != TokenKind::Ident {
+        return None;
+    }
+    // the parameter list of a declaration isn't a call
+    if prev_significant(tokens, index).is_some_and(|i| tokens[i].is_ident(source, "fn")) {
+        return None;
+    }
+    Some(path_at(source, tokens, tokens[index].range.end)?.text())
+}
+
+/// Byte ranges of the parameters in a signature like `fn f(a: T, b: ptr<function, T>) -> T`.
+fn parameter_ranges(signature: &str) -> Vec<Range<usize>> {
+    let Some(open) = signature.find('(') else {
+        return Vec::new();
+    };
+    let mut ranges = Vec::new();
+    let mut push = |range: Range<usize>| {
+        let text = &signature[range.clone()];
+        let start = range.start + (text.len() - text.trim_start().len());
+        let end = range.end - (text.len() - text.trim_end().len());
+        if start < end {
+            ranges.push(start..end);
+        }
+    };
+    let mut depth = 0;
+    let mut start = open + 1;
+    for (i, c) in signature.char_indices().skip_while(|&(i, _)| i <= open) {
+        match c {
+            '<' | '(' => depth += 1,
+            '>' => depth -= 1,
+            ')' if depth == 0 => {
+                push(start..i);
+                break;
+            }
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                push(start..i);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameters(signature: &str) -> Vec<&str> {
+        parameter_ranges(signature)
+            .into_iter()
+            .map(|range| &signature[range])
+            .collect()
+    }
+
+    #[test]
+    fn splits_parameters_at_top_level_commas() {
+        assert_eq!(
+            parameters("fn f(a: f32, b: vec2<f32>) -> f32"),
+            ["a: f32", "b: vec2<f32>"]
+        );
+    }
+
+    #[test]
+    fn ignores_commas_in_templates_and_parentheses() {
+        assert_eq!(
+            parameters("fn f(a: array<vec4<f32>, 4>, b: mat2x2<f32>)"),
+            ["a: array<vec4<f32>, 4>", "b: mat2x2<f32>"]
+        );
+        assert_eq!(
+            parameters("fn textureSample(t: texture_2d<f32>, s: sampler, coords: vec2<f32>)"),
+            ["t: texture_2d<f32>", "s: sampler", "coords: vec2<f32>"]
+        );
+    }
+
+    #[test]
+    fn no_parameters() {
+        assert!(parameters("fn f() -> f32").is_empty());
+        assert!(parameters("f32").is_empty());
+    }
+}
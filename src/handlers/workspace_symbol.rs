@@ -0,0 +1,78 @@
+use std::future::{ready, Future};
+
+use lsp_types::{
+    request::WorkspaceSymbolRequest, Location, OneOf, SymbolKind, Url, WorkspaceSymbol,
+    WorkspaceSymbolOptions, WorkspaceSymbolParams, WorkspaceSymbolResponse,
+};
+
+use crate::{
+    fuzzy::fuzzy_score,
+    server::{Result, WgslServerState},
+    syntax::{self, DeclarationKind},
+};
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspaceSymbolOptions
+pub fn workspace_symbol_capability() -> OneOf<bool, WorkspaceSymbolOptions> {
+    OneOf::Left(true)
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspace_symbol
+pub fn workspace_symbol(
+    st: &mut WgslServerState,
+    params: WorkspaceSymbolParams,
+) -> impl Future<Output = Result<WorkspaceSymbolRequest>> {
+    let mut matches: Vec<_> = st
+        .workspace_symbols
+        .values()
+        .flatten()
+        .filter_map(|symbol| Some((fuzzy_score(&params.query, &symbol.name)?, symbol)))
+        .collect();
+    matches.sort_by(|(a_score, a), (b_score, b)| b_score.cmp(a_score).then(a.name.cmp(&b.name)));
+
+    ready(Ok(Some(WorkspaceSymbolResponse::Nested(
+        matches
+            .into_iter()
+            .map(|(_, symbol)| symbol.clone())
+            .collect(),
+    ))))
+}
+
+impl WgslServerState {
+    /// Rescan a document's module-scope declarations for workspace symbol search.
+    pub fn update_workspace_symbols(&mut self, uri: &Url) {
+        let Some(document) = self.open_documents.get(uri) else {
+            self.workspace_symbols.remove(uri);
+            return;
+        };
+        let (source, tree) = (document.source(), document.syntax_tree());
+        let container_name =
+            syntax::define_import_path(&source, &tree.tokens).map(|(name, _)| name);
+        let index = self.line_index(&source);
+
+        let symbols = tree
+            .declarations(&source)
+            .into_iter()
+            .map(|declaration| {
+                let kind = match declaration.kind {
+                    DeclarationKind::Function(_) => SymbolKind::FUNCTION,
+                    DeclarationKind::Struct => SymbolKind::STRUCT,
+                    DeclarationKind::Constant | DeclarationKind::Override => SymbolKind::CONSTANT,
+                    DeclarationKind::Alias => SymbolKind::TYPE_PARAMETER,
+                    _ => SymbolKind::VARIABLE,
+                };
+                WorkspaceSymbol {
+                    name: declaration.name,
+                    kind,
+                    tags: None,
+                    container_name: container_name.clone(),
+                    location: OneOf::Left(Location::new(
+                        uri.clone(),
+                        index.range(declaration.name_range.start, declaration.name_range.end),
+                    )),
+                    data: None,
+                }
+            })
+            .collect();
+        self.workspace_symbols.insert(uri.clone(), symbols);
+    }
+}
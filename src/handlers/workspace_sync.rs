@@ -0,0 +1,196 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::ControlFlow,
+};
+
+use lsp_types::{
+    notification::PublishDiagnostics, DidChangeWatchedFilesParams, DidChangeWorkspaceFoldersParams,
+    FileChangeType, MessageType, OneOf, PublishDiagnosticsParams, Url,
+    WorkspaceFoldersServerCapabilities, WorkspaceServerCapabilities,
+};
+
+use crate::{
+    document::{normalize_uri, OpenDocument},
+    project::Project,
+    server::{NotifyResult, WgslServerState},
+    validate::{validate_dependents, validate_document},
+};
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspaceFoldersServerCapabilities
+pub fn workspace_capability() -> WorkspaceServerCapabilities {
+    WorkspaceServerCapabilities {
+        workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+            supported: Some(true),
+            change_notifications: Some(OneOf::Left(true)),
+        }),
+        file_operations: None,
+    }
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspace_didChangeWatchedFiles
+pub fn did_change_watched_files(
+    st: &mut WgslServerState,
+    params: DidChangeWatchedFilesParams,
+) -> NotifyResult {
+    // changed modules by the project they're in
+    let mut changed_modules: HashMap<usize, HashSet<String>> = HashMap::new();
+    for change in params.changes {
+        let uri = normalize_uri(change.uri);
+        let config_project = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| st.config_file_project(&path));
+        if let Some(project) = config_project {
+            if let ControlFlow::Break(result) = st.reload_config_file(project) {
+                return ControlFlow::Break(result);
+            }
+            continue;
+        }
+        // the client's copy of a document takes precedence over the file on disk
+        if let Some(OpenDocument::ClientOwned(..)) = st.open_documents.get(&uri) {
+            continue;
+        }
+
+        let project = st.project_index(&uri);
+        let modules = changed_modules.entry(project).or_default();
+        modules.extend(st.unregister_document(&uri));
+        match change.typ {
+            FileChangeType::CREATED | FileChangeType::CHANGED => {
+                if let ControlFlow::Break(result) = st.server_open(uri.clone()) {
+                    return ControlFlow::Break(result);
+                }
+                modules.extend(st.registered_modules(&uri));
+            }
+            FileChangeType::DELETED => {
+                st.open_documents.remove(&uri);
+                st.update_workspace_symbols(&uri);
+                if let ControlFlow::Break(result) =
+                    st.log(MessageType::INFO, &format!("Removed document: {}", uri))
+                {
+                    return ControlFlow::Break(result);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !st.should_validate {
+        return ControlFlow::Continue(());
+    }
+    for (project, modules) in changed_modules {
+        let modules: Vec<String> = modules.into_iter().collect();
+        if let ControlFlow::Break(result) = validate_dependents(st, project, &modules) {
+            return ControlFlow::Break(result);
+        }
+    }
+    ControlFlow::Continue(())
+}
+
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspace_didChangeWorkspaceFolders
+///
+/// Documents are moved to the projects they belong to after the change, and the documents open in
+/// the client that moved are re-validated.
+pub fn did_change_workspace_folders(
+    st: &mut WgslServerState,
+    params: DidChangeWorkspaceFoldersParams,
+) -> NotifyResult {
+    for folder in params.event.removed {
+        let Some(project) = folder
+            .uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| st.folder_project_index(&path))
+        else {
+            continue;
+        };
+        // files on disk are only loaded while they're in the workspace
+        let closed: Vec<Url> = st
+            .open_documents
+            .iter()
+            .filter(|(_, document)| matches!(document, OpenDocument::ServerOwned(..)))
+            .map(|(uri, _)| uri.clone())
+            .filter(|uri| st.project_index(uri) == project)
+            .collect();
+        for uri in closed {
+            if let ControlFlow::Break(result) = st.server_close(&uri) {
+                return ControlFlow::Break(result);
+            }
+        }
+        let removed = st.projects.remove(project);
+        if let Some(config_file) = removed.config_file {
+            if let ControlFlow::Break(result) =
+                st.notify::<PublishDiagnostics>(PublishDiagnosticsParams {
+                    uri: config_file.uri,
+                    diagnostics: Vec::new(),
+                    version: None,
+                })
+            {
+                return ControlFlow::Break(result);
+            }
+        }
+        if let ControlFlow::Break(result) = st.log(
+            MessageType::INFO,
+            &format!("Removed workspace folder: {}", folder.uri),
+        ) {
+            return ControlFlow::Break(result);
+        }
+    }
+
+    let mut added = Vec::new();
+    for folder in params.event.added {
+        let Ok(path) = folder.uri.to_file_path() else {
+            continue;
+        };
+        if st.folder_project_index(&path).is_some() {
+            continue;
+        }
+        st.projects
+            .push(Project::new(Some(path), st.settings.clone()));
+        let project = st.projects.len() - 1;
+        st.load_config_file(project);
+        added.push(project);
+        if let ControlFlow::Break(result) = st.log(
+            MessageType::INFO,
+            &format!("Added workspace folder: {}", folder.uri),
+        ) {
+            return ControlFlow::Break(result);
+        }
+    }
+    // this also applies the config files of the added folders
+    if let ControlFlow::Break(result) = st.reload_settings() {
+        return ControlFlow::Break(result);
+    }
+    let moved = st.reassign_documents();
+    for project in added {
+        if let ControlFlow::Break(result) = st.load_project_files(project) {
+            return ControlFlow::Break(result);
+        }
+        if let ControlFlow::Break(result) = st.publish_config_diagnostics(project, None) {
+            return ControlFlow::Break(result);
+        }
+    }
+
+    if !st.should_validate {
+        return ControlFlow::Continue(());
+    }
+    for uri in moved {
+        if let Some(OpenDocument::ClientOwned(..)) = st.open_documents.get(&uri) {
+            if let ControlFlow::Break(result) = validate_document(st, uri) {
+                return ControlFlow::Break(result);
+            }
+        }
+    }
+    ControlFlow::Continue(())
+}
+
+impl WgslServerState {
+    /// Names the document at `uri` is registered under in the module lookup of its project.
+    fn registered_modules(&self, uri: &Url) -> Vec<String> {
+        self.project(uri)
+            .module_lookup
+            .iter()
+            .filter(|(_, u)| *u == uri)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
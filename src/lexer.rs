@@ -0,0 +1,156 @@
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// Identifiers and keywords, which WGSL doesn't distinguish lexically.
+    Ident,
+    Number,
+    /// Quoted strings, which only appear in naga_oil import paths.
+    String,
+    /// Punctuation. `::` and `->` are single tokens, everything else is one character.
+    Punct,
+    Comment,
+    Whitespace,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub range: Range<usize>,
+}
+
+impl Token {
+    pub fn text<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.range.clone()]
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        matches!(self.kind, TokenKind::Comment | TokenKind::Whitespace)
+    }
+
+    pub fn is_punct(&self, source: &str, punct: &str) -> bool {
+        self.kind == TokenKind::Punct && self.text(source) == punct
+    }
+
+    pub fn is_ident(&self, source: &str, ident: &str) -> bool {
+        self.kind == TokenKind::Ident && self.text(source) == ident
+    }
+}
+
+/// Split WGSL source (including naga_oil directives) into tokens.
+///
+/// This is lossless, every byte of the source belongs to exactly one token, so it's safe to use on
+/// code that doesn't parse.
+pub fn tokenize(source: &str) -> Vec<Token> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let start = pos;
+        let c = source[pos..].chars().next().unwrap();
+        let kind = if c.is_whitespace() {
+            pos += source[pos..]
+                .find(|c: char| !c.is_whitespace())
+                .unwrap_or(source.len() - pos);
+            TokenKind::Whitespace
+        } else if source[pos..].starts_with("//") {
+            pos += source[pos..].find('\n').unwrap_or(source.len() - pos);
+            TokenKind::Comment
+        } else if source[pos..].starts_with("/*") {
+            // block comments nest in WGSL
+            let mut depth = 0;
+            while pos < bytes.len() {
+                if source[pos..].starts_with("/*") {
+                    depth += 1;
+                    pos += 2;
+                } else if source[pos..].starts_with("*/") {
+                    depth -= 1;
+                    pos += 2;
+                    if depth == 0 {
+                        break;
+                    }
+                } else {
+                    pos += source[pos..].chars().next().unwrap().len_utf8();
+                }
+            }
+            TokenKind::Comment
+        } else if is_ident_start(c) {
+            pos += source[pos..]
+                .find(|c: char| !is_ident_continue(c))
+                .unwrap_or(source.len() - pos);
+            TokenKind::Ident
+        } else if c.is_ascii_digit()
+            || (c == '.' && bytes.get(pos + 1).is_some_and(u8::is_ascii_digit))
+        {
+            let hex = source[pos..].starts_with("0x") || source[pos..].starts_with("0X");
+            pos += 1;
+            while pos < bytes.len() {
+                let b = bytes[pos];
+                let exponent_sign = (b == b'+' || b == b'-')
+                    && match bytes[pos - 1] {
+                        b'e' | b'E' => !hex,
+                        b'p' | b'P' => hex,
+                        _ => false,
+                    };
+                if b.is_ascii_alphanumeric() || b == b'_' || b == b'.' || exponent_sign {
+                    pos += 1;
+                } else {
+                    break;
+                }
+            }
+            TokenKind::Number
+        } else if c == '"' {
+            pos += 1;
+            pos += source[pos..]
+                .find(['"', '\n'])
+                .map(|i| i + usize::from(source[pos + i..].starts_with('"')))
+                .unwrap_or(source.len() - pos);
+            TokenKind::String
+        } else {
+            if source[pos..].starts_with("::") || source[pos..].starts_with("->") {
+                pos += 2;
+            } else {
+                pos += c.len_utf8();
+            }
+            TokenKind::Punct
+        };
+        tokens.push(Token {
+            kind,
+            range: start..pos,
+        });
+    }
+
+    tokens
+}
+
+pub fn is_ident_start(c: char) -> bool {
+    c == '_' || c.is_alphabetic()
+}
+
+pub fn is_ident_continue(c: char) -> bool {
+    c == '_' || c.is_alphanumeric()
+}
+
+/// Index of the token containing `offset`, preferring the token that ends at `offset`
+/// if it's an identifier so that a cursor placed right after a name still resolves it.
+pub fn token_at(tokens: &[Token], offset: usize) -> Option<usize> {
+    let index = tokens.partition_point(|t| t.range.end <= offset);
+    if index > 0
+        && tokens[index - 1].range.end == offset
+        && tokens[index - 1].kind == TokenKind::Ident
+    {
+        return Some(index - 1);
+    }
+    (index < tokens.len() && tokens[index].range.start <= offset).then_some(index)
+}
+
+/// Index of the next non-trivia token after `index`.
+pub fn next_significant(tokens: &[Token], index: usize) -> Option<usize> {
+    (index + 1..tokens.len()).find(|&i| !tokens[i].is_trivia())
+}
+
+/// Index of the previous non-trivia token before `index`.
+pub fn prev_significant(tokens: &[Token], index: usize) -> Option<usize> {
+    (0..index).rev().find(|&i| !tokens[i].is_trivia())
+}
@@ -0,0 +1,184 @@
+use std::iter;
+
+use lsp_types::{Position, PositionEncodingKind, Range};
+
+use crate::server::WgslServerState;
+
+/// What the `character` of a [Position] counts, as negotiated with the client.
+/// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#positionEncodingKind
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PositionEncoding {
+    Utf8,
+    /// The default, which every client has to support.
+    #[default]
+    Utf16,
+}
+
+impl PositionEncoding {
+    /// Pick the first encoding the client offers that we support, or UTF-16 if there's none.
+    pub fn negotiate(offered: &[PositionEncodingKind]) -> Self {
+        offered
+            .iter()
+            .find_map(|kind| {
+                if *kind == PositionEncodingKind::UTF8 {
+                    Some(PositionEncoding::Utf8)
+                } else if *kind == PositionEncodingKind::UTF16 {
+                    Some(PositionEncoding::Utf16)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            PositionEncoding::Utf8 => PositionEncodingKind::UTF8,
+            PositionEncoding::Utf16 => PositionEncodingKind::UTF16,
+        }
+    }
+
+    fn units(self, c: char) -> u32 {
+        match self {
+            PositionEncoding::Utf8 => c.len_utf8() as u32,
+            PositionEncoding::Utf16 => c.len_utf16() as u32,
+        }
+    }
+}
+
+/// Converts between byte offsets in a source and LSP [Position]s in the negotiated encoding.
+pub struct LineIndex<'a> {
+    source: &'a str,
+    /// Byte offset of the start of every line.
+    line_starts: Vec<usize>,
+    encoding: PositionEncoding,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str, encoding: PositionEncoding) -> Self {
+        let line_starts = iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            source,
+            line_starts,
+            encoding,
+        }
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let mut offset = offset.min(self.source.len());
+        while !self.source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let character = self.source[self.line_starts[line]..offset]
+            .chars()
+            .map(|c| self.encoding.units(c))
+            .sum();
+        Position::new(line as u32, character)
+    }
+
+    /// The inverse of [LineIndex::position], clamped to the end of the line.
+    /// A position inside a character rounds up to the end of that character.
+    pub fn offset(&self, position: Position) -> usize {
+        let Some(&line_start) = self.line_starts.get(position.line as usize) else {
+            return self.source.len();
+        };
+        let line = &self.source[line_start..];
+        let line = &line[..line.find('\n').unwrap_or(line.len())];
+        let mut units = 0;
+        for (i, c) in line.char_indices() {
+            if units >= position.character {
+                return line_start + i;
+            }
+            units += self.encoding.units(c);
+        }
+        line_start + line.len()
+    }
+
+    pub fn range(&self, start: usize, end: usize) -> Range {
+        Range::new(self.position(start), self.position(end))
+    }
+}
+
+impl WgslServerState {
+    /// Index a source for converting positions in the encoding negotiated with the client.
+    pub fn line_index<'a>(&self, source: &'a str) -> LineIndex<'a> {
+        LineIndex::new(source, self.position_encoding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "let a = 1;\nlet é = \"😀\";\n";
+
+    #[test]
+    fn negotiates_the_first_supported_encoding() {
+        let offered = [
+            PositionEncodingKind::UTF32,
+            PositionEncodingKind::UTF8,
+            PositionEncodingKind::UTF16,
+        ];
+        assert_eq!(
+            PositionEncoding::negotiate(&offered),
+            PositionEncoding::Utf8
+        );
+        assert_eq!(
+            PositionEncoding::negotiate(&[PositionEncodingKind::UTF32]),
+            PositionEncoding::Utf16
+        );
+        assert_eq!(PositionEncoding::negotiate(&[]), PositionEncoding::Utf16);
+    }
+
+    #[test]
+    fn utf16_counts_code_units() {
+        let index = LineIndex::new(SOURCE, PositionEncoding::Utf16);
+        let emoji = SOURCE.find('😀').unwrap();
+        assert_eq!(index.position(emoji), Position::new(1, 9));
+        assert_eq!(
+            index.position(emoji + '😀'.len_utf8()),
+            Position::new(1, 11)
+        );
+        assert_eq!(index.offset(Position::new(1, 9)), emoji);
+        assert_eq!(index.offset(Position::new(1, 11)), emoji + '😀'.len_utf8());
+    }
+
+    #[test]
+    fn utf8_counts_bytes() {
+        let index = LineIndex::new(SOURCE, PositionEncoding::Utf8);
+        let emoji = SOURCE.find('😀').unwrap();
+        assert_eq!(index.position(emoji), Position::new(1, 10));
+        assert_eq!(index.offset(Position::new(1, 10)), emoji);
+    }
+
+    #[test]
+    fn positions_round_trip_at_char_boundaries() {
+        for encoding in [PositionEncoding::Utf8, PositionEncoding::Utf16] {
+            let index = LineIndex::new(SOURCE, encoding);
+            for (offset, _) in SOURCE.char_indices() {
+                assert_eq!(index.offset(index.position(offset)), offset);
+            }
+        }
+    }
+
+    #[test]
+    fn positions_inside_a_character() {
+        let index = LineIndex::new(SOURCE, PositionEncoding::Utf16);
+        let emoji = SOURCE.find('😀').unwrap();
+        // offsets round down to the start of the character
+        assert_eq!(index.position(emoji + 1), Position::new(1, 9));
+        // positions round up to the end of it
+        assert_eq!(index.offset(Position::new(1, 10)), emoji + '😀'.len_utf8());
+    }
+
+    #[test]
+    fn out_of_range_positions_are_clamped() {
+        let index = LineIndex::new(SOURCE, PositionEncoding::Utf16);
+        assert_eq!(index.offset(Position::new(0, 100)), "let a = 1;".len());
+        assert_eq!(index.offset(Position::new(5, 0)), SOURCE.len());
+        assert_eq!(index.position(SOURCE.len() + 10), Position::new(2, 0));
+    }
+}
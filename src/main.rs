@@ -6,10 +6,26 @@ use server::make_wgsl_router;
 use tower::ServiceBuilder;
 use tracing::Level;
 
+mod builtins;
+mod config_file;
+mod dependency_graph;
 mod document;
+mod edit_map;
+mod fuzzy;
+mod glob;
 mod handlers;
+mod lexer;
+mod line_index;
+mod project;
 mod server;
+mod settings;
+mod source_map;
+mod symbol_index;
+mod symbols;
+mod syntax;
+mod syntax_tree;
 mod validate;
+mod validation_worker;
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
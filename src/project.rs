@@ -0,0 +1,152 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use lsp_types::Url;
+use naga_oil::compose::Composer;
+
+use crate::{
+    config_file::ConfigFile, dependency_graph::DependencyGraph, server::WgslServerState,
+    settings::Settings,
+};
+
+/// The modules and settings of a workspace folder.
+///
+/// Every folder has its own composer and module lookup, so folders that declare the same
+/// `#define_import_path` don't overwrite each other.
+#[derive(Debug)]
+pub struct Project {
+    /// The workspace folder, or [None] for the project of documents outside every folder.
+    pub root: Option<PathBuf>,
+    /// Mapping of module names/paths to their URLs.
+    /// This will only contain either a path or a module name, not both.
+    pub module_lookup: HashMap<String, Url>,
+    /// Which documents import which modules, for re-validating importers after a change.
+    pub dependency_graph: DependencyGraph,
+    /// Non-validating composer for building modules.
+    pub composer: Composer,
+    /// Settings from the client with the folder's config file on top. The composer is rebuilt
+    /// whenever they change, and the validation worker validates with them.
+    pub settings: Settings,
+    /// The config file at the root of the folder, if it has one.
+    pub config_file: Option<ConfigFile>,
+    /// Whether the files of the folder and include paths were loaded. Until then, settings
+    /// changes don't load or unload files, so that they're only loaded once the settings are final.
+    pub files_loaded: bool,
+}
+
+impl Project {
+    pub fn new(root: Option<PathBuf>, settings: Settings) -> Self {
+        let capabilities = settings.capabilities.capabilities();
+        Self {
+            root,
+            module_lookup: HashMap::new(),
+            dependency_graph: DependencyGraph::default(),
+            composer: Composer::non_validating().with_capabilities(capabilities),
+            settings,
+            config_file: None,
+            files_loaded: false,
+        }
+    }
+
+    /// Whether a file is in the workspace folder or one of the include paths.
+    fn contains(&self, path: &Path) -> bool {
+        self.root
+            .as_ref()
+            .is_some_and(|root| path.starts_with(root))
+            || self
+                .settings
+                .include_paths
+                .iter()
+                .any(|include_path| path.starts_with(include_path))
+    }
+}
+
+impl WgslServerState {
+    /// The index of the project a document belongs to.
+    ///
+    /// That's the innermost workspace folder containing it, then the first project with an
+    /// include path containing it, and otherwise the project of documents outside every folder.
+    pub fn project_index(&self, uri: &Url) -> usize {
+        uri.to_file_path()
+            .map_or(0, |path| self.path_project_index(&path))
+    }
+
+    pub fn path_project_index(&self, path: &Path) -> usize {
+        let folder = self
+            .projects
+            .iter()
+            .enumerate()
+            .filter_map(|(i, project)| Some((i, project.root.as_ref()?)))
+            .filter(|(_, root)| path.starts_with(root))
+            .max_by_key(|(_, root)| root.components().count());
+        if let Some((i, _)) = folder {
+            return i;
+        }
+        self.projects
+            .iter()
+            .skip(1)
+            .position(|project| project.contains(path))
+            .map_or(0, |i| i + 1)
+    }
+
+    /// The project a document belongs to.
+    pub fn project(&self, uri: &Url) -> &Project {
+        &self.projects[self.project_index(uri)]
+    }
+
+    pub fn project_mut(&mut self, uri: &Url) -> &mut Project {
+        let index = self.project_index(uri);
+        &mut self.projects[index]
+    }
+
+    /// The index of the project of a workspace folder.
+    pub fn folder_project_index(&self, folder: &Path) -> Option<usize> {
+        self.projects
+            .iter()
+            .position(|project| project.root.as_deref() == Some(folder))
+    }
+
+    /// Remove a document's modules from the module lookup, composer, and dependency graph of
+    /// every project, and drop its cached module. Returns the names it was registered under.
+    pub fn unregister_document(&mut self, uri: &Url) -> Vec<String> {
+        let mut names = Vec::new();
+        for project in &mut self.projects {
+            let registered: Vec<String> = project
+                .module_lookup
+                .iter()
+                .filter(|(_, u)| *u == uri)
+                .map(|(name, _)| name.clone())
+                .collect();
+            for name in &registered {
+                project.module_lookup.remove(name);
+                // this will remove all dependents as well
+                project.composer.remove_composable_module(name);
+            }
+            project.dependency_graph.remove(uri);
+            names.extend(registered);
+        }
+        self.cached_modules.remove(uri);
+        names
+    }
+
+    /// Move documents that now belong to a different project, after projects were added or
+    /// removed or their include paths changed. Returns the documents that were moved.
+    pub fn reassign_documents(&mut self) -> Vec<Url> {
+        let moved: Vec<Url> = self
+            .open_documents
+            .keys()
+            .filter(|uri| {
+                let index = self.project_index(uri);
+                self.projects[index].dependency_graph.module(uri).is_none()
+            })
+            .cloned()
+            .collect();
+        for uri in &moved {
+            self.unregister_document(uri);
+            self.preprocess(uri);
+        }
+        moved
+    }
+}
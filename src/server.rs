@@ -3,29 +3,54 @@ use std::{collections::HashMap, fmt::Debug, ops::ControlFlow};
 use async_lsp::{router::Router, ClientSocket, ErrorCode, ResponseError};
 use lsp_types::{
     notification::{
-        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Initialized, LogMessage,
-        Notification,
+        DidChangeConfiguration, DidChangeTextDocument, DidChangeWatchedFiles,
+        DidChangeWorkspaceFolders, DidCloseTextDocument, DidOpenTextDocument, Initialized,
+        LogMessage, Notification,
     },
     request::{
-        GotoDefinition, HoverRequest, Initialize, Request, SemanticTokensFullRequest, Shutdown,
+        Completion, DocumentHighlightRequest, DocumentSymbolRequest, FoldingRangeRequest,
+        GotoDefinition, HoverRequest, Initialize, PrepareRenameRequest, References, Rename,
+        Request, ResolveCompletionItem, SemanticTokensFullDeltaRequest, SemanticTokensFullRequest,
+        SemanticTokensRangeRequest, Shutdown, SignatureHelpRequest, WorkspaceSymbolRequest,
     },
-    LogMessageParams, MessageType, ServerInfo, Url,
+    LogMessageParams, MessageType, SemanticTokens, ServerInfo, Url, WorkspaceSymbol,
 };
-use naga::valid::{Capabilities, ValidationFlags, Validator};
-use naga_oil::compose::Composer;
+use naga_oil::compose::{preprocess::Preprocessor, ShaderDefValue};
+use serde_json::Value;
 
 use crate::{
     document::OpenDocument,
     handlers::{
+        completion::{completion, completion_resolve},
+        configuration::{configuration_received, did_change_configuration, ConfigurationReceived},
+        document_symbol::document_symbol,
         document_sync::{did_change_document, did_close_document, did_open_document},
+        folding_range::folding_range,
+        goto_definition::goto_definition,
+        hover::hover,
         lifecycle::{initialize, initialized, shutdown},
-        semantic_tokens::semantic_tokens_full,
+        references::{document_highlight, references},
+        rename::{prepare_rename, rename},
+        semantic_tokens::{
+            semantic_tokens_full, semantic_tokens_full_delta, semantic_tokens_range,
+        },
+        signature_help::signature_help,
+        workspace_symbol::workspace_symbol,
+        workspace_sync::{did_change_watched_files, did_change_workspace_folders},
     },
+    line_index::PositionEncoding,
+    project::Project,
+    settings::Settings,
     validate::CachedModule,
+    validation_worker::{
+        validate_after_edit, validation_finished, ValidateAfterEdit, ValidationFinished,
+        ValidationWorker,
+    },
 };
 
 pub type Result<T> = async_lsp::Result<<T as Request>::Result, ResponseError>;
 pub type NotifyResult = ControlFlow<async_lsp::Result<()>>;
+pub type ShaderDefs = HashMap<String, ShaderDefValue>;
 
 pub fn get_server_info() -> ServerInfo {
     ServerInfo {
@@ -43,24 +68,45 @@ pub fn make_wgsl_router(client: ClientSocket) -> Router<WgslServerState> {
         .request::<Shutdown, _>(shutdown)
         .notification::<Initialized>(initialized)
         // document sync
-        // TODO: .notification::<DidChangeConfiguration>(on_did_change_configuration)
+        .notification::<DidChangeConfiguration>(did_change_configuration)
+        .event::<ConfigurationReceived>(configuration_received)
         .notification::<DidOpenTextDocument>(did_open_document)
         .notification::<DidChangeTextDocument>(did_change_document)
         .notification::<DidCloseTextDocument>(did_close_document)
+        .notification::<DidChangeWatchedFiles>(did_change_watched_files)
+        .notification::<DidChangeWorkspaceFolders>(did_change_workspace_folders)
         // language features
         .request::<SemanticTokensFullRequest, _>(semantic_tokens_full)
-        .request::<HoverRequest, _>(|_, _| async move { unimplemented!("Not yet implemented!") })
-        .request::<GotoDefinition, _>(|_, _| async move { unimplemented!("Not yet implemented!") })
+        .request::<SemanticTokensFullDeltaRequest, _>(semantic_tokens_full_delta)
+        .request::<SemanticTokensRangeRequest, _>(semantic_tokens_range)
+        .request::<HoverRequest, _>(hover)
+        .request::<GotoDefinition, _>(goto_definition)
+        .request::<References, _>(references)
+        .request::<DocumentHighlightRequest, _>(document_highlight)
+        .request::<PrepareRenameRequest, _>(prepare_rename)
+        .request::<Rename, _>(rename)
+        .request::<DocumentSymbolRequest, _>(document_symbol)
+        .request::<WorkspaceSymbolRequest, _>(workspace_symbol)
+        .request::<FoldingRangeRequest, _>(folding_range)
+        .request::<Completion, _>(completion)
+        .request::<ResolveCompletionItem, _>(completion_resolve)
+        .request::<SignatureHelpRequest, _>(signature_help)
+        // background validation
+        .event::<ValidateAfterEdit>(validate_after_edit)
+        .event::<ValidationFinished>(validation_finished)
         .unhandled_notification(log_unhandled)
         .unhandled_event(log_unhandled)
         .unhandled_request(|st, req| {
-            log_unhandled(st, req);
-            async move {
-                Err(ResponseError::new(
-                    ErrorCode::METHOD_NOT_FOUND,
-                    "Request not implemented",
-                ))
-            }
+            let error = match log_unhandled(st, req) {
+                ControlFlow::Continue(()) => {
+                    ResponseError::new(ErrorCode::METHOD_NOT_FOUND, "Request not implemented")
+                }
+                ControlFlow::Break(_) => ResponseError::new(
+                    ErrorCode::INTERNAL_ERROR,
+                    "The connection to the client was closed",
+                ),
+            };
+            async move { Err(error) }
         });
 
     router
@@ -72,14 +118,30 @@ pub struct WgslServerState {
     pub client: ClientSocket,
     /// Open documents, either owned by the client or the server.
     pub open_documents: HashMap<Url, OpenDocument>,
-    /// Mapping of module names/paths to their URLs.
-    /// This will only contain either a path or a module name, not both.
-    pub module_lookup: HashMap<String, Url>,
+    /// The workspace folders, after the project of documents outside every folder.
+    pub projects: Vec<Project>,
+    /// Module-scope declarations of every open document, for workspace symbol search.
+    pub workspace_symbols: HashMap<Url, Vec<WorkspaceSymbol>>,
+    /// The semantic tokens last sent for each document, which deltas are computed against.
+    pub semantic_tokens: HashMap<Url, SemanticTokens>,
+    /// The result id of the last semantic tokens sent.
+    pub next_semantic_tokens_id: u64,
     /// Cache of successfully built modules.
     pub cached_modules: HashMap<Url, CachedModule>,
-    /// Non-validating composer for building modules.
-    pub composer: Composer,
-    pub validator: Validator,
+    /// Validates built modules off the main loop.
+    pub validation_worker: ValidationWorker,
+    /// Settings from the client, for those that aren't per project like the log level.
+    pub settings: Settings,
+    /// Settings last sent by the client, which config files are applied on top of.
+    pub client_settings: Value,
+    /// Whether the client can be asked for settings with `workspace/configuration`.
+    pub supports_configuration_request: bool,
+    /// Whether the client can be asked to request semantic tokens again.
+    pub supports_semantic_tokens_refresh: bool,
+    /// How positions are encoded, negotiated with the client during `initialize`.
+    pub position_encoding: PositionEncoding,
+    /// Standalone preprocessor used to reproduce the source the composer parsed, for mapping spans.
+    pub preprocessor: Preprocessor,
     /// Whether to validate newly opened/changed documents.
     ///
     /// This is false at first so that we get time to load all the documents and their dependencies.
@@ -89,25 +151,38 @@ pub struct WgslServerState {
 
 impl WgslServerState {
     pub fn new(client: ClientSocket) -> Self {
+        let validation_worker = ValidationWorker::spawn(client.clone());
+        let settings = Settings::default();
         Self {
             client,
             open_documents: HashMap::new(),
-            module_lookup: HashMap::new(),
+            projects: vec![Project::new(None, settings.clone())],
+            workspace_symbols: HashMap::new(),
+            semantic_tokens: HashMap::new(),
+            next_semantic_tokens_id: 0,
             cached_modules: HashMap::new(),
-            composer: Composer::non_validating().with_capabilities(Capabilities::all()),
-            validator: Validator::new(ValidationFlags::all(), Capabilities::all()),
+            validation_worker,
+            settings,
+            client_settings: Value::Null,
+            supports_configuration_request: false,
+            supports_semantic_tokens_refresh: false,
+            position_encoding: PositionEncoding::default(),
+            preprocessor: Preprocessor::default(),
             should_validate: false,
         }
     }
 
-    /// Send a [LogMessage] notification to the client.
+    /// Send a [LogMessage] notification to the client, unless the configured log level filters it out.
     ///
     /// This returns [ControlFlow::Break] if the message could not be sent due to the main loop stopping.
     pub fn log(&self, typ: MessageType, message: &str) -> NotifyResult {
-        return self.notify::<LogMessage>(LogMessageParams {
+        if !self.settings.log_level.allows(typ) {
+            return ControlFlow::Continue(());
+        }
+        self.notify::<LogMessage>(LogMessageParams {
             typ,
             message: message.to_string(),
-        });
+        })
     }
 
     /// Send a [Notification] to the client.
@@ -0,0 +1,260 @@
+use std::{collections::HashMap, fmt, time::Duration};
+
+use bitflags::Flags;
+use lsp_types::{DiagnosticSeverity, MessageType};
+use naga::valid::{Capabilities, ValidationFlags};
+use naga_oil::compose::ShaderDefValue;
+use serde::{de::Error, Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::{glob::Globs, server::ShaderDefs};
+
+/// The section of the client's configuration holding our settings.
+pub const SECTION: &str = "wgsl-lsp";
+
+/// Settings the client can change, read from `initializationOptions` and from the `wgsl-lsp`
+/// section of `workspace/configuration`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    /// Directories to load .wgsl files from besides the workspace folders.
+    pub include_paths: Vec<String>,
+    /// Globs of files and directories not to load, relative to the workspace folders, like
+    /// `["target/**", "**/generated"]`.
+    pub exclude: Globs,
+    /// Shader defs to build modules with.
+    #[serde(deserialize_with = "deserialize_shader_defs")]
+    pub shader_defs: ShaderDefs,
+    /// Named sets of shader defs to validate documents with on top of `shader_defs`.
+    #[serde(deserialize_with = "deserialize_shader_def_permutations")]
+    pub shader_def_permutations: Vec<(String, ShaderDefs)>,
+    /// The checks naga runs when validating, by name, like `["EXPRESSIONS", "BINDINGS"]`.
+    #[serde(deserialize_with = "deserialize_flags")]
+    pub validation_flags: ValidationFlags,
+    /// The features shaders may use.
+    pub capabilities: CapabilitiesProfile,
+    /// How long to wait after an edit before validating, in milliseconds.
+    #[serde(deserialize_with = "deserialize_millis")]
+    pub validation_delay: Duration,
+    /// The least severe messages to log to the client.
+    pub log_level: LogLevel,
+    /// How to report diagnostics, by their code, like `{ "validation-error": "warning" }`.
+    pub lints: HashMap<String, LintLevel>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            include_paths: Vec::new(),
+            exclude: Globs::default(),
+            shader_defs: ShaderDefs::new(),
+            shader_def_permutations: Vec::new(),
+            validation_flags: ValidationFlags::all(),
+            capabilities: CapabilitiesProfile::Everything,
+            validation_delay: Duration::from_millis(200),
+            log_level: LogLevel::default(),
+            lints: HashMap::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Read settings from a JSON object, using defaults for anything that's missing.
+    pub fn from_value(value: &Value) -> Result<Self, String> {
+        if value.is_null() {
+            return Ok(Self::default());
+        }
+        Self::deserialize(value).map_err(|e| format!("Invalid settings: {e}"))
+    }
+}
+
+/// The features shaders may use, either named after a target like `"webgpu"`, or listed by name
+/// like `["FLOAT64", "PUSH_CONSTANT"]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapabilitiesProfile {
+    /// What WebGPU supports without optional features.
+    WebGpu,
+    /// Everything wgpu can enable on Vulkan.
+    NativeVulkan,
+    /// Everything naga supports.
+    Everything,
+    Explicit(Capabilities),
+}
+
+impl CapabilitiesProfile {
+    pub fn capabilities(self) -> Capabilities {
+        match self {
+            // naga's default happens to match core WebGPU: sample_index and cube map arrays
+            CapabilitiesProfile::WebGpu => Capabilities::default(),
+            // Vulkan has shaderClipDistance and shaderCullDistance, but wgpu has no feature to
+            // enable them, so shaders using them can't be created on Vulkan through wgpu
+            CapabilitiesProfile::NativeVulkan => {
+                Capabilities::all() - Capabilities::CLIP_DISTANCE - Capabilities::CULL_DISTANCE
+            }
+            CapabilitiesProfile::Everything => Capabilities::all(),
+            CapabilitiesProfile::Explicit(capabilities) => capabilities,
+        }
+    }
+}
+
+impl fmt::Display for CapabilitiesProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CapabilitiesProfile::WebGpu => write!(f, "the `webgpu` capabilities profile"),
+            CapabilitiesProfile::NativeVulkan => {
+                write!(f, "the `native-vulkan` capabilities profile")
+            }
+            CapabilitiesProfile::Everything => write!(f, "the `everything` capabilities profile"),
+            CapabilitiesProfile::Explicit(_) => write!(f, "the configured capabilities"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CapabilitiesProfile {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        match Value::deserialize(d)? {
+            Value::String(profile) => match profile.as_str() {
+                "webgpu" => Ok(CapabilitiesProfile::WebGpu),
+                "native-vulkan" => Ok(CapabilitiesProfile::NativeVulkan),
+                "everything" => Ok(CapabilitiesProfile::Everything),
+                _ => Err(D::Error::custom(format!(
+                    "unknown capabilities profile `{profile}`, expected `webgpu`, `native-vulkan`, \
+                     `everything`, or a list of capabilities"
+                ))),
+            },
+            value => deserialize_flags(value)
+                .map(CapabilitiesProfile::Explicit)
+                .map_err(D::Error::custom),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warning,
+    Info,
+    #[default]
+    Log,
+}
+
+impl LogLevel {
+    /// Whether messages of a type are logged at this level.
+    pub fn allows(self, typ: MessageType) -> bool {
+        let level = match typ {
+            MessageType::ERROR => LogLevel::Error,
+            MessageType::WARNING => LogLevel::Warning,
+            MessageType::INFO => LogLevel::Info,
+            _ => LogLevel::Log,
+        };
+        level as u8 <= self as u8
+    }
+}
+
+/// How to report the diagnostics of a lint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    /// Don't report them.
+    Allow,
+    Hint,
+    Info,
+    Warning,
+    Error,
+}
+
+impl LintLevel {
+    /// The severity to report diagnostics with, or [None] if they aren't reported.
+    pub fn severity(self) -> Option<DiagnosticSeverity> {
+        match self {
+            LintLevel::Allow => None,
+            LintLevel::Hint => Some(DiagnosticSeverity::HINT),
+            LintLevel::Info => Some(DiagnosticSeverity::INFORMATION),
+            LintLevel::Warning => Some(DiagnosticSeverity::WARNING),
+            LintLevel::Error => Some(DiagnosticSeverity::ERROR),
+        }
+    }
+}
+
+/// Parse shader defs from an object like `{ "VERTEX_UVS": true, "MAX_LIGHTS": 4, "FLAGS": "3u" }`.
+///
+/// Numbers are `i32`s unless they only fit in a `u32`. Strings are parsed like WGSL integer
+/// literals, so a `u` suffix makes a `u32`. As in naga_oil, `#ifdef` is true for any def that's
+/// set, even to `false`.
+fn parse_shader_defs(value: &Value) -> Result<ShaderDefs, String> {
+    let Some(defs) = value.as_object() else {
+        return Err(format!("Shader defs must be an object, found: {value}"));
+    };
+    defs.iter()
+        .map(|(name, value)| {
+            let def = match value {
+                Value::Bool(b) => Some(ShaderDefValue::Bool(*b)),
+                Value::Number(n) => n
+                    .as_i64()
+                    .and_then(|n| i32::try_from(n).ok())
+                    .map(ShaderDefValue::Int)
+                    .or_else(|| {
+                        n.as_u64()
+                            .and_then(|n| u32::try_from(n).ok())
+                            .map(ShaderDefValue::UInt)
+                    }),
+                Value::String(s) => match s.strip_suffix('u') {
+                    Some(s) => s.parse().ok().map(ShaderDefValue::UInt),
+                    None => s
+                        .strip_suffix('i')
+                        .unwrap_or(s)
+                        .parse()
+                        .ok()
+                        .map(ShaderDefValue::Int),
+                },
+                _ => None,
+            };
+            def.map(|def| (name.clone(), def))
+                .ok_or_else(|| format!("Invalid value for shader def `{name}`: {value}"))
+        })
+        .collect()
+}
+
+/// Parse named permutations of shader defs from an object like
+/// `{ "skinned": { "SKINNED": true }, "morph+prepass": { "MORPH_TARGETS": true, "PREPASS": true } }`.
+fn parse_shader_def_permutations(value: &Value) -> Result<Vec<(String, ShaderDefs)>, String> {
+    let Some(permutations) = value.as_object() else {
+        return Err(format!(
+            "Shader def permutations must be an object, found: {value}"
+        ));
+    };
+    permutations
+        .iter()
+        .map(|(name, defs)| {
+            parse_shader_defs(defs)
+                .map(|defs| (name.clone(), defs))
+                .map_err(|e| format!("{e} (in permutation `{name}`)"))
+        })
+        .collect()
+}
+
+fn deserialize_shader_defs<'de, D: Deserializer<'de>>(d: D) -> Result<ShaderDefs, D::Error> {
+    parse_shader_defs(&Value::deserialize(d)?).map_err(D::Error::custom)
+}
+
+fn deserialize_shader_def_permutations<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<Vec<(String, ShaderDefs)>, D::Error> {
+    parse_shader_def_permutations(&Value::deserialize(d)?).map_err(D::Error::custom)
+}
+
+/// Flags by the names of their constants, in any case.
+fn deserialize_flags<'de, D: Deserializer<'de>, F: Flags>(d: D) -> Result<F, D::Error> {
+    Vec::<String>::deserialize(d)?
+        .iter()
+        .try_fold(F::empty(), |flags, name| {
+            F::from_name(&name.to_uppercase())
+                .map(|flag| flags.union(flag))
+                .ok_or_else(|| D::Error::custom(format!("unknown flag `{name}`")))
+        })
+}
+
+fn deserialize_millis<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    u64::deserialize(d).map(Duration::from_millis)
+}
@@ -0,0 +1,400 @@
+use std::ops::Range;
+
+use naga::{Expression, Module, Span};
+
+use crate::lexer::{is_ident_continue, next_significant, tokenize, Token, TokenKind};
+
+/// naga_oil packs the index of the module an item came from into the upper bits of its spans.
+/// Spans with a module index of 0 belong to the document the module was built from.
+pub const SPAN_SHIFT: usize = 21;
+
+/// Maps spans in a module built by naga_oil back to byte offsets in the document's source.
+///
+/// naga_oil parses a header containing every imported item followed by the preprocessed source,
+/// so spans are shifted by the header length, and every line that refers to an imported item is
+/// rewritten with decorated names. Lines are preserved one-to-one, so we can align each line of the
+/// preprocessed source with the matching line of the document.
+/// See https://github.com/bevyengine/naga_oil/issues/76
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    /// Offset of the preprocessed source in the string naga parsed.
+    start_offset: usize,
+    /// Document offset for each offset in the preprocessed source.
+    to_source: Vec<usize>,
+    /// Preprocessed offset for each offset in the document.
+    to_module: Vec<usize>,
+}
+
+impl SourceMap {
+    pub fn new(source: &str, preprocessed: &str, module: &Module) -> Self {
+        let mut to_source = vec![0; preprocessed.len() + 1];
+        let mut to_module = vec![0; source.len() + 1];
+
+        let source_lines = line_ranges(source);
+        let preprocessed_lines = line_ranges(preprocessed);
+        for (src, pre) in source_lines.iter().zip(preprocessed_lines.iter()) {
+            align_line(
+                source,
+                src.clone(),
+                preprocessed,
+                pre.clone(),
+                &mut to_source,
+                &mut to_module,
+            );
+        }
+        // anything left over (trailing newlines, extra lines) maps to the end of the other side
+        let source_end = source_lines.last().map(|l| l.end).unwrap_or(0);
+        let pre_end = preprocessed_lines.last().map(|l| l.end).unwrap_or(0);
+        fill_gaps(
+            &mut to_source,
+            &preprocessed_lines,
+            source_end,
+            &source_lines,
+        );
+        fill_gaps(&mut to_module, &source_lines, pre_end, &preprocessed_lines);
+
+        Self {
+            start_offset: find_start_offset(preprocessed, module),
+            to_source,
+            to_module,
+        }
+    }
+
+    /// Map a span from the module to a range in the document.
+    ///
+    /// Returns [None] if the span is undefined or belongs to an imported module.
+    pub fn span_to_source(&self, span: Span) -> Option<Range<usize>> {
+        let range = span.to_range()?;
+        if range.start >> SPAN_SHIFT != 0 || range.start < self.start_offset {
+            return None;
+        }
+        let start = range.start - self.start_offset;
+        let end = range.end - self.start_offset;
+        let start = *self.to_source.get(start)?;
+        let end = *self
+            .to_source
+            .get(end)
+            .unwrap_or(self.to_source.last().unwrap());
+        Some(start..end.max(start))
+    }
+
+    /// Map a document offset to the equivalent position in the module's span space.
+    pub fn source_to_span(&self, offset: usize) -> usize {
+        let offset = offset.min(self.to_module.len() - 1);
+        self.to_module[offset] + self.start_offset
+    }
+
+    /// Whether a span from the module contains the document offset.
+    pub fn span_contains(&self, span: Span, offset: usize) -> bool {
+        let position = self.source_to_span(offset);
+        span.to_range()
+            .is_some_and(|range| range.start <= position && position < range.end)
+    }
+}
+
+/// Ranges of each line excluding line terminators, matching [str::lines].
+fn line_ranges(text: &str) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches('\n').trim_end_matches('\r');
+        lines.push(start..start + content.len());
+        start += line.len();
+    }
+    lines
+}
+
+/// Map every offset between lines (line terminators) to the start of the next mapped offset.
+fn fill_gaps(
+    map: &mut [usize],
+    lines: &[Range<usize>],
+    other_end: usize,
+    other_lines: &[Range<usize>],
+) {
+    let mut covered = vec![false; map.len()];
+    for (line, other) in lines.iter().zip(other_lines.iter()) {
+        for covered in &mut covered[line.start..=line.end.min(map.len() - 1)] {
+            *covered = true;
+        }
+        // the end of the line maps to the end of the other line
+        map[line.end] = other.end;
+    }
+    let mut next = other_end;
+    for i in (0..map.len()).rev() {
+        if covered[i] {
+            next = map[i];
+        } else {
+            map[i] = next;
+        }
+    }
+}
+
+/// Align a line from the document with the same line after naga_oil preprocessed it.
+///
+/// The only differences within a line come from naga_oil substituting identifiers, so matching
+/// text maps one-to-one and differing words map onto each other as a whole.
+fn align_line(
+    source: &str,
+    src: Range<usize>,
+    preprocessed: &str,
+    pre: Range<usize>,
+    to_source: &mut [usize],
+    to_module: &mut [usize],
+) {
+    let is_word = |text: &str, at: usize| text[at..].chars().next().is_some_and(is_ident_continue);
+    let (mut i, mut j) = (src.start, pre.start);
+
+    while i < src.end && j < pre.end {
+        let substitutable = is_word(source, i) || source[i..].starts_with(['#', '"']);
+        if is_word(preprocessed, j) && substitutable {
+            let src_end = source_word_end(source, i, src.end);
+            let pre_end = j + preprocessed[j..pre.end]
+                .find(|c: char| !is_ident_continue(c))
+                .unwrap_or(pre.end - j);
+            let src_word = &source[i..src_end];
+            let pre_word = &preprocessed[j..pre_end];
+            if src_word == pre_word {
+                for k in 0..src_word.len() {
+                    to_source[j + k] = i + k;
+                    to_module[i + k] = j + k;
+                }
+            } else {
+                // `module::item` becomes `itemX_naga_oil_mod_X...X`, so line up the item names
+                let item = src_word.rsplit("::").next().unwrap_or(src_word);
+                let item_start = src_end - item.len();
+                let shared = if pre_word.starts_with(item) {
+                    item.len()
+                } else {
+                    0
+                };
+                for k in 0..pre_word.len() {
+                    to_source[j + k] = if k < shared { item_start + k } else { src_end };
+                }
+                for k in 0..src_word.len() {
+                    to_module[i + k] = if i + k >= item_start && i + k - item_start < shared {
+                        j + i + k - item_start
+                    } else {
+                        j
+                    };
+                }
+            }
+            i = src_end;
+            j = pre_end;
+        } else {
+            let src_len = source[i..].chars().next().map_or(1, char::len_utf8);
+            let pre_len = preprocessed[j..].chars().next().map_or(1, char::len_utf8);
+            for k in 0..pre_len {
+                to_source[j + k] = i;
+            }
+            for k in 0..src_len {
+                to_module[i + k] = j;
+            }
+            i += src_len;
+            j += pre_len;
+        }
+    }
+    to_source[j..pre.end].fill(src.end);
+    to_module[i..src.end].fill(pre.end);
+}
+
+/// End of an identifier path like `a::b::c`, `"path"::c`, or a shader def like `#DEF` / `#{DEF}`.
+fn source_word_end(source: &str, start: usize, line_end: usize) -> usize {
+    let line = &source[..line_end];
+    let mut i = start;
+    if line[i..].starts_with('#') {
+        i += 1;
+        i += line[i..].len() - line[i..].trim_start().len();
+        let braced = line[i..].starts_with('{');
+        i += usize::from(braced);
+        i += line[i..]
+            .find(|c: char| !is_ident_continue(c))
+            .unwrap_or(line.len() - i);
+        return i + usize::from(braced && line[i..].starts_with('}'));
+    }
+    loop {
+        if line[i..].starts_with('"') {
+            i += 1 + line[i + 1..]
+                .find('"')
+                .map_or(line.len() - i - 1, |e| e + 1);
+        } else {
+            i += line[i..]
+                .find(|c: char| !is_ident_continue(c))
+                .unwrap_or(line.len() - i);
+        }
+        if line[i..].starts_with("::")
+            && line[i + 2..].starts_with(|c: char| is_ident_continue(c) || c == '"')
+        {
+            i += 2;
+        } else {
+            return i;
+        }
+    }
+}
+
+/// Find where the preprocessed source starts in the string naga parsed by locating a declaration
+/// from the module in the preprocessed source.
+fn find_start_offset(preprocessed: &str, module: &Module) -> usize {
+    let tokens = tokenize(preprocessed);
+    let local = |span: Span| span.to_range().filter(|r| r.start >> SPAN_SHIFT == 0);
+
+    // function spans start at `fn`
+    for (handle, function) in module.functions.iter() {
+        if let (Some(range), Some(name)) =
+            (local(module.functions.get_span(handle)), &function.name)
+        {
+            if let Some(pos) = find_declaration(preprocessed, &tokens, "fn", name) {
+                if range.start >= pos {
+                    return range.start - pos;
+                }
+            }
+        }
+    }
+    for (handle, var) in module.global_variables.iter() {
+        if let (Some(range), Some(name)) =
+            (local(module.global_variables.get_span(handle)), &var.name)
+        {
+            if let Some(pos) = find_declaration(preprocessed, &tokens, "var", name) {
+                if range.start >= pos {
+                    return range.start - pos;
+                }
+            }
+        }
+    }
+    for (handle, constant) in module.constants.iter() {
+        if let (Some(range), Some(name)) =
+            (local(module.constants.get_span(handle)), &constant.name)
+        {
+            if let Some(pos) = find_declaration(preprocessed, &tokens, "const", name) {
+                if range.start >= pos {
+                    return range.start - pos;
+                }
+            }
+        }
+    }
+    for (handle, ty) in module.types.iter() {
+        if let (Some(range), Some(name)) = (local(module.types.get_span(handle)), &ty.name) {
+            if let Some(pos) = find_declaration(preprocessed, &tokens, "struct", name) {
+                if range.start >= pos {
+                    return range.start - pos;
+                }
+            }
+        }
+    }
+
+    // Entry points aren't in the function arena, so fall back to the first named reference
+    // inside an entry point body.
+    for ep in &module.entry_points {
+        let Some(decl) = find_declaration(preprocessed, &tokens, "fn", &ep.name) else {
+            continue;
+        };
+        for (handle, expr) in ep.function.expressions.iter() {
+            let name = match *expr {
+                Expression::GlobalVariable(h) => module.global_variables[h].name.as_deref(),
+                Expression::Constant(h) => module.constants[h].name.as_deref(),
+                Expression::CallResult(h) => module.functions[h].name.as_deref(),
+                _ => None,
+            };
+            let (Some(range), Some(name)) = (local(ep.function.expressions.get_span(handle)), name)
+            else {
+                continue;
+            };
+            let pos = tokens
+                .iter()
+                .find(|t| t.range.start > decl && t.is_ident(preprocessed, name))
+                .map(|t| t.range.start);
+            if let Some(pos) = pos.filter(|&pos| range.start >= pos) {
+                return range.start - pos;
+            }
+        }
+    }
+
+    0
+}
+
+/// Offset of `keyword` for the declaration of `name`, skipping `var` templates like `<uniform>`.
+fn find_declaration(source: &str, tokens: &[Token], keyword: &str, name: &str) -> Option<usize> {
+    tokens.iter().enumerate().find_map(|(i, token)| {
+        if !token.is_ident(source, keyword) {
+            return None;
+        }
+        let mut next = next_significant(tokens, i)?;
+        if tokens[next].is_punct(source, "<") {
+            next = (next..tokens.len()).find(|&n| tokens[n].is_punct(source, ">"))?;
+            next = next_significant(tokens, next)?;
+        }
+        (tokens[next].kind == TokenKind::Ident && tokens[next].text(source) == name)
+            .then_some(token.range.start)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a source map the way naga_oil would, with `header` parsed before the preprocessed
+    /// source.
+    fn source_map(source: &str, header: &str, preprocessed: &str) -> SourceMap {
+        let module = naga::front::wgsl::parse_str(&format!("{header}{preprocessed}")).unwrap();
+        SourceMap::new(source, preprocessed, &module)
+    }
+
+    fn span(range: Range<usize>) -> Span {
+        Span::new(range.start as u32, range.end as u32)
+    }
+
+    #[test]
+    fn unchanged_source_maps_to_itself() {
+        let source = "fn main() {\n    let x = 1;\n}\n";
+        let map = source_map(source, "", source);
+        assert_eq!(map.span_to_source(span(3..7)), Some(3..7));
+        assert_eq!(map.source_to_span(20), 20);
+        assert!(map.span_contains(span(16..17), 16));
+        assert!(!map.span_contains(span(16..17), 17));
+    }
+
+    #[test]
+    fn spans_are_shifted_by_the_header() {
+        let header = "fn imported() {}\n";
+        let source = "fn main() {\n    imported();\n}\n";
+        let map = source_map(source, header, source);
+        let shift = header.len();
+        assert_eq!(map.span_to_source(span(shift + 3..shift + 7)), Some(3..7));
+        assert_eq!(map.source_to_span(3), shift + 3);
+        // the header itself isn't part of the document
+        assert_eq!(map.span_to_source(span(3..11)), None);
+    }
+
+    #[test]
+    fn spans_from_imported_modules_are_ignored() {
+        let source = "fn main() {}\n";
+        let map = source_map(source, "", source);
+        let imported = 1 << SPAN_SHIFT;
+        assert_eq!(map.span_to_source(span(imported..imported + 4)), None);
+        assert_eq!(map.span_to_source(Span::UNDEFINED), None);
+    }
+
+    #[test]
+    fn decorated_names_map_onto_import_paths() {
+        let header = "fn helperX_naga_oil_mod_XOV2GS3DFOJUXGX() -> f32 { return 1.0; }\n";
+        let source = "fn main() -> f32 {\n    return util::helper();\n}\n";
+        let preprocessed =
+            "fn main() -> f32 {\n    return helperX_naga_oil_mod_XOV2GS3DFOJUXGX();\n}\n";
+        let map = source_map(source, header, preprocessed);
+        let shift = header.len();
+
+        let path = source.find("util").unwrap();
+        let item = source.find("helper").unwrap();
+        let decorated = preprocessed.find("helper").unwrap();
+        let decorated_end = preprocessed.find("();").unwrap();
+        // the item name lines up, and the module path maps to the start of the decorated name
+        assert_eq!(map.source_to_span(item + 2), shift + decorated + 2);
+        assert_eq!(map.source_to_span(path), shift + decorated);
+        assert_eq!(
+            map.span_to_source(span(shift + decorated..shift + decorated_end)),
+            Some(item..item + "helper".len())
+        );
+        // text after the substitution is still aligned
+        let paren = source.find("();").unwrap();
+        assert_eq!(map.source_to_span(paren), shift + decorated_end);
+    }
+}
@@ -0,0 +1,161 @@
+use std::{collections::HashSet, ops::Range};
+
+use crate::{
+    lexer::TokenKind,
+    symbols::{function_by_name, item_name, undecorate, FunctionScope, Resolver, Symbol},
+    syntax::{self, DeclarationKind},
+    validate::CachedModule,
+};
+
+/// Identifies a symbol across modules.
+///
+/// Each document is composed into its own [naga::Module], so handles can't be compared between
+/// documents. Module-scope items are identified by their full `module::item` path instead.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SymbolKey {
+    /// A function, entry point, type, global, or constant, by its full path.
+    Item(String),
+    /// A struct member, by the full path of the struct and the member index.
+    Member(String, u32),
+    /// Arguments and locals, which can only be referred to in the document declaring them.
+    Local(Symbol),
+}
+
+impl SymbolKey {
+    /// The module that declares the symbol, if it can be referred to from other modules.
+    pub fn module(&self) -> Option<&str> {
+        match self {
+            SymbolKey::Item(path) | SymbolKey::Member(path, _) => {
+                path.rsplit_once("::").map(|(module, _)| module)
+            }
+            SymbolKey::Local(_) => None,
+        }
+    }
+}
+
+/// A name in the source that refers to a symbol.
+#[derive(Debug, Clone)]
+pub struct Occurrence {
+    /// Range of the name. For paths like `module::item` this is only the last segment.
+    pub range: Range<usize>,
+    pub key: SymbolKey,
+    /// The symbol in this module, or [None] for items named in `#import` directives, which aren't
+    /// in it unless they're used.
+    pub symbol: Option<Symbol>,
+    /// Whether this is the name in the symbol's declaration.
+    pub is_declaration: bool,
+}
+
+/// Every name in a module's source that refers to a symbol, in source order.
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    pub occurrences: Vec<Occurrence>,
+}
+
+impl SymbolIndex {
+    pub fn new(cached: &CachedModule) -> Self {
+        let source = &cached.source;
+        let resolver = Resolver::new(cached);
+        let directives = syntax::directives(source, &resolver.tokens);
+        let declared: HashSet<_> = resolver
+            .declarations
+            .iter()
+            .flat_map(|d| std::iter::once(d).chain(d.children.iter()))
+            .map(|d| d.name_range.clone())
+            .collect();
+
+        let mut occurrences = Vec::new();
+        // imported items aren't necessarily used, so they may not be in the module
+        for import in &resolver.imports {
+            if let (Some((_, range)), true) = (import.segments.last(), import.segments.len() > 1) {
+                occurrences.push(Occurrence {
+                    range: range.clone(),
+                    key: SymbolKey::Item(syntax::join_path(&import.segments)),
+                    symbol: None,
+                    is_declaration: false,
+                });
+            }
+        }
+
+        // each function is looked up once for all the names in it
+        let functions: Vec<_> = resolver
+            .declarations
+            .iter()
+            .filter(|d| matches!(d.kind, DeclarationKind::Function(_)))
+            .map(|d| {
+                let scope = function_by_name(&cached.module, &d.name)
+                    .map(|function| FunctionScope::new(&cached.module, function));
+                (d.range.clone(), scope)
+            })
+            .collect();
+
+        for token in &resolver.tokens {
+            if token.kind != TokenKind::Ident
+                || directives
+                    .iter()
+                    .any(|d| d.start <= token.range.start && token.range.end <= d.end)
+            {
+                continue;
+            }
+            let offset = token.range.start;
+            let started = functions.partition_point(|(range, _)| range.start <= offset);
+            let function = functions[..started]
+                .last()
+                .filter(|(range, _)| offset <= range.end)
+                .and_then(|(_, scope)| scope.as_ref());
+            let Some((_, symbol)) = resolver.resolve_in(function, offset) else {
+                continue;
+            };
+            if let Some(key) = symbol_key(cached, symbol) {
+                occurrences.push(Occurrence {
+                    range: token.range.clone(),
+                    key,
+                    symbol: Some(symbol),
+                    is_declaration: declared.contains(&token.range),
+                });
+            }
+        }
+
+        occurrences.sort_by_key(|o| o.range.start);
+        Self { occurrences }
+    }
+
+    /// The occurrence containing `offset`, including a cursor right after the name.
+    pub fn occurrence_at(&self, offset: usize) -> Option<&Occurrence> {
+        let started = self
+            .occurrences
+            .partition_point(|o| o.range.start <= offset);
+        self.occurrences[..started]
+            .last()
+            .filter(|o| offset <= o.range.end)
+    }
+
+    pub fn occurrences_of<'a>(
+        &'a self,
+        key: &'a SymbolKey,
+    ) -> impl Iterator<Item = &'a Occurrence> {
+        self.occurrences.iter().filter(move |o| o.key == *key)
+    }
+}
+
+/// The key of a symbol from the module in `cached`.
+pub fn symbol_key(cached: &CachedModule, symbol: Symbol) -> Option<SymbolKey> {
+    let item_path = || {
+        let name = item_name(&cached.module, symbol)?;
+        let path = undecorate(name);
+        Some(if path == name {
+            // items declared in this module aren't decorated
+            format!("{}::{}", cached.module_name, name)
+        } else {
+            path
+        })
+    };
+    match symbol {
+        Symbol::Argument(..) | Symbol::Local(..) | Symbol::Let(..) => {
+            Some(SymbolKey::Local(symbol))
+        }
+        Symbol::Expression(..) => None,
+        Symbol::Member(_, index) => Some(SymbolKey::Member(item_path()?, index)),
+        _ => Some(SymbolKey::Item(item_path()?)),
+    }
+}
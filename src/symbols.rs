@@ -0,0 +1,511 @@
+use std::ops::Range;
+
+use naga::{
+    front::Typifier, proc::ResolveContext, Constant, Expression, Function, GlobalVariable, Handle,
+    LocalVariable, Module, ShaderStage, Span, Type, TypeInner,
+};
+use naga_oil::compose::Composer;
+
+use crate::{
+    lexer::{prev_significant, token_at, tokenize, Token, TokenKind},
+    source_map::SourceMap,
+    syntax::{self, DeclarationKind},
+    validate::CachedModule,
+};
+
+const DECORATION_PRE: &str = "X_naga_oil_mod_X";
+const DECORATION_OVERRIDE_PRE: &str = "X_naga_oil_vrt_X";
+const DECORATION_POST: &str = "X";
+
+/// A function in a [Module], which is either in the function arena or an entry point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FunctionRef {
+    Function(Handle<Function>),
+    EntryPoint(usize),
+}
+
+impl FunctionRef {
+    pub fn get(self, module: &Module) -> &Function {
+        match self {
+            FunctionRef::Function(handle) => &module.functions[handle],
+            FunctionRef::EntryPoint(index) => &module.entry_points[index].function,
+        }
+    }
+}
+
+/// Something in a [Module] that a name in the source can refer to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symbol {
+    Function(Handle<Function>),
+    EntryPoint(usize),
+    Type(Handle<Type>),
+    Member(Handle<Type>, u32),
+    Global(Handle<GlobalVariable>),
+    Constant(Handle<Constant>),
+    Argument(FunctionRef, u32),
+    Local(FunctionRef, Handle<LocalVariable>),
+    /// A `let` binding, identified by the expression it names.
+    Let(FunctionRef, Handle<Expression>),
+    /// Any other expression, which we only know the type of.
+    Expression(FunctionRef, Handle<Expression>),
+}
+
+/// An identifier path like `a::b::c` under the cursor.
+#[derive(Debug, Clone)]
+pub struct PathAtCursor {
+    pub segments: Vec<(String, Range<usize>)>,
+    /// Index of the segment the cursor is on.
+    pub active: usize,
+    /// Whether the path follows a `.`, making it a member access or swizzle.
+    pub is_member: bool,
+}
+
+impl PathAtCursor {
+    pub fn range(&self) -> Range<usize> {
+        self.segments.first().unwrap().1.start..self.segments.last().unwrap().1.end
+    }
+
+    pub fn text(&self) -> String {
+        self.segments
+            .iter()
+            .map(|(s, _)| s.as_str())
+            .collect::<Vec<_>>()
+            .join("::")
+    }
+}
+
+/// Find the identifier path containing `offset`.
+pub fn path_at(source: &str, tokens: &[Token], offset: usize) -> Option<PathAtCursor> {
+    let index = token_at(tokens, offset)?;
+    if !matches!(tokens[index].kind, TokenKind::Ident | TokenKind::String) {
+        return None;
+    }
+    let is_segment = |i: usize| matches!(tokens[i].kind, TokenKind::Ident | TokenKind::String);
+    let is_separator = |i: usize| tokens[i].is_punct(source, "::");
+
+    let mut first = index;
+    while first >= 2 && is_separator(first - 1) && is_segment(first - 2) {
+        first -= 2;
+    }
+    let mut last = index;
+    while last + 2 < tokens.len() && is_separator(last + 1) && is_segment(last + 2) {
+        last += 2;
+    }
+
+    let segments = (first..=last)
+        .step_by(2)
+        .map(|i| (tokens[i].text(source).to_owned(), tokens[i].range.clone()))
+        .collect();
+    let is_member =
+        prev_significant(tokens, first).is_some_and(|p| tokens[p].is_punct(source, "."));
+    Some(PathAtCursor {
+        segments,
+        active: (index - first) / 2,
+        is_member,
+    })
+}
+
+/// Every identifier path in the source that isn't a member access, e.g. `a` or `a::b::c`.
+pub fn paths(source: &str, tokens: &[Token]) -> Vec<PathAtCursor> {
+    tokens
+        .iter()
+        .enumerate()
+        .filter(|(i, token)| {
+            token.kind == TokenKind::Ident && (*i == 0 || !tokens[i - 1].is_punct(source, "::"))
+        })
+        .filter_map(|(_, token)| path_at(source, tokens, token.range.start))
+        .filter(|path| !path.is_member)
+        .collect()
+}
+
+/// Resolve a path as written in the source to the full path naga_oil substitutes for it,
+/// following `#import` aliases.
+pub fn resolve_import_path(imports: &[syntax::Import], path: &str) -> String {
+    let (first, rest) = path.split_once("::").unwrap_or((path, ""));
+    match imports.iter().find(|import| import.name == first) {
+        Some(import) if rest.is_empty() => import.path.clone(),
+        Some(import) => format!("{}::{}", import.path, rest),
+        None => path.to_owned(),
+    }
+}
+
+/// The name naga_oil gives an item in a composed [Module], e.g. `itemX_naga_oil_mod_X...X`
+/// for `module::item`.
+pub fn module_item_name(full_path: &str) -> String {
+    match full_path.rsplit_once("::") {
+        Some((module, item)) => Composer::decorated_name(Some(module), item),
+        None => full_path.to_owned(),
+    }
+}
+
+/// Replace every naga_oil decorated name in `text` with its `module::item` path.
+pub fn undecorate(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    loop {
+        let found = [DECORATION_PRE, DECORATION_OVERRIDE_PRE]
+            .iter()
+            .filter_map(|pre| rest.find(pre).map(|i| (i, *pre)))
+            .min();
+        let Some((index, pre)) = found else {
+            output.push_str(rest);
+            return output;
+        };
+        let encoded_start = index + pre.len();
+        // the encoded module name is followed by the closing `X`, which is also a base32 character
+        let encoded_len = rest[encoded_start..]
+            .find(|c: char| !(c.is_ascii_uppercase() || c.is_ascii_digit()))
+            .unwrap_or(rest.len() - encoded_start);
+        let encoded_end = encoded_start + encoded_len.saturating_sub(DECORATION_POST.len());
+        let module = data_encoding::BASE32_NOPAD
+            .decode(&rest.as_bytes()[encoded_start..encoded_end])
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok());
+        let item_start = rest[..index]
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        match module {
+            Some(module) if rest[encoded_end..].starts_with(DECORATION_POST) => {
+                output.push_str(&rest[..item_start]);
+                output.push_str(&module);
+                output.push_str("::");
+                output.push_str(&rest[item_start..index]);
+                rest = &rest[encoded_end + DECORATION_POST.len()..];
+            }
+            _ => {
+                output.push_str(&rest[..encoded_start]);
+                rest = &rest[encoded_start..];
+            }
+        }
+    }
+}
+
+/// Find the function declared around `offset`, matching the source declaration to the [Module].
+pub fn function_at(
+    cached: &CachedModule,
+    declarations: &[syntax::Declaration],
+    offset: usize,
+) -> Option<(FunctionRef, syntax::Declaration)> {
+    let declaration = declarations.iter().find(|d| {
+        matches!(d.kind, DeclarationKind::Function(_))
+            && d.range.start <= offset
+            && offset <= d.range.end
+    })?;
+    let function = function_by_name(&cached.module, &declaration.name)?;
+    Some((function, declaration.clone()))
+}
+
+pub fn function_by_name(module: &Module, name: &str) -> Option<FunctionRef> {
+    module
+        .functions
+        .iter()
+        .find(|(_, f)| f.name.as_deref() == Some(name))
+        .map(|(handle, _)| FunctionRef::Function(handle))
+        .or_else(|| {
+            module
+                .entry_points
+                .iter()
+                .position(|ep| ep.name == name)
+                .map(FunctionRef::EntryPoint)
+        })
+}
+
+/// Find a module-scope item by the name it has in the composed [Module].
+pub fn module_item_by_name(module: &Module, name: &str) -> Option<Symbol> {
+    if let Some(function) = function_by_name(module, name) {
+        return Some(match function {
+            FunctionRef::Function(handle) => Symbol::Function(handle),
+            FunctionRef::EntryPoint(index) => Symbol::EntryPoint(index),
+        });
+    }
+    let named = |n: &Option<String>| n.as_deref() == Some(name);
+    module
+        .types
+        .iter()
+        .find(|(_, t)| named(&t.name))
+        .map(|(h, _)| Symbol::Type(h))
+        .or_else(|| {
+            module
+                .global_variables
+                .iter()
+                .find(|(_, g)| named(&g.name))
+                .map(|(h, _)| Symbol::Global(h))
+        })
+        .or_else(|| {
+            module
+                .constants
+                .iter()
+                .find(|(_, c)| named(&c.name))
+                .map(|(h, _)| Symbol::Constant(h))
+        })
+}
+
+/// The name of a module-scope item as it appears in the composed [Module].
+pub fn item_name(module: &Module, symbol: Symbol) -> Option<&str> {
+    match symbol {
+        Symbol::Function(handle) => module.functions[handle].name.as_deref(),
+        Symbol::EntryPoint(index) => Some(module.entry_points[index].name.as_str()),
+        Symbol::Type(handle) | Symbol::Member(handle, _) => module.types[handle].name.as_deref(),
+        Symbol::Global(handle) => module.global_variables[handle].name.as_deref(),
+        Symbol::Constant(handle) => module.constants[handle].name.as_deref(),
+        _ => None,
+    }
+}
+
+/// Find a local variable, `let` binding, or argument visible at `offset` in the function.
+fn local_by_name(
+    cached: &CachedModule,
+    function: FunctionRef,
+    name: &str,
+    offset: usize,
+) -> Option<Symbol> {
+    let fun = function.get(&cached.module);
+    let position = cached.source_map.source_to_span(offset);
+    let start = |span: naga::Span| span.to_range().map_or(0, |r| r.start);
+
+    let locals = fun
+        .local_variables
+        .iter()
+        .filter(|(_, v)| v.name.as_deref() == Some(name))
+        .map(|(h, _)| {
+            (
+                start(fun.local_variables.get_span(h)),
+                Symbol::Local(function, h),
+            )
+        });
+    let bindings = fun
+        .named_expressions
+        .iter()
+        .filter(|(_, n)| n.as_str() == name)
+        .map(|(&h, _)| {
+            let symbol = match fun.expressions[h] {
+                Expression::FunctionArgument(index) => Symbol::Argument(function, index),
+                _ => Symbol::Let(function, h),
+            };
+            (start(fun.expressions.get_span(h)), symbol)
+        });
+    let candidates: Vec<_> = locals.chain(bindings).collect();
+
+    // Prefer the closest declaration before the cursor to approximate shadowing. A `let` span
+    // starts at its initializer, so the cursor can be on its name before the span starts.
+    candidates
+        .iter()
+        .filter(|(start, _)| *start <= position)
+        .max_by_key(|(start, _)| *start)
+        .or_else(|| candidates.iter().min_by_key(|(start, _)| *start))
+        .map(|(_, symbol)| *symbol)
+        .or_else(|| {
+            fun.arguments
+                .iter()
+                .position(|arg| arg.name.as_deref() == Some(name))
+                .map(|index| Symbol::Argument(function, index as u32))
+        })
+}
+
+/// A function in the [Module] with what's needed to resolve names in it, computed once so names
+/// can be resolved without going over the whole function for each.
+pub struct FunctionScope<'a> {
+    pub function: FunctionRef,
+    fun: &'a Function,
+    /// Types of the expressions, or [None] if naga couldn't resolve them.
+    typifier: Option<Typifier>,
+    /// Spans of the expressions, sorted by start.
+    spans: Vec<(Range<usize>, Handle<Expression>)>,
+}
+
+impl<'a> FunctionScope<'a> {
+    pub fn new(module: &'a Module, function: FunctionRef) -> Self {
+        let fun = function.get(module);
+        let ctx = ResolveContext::with_locals(module, &fun.local_variables, &fun.arguments);
+        let mut typifier = Typifier::new();
+        let typifier = match fun.expressions.iter().last() {
+            Some((last, _)) => typifier
+                .grow(last, &fun.expressions, &ctx)
+                .is_ok()
+                .then_some(typifier),
+            None => Some(typifier),
+        };
+        let mut spans: Vec<_> = fun
+            .expressions
+            .iter()
+            .filter_map(|(handle, _)| Some((fun.expressions.get_span(handle).to_range()?, handle)))
+            .collect();
+        spans.sort_by_key(|(range, _)| range.start);
+        Self {
+            function,
+            fun,
+            typifier,
+            spans,
+        }
+    }
+
+    /// Find the smallest expression in the function containing `offset`.
+    pub fn expression_at(
+        &self,
+        source_map: &SourceMap,
+        offset: usize,
+    ) -> Option<Handle<Expression>> {
+        let position = source_map.source_to_span(offset);
+        let started = self
+            .spans
+            .partition_point(|(range, _)| range.start <= position);
+        let mut smallest: Option<(usize, Handle<Expression>)> = None;
+        for (range, handle) in self.spans[..started].iter().rev() {
+            // anything starting further back that contains the offset is larger
+            if smallest.is_some_and(|(len, _)| position - range.start >= len) {
+                break;
+            }
+            let span = Span::new(range.start as u32, range.end as u32);
+            if source_map.span_contains(span, offset)
+                && smallest.is_none_or(|(len, _)| range.len() <= len)
+            {
+                smallest = Some((range.len(), *handle));
+            }
+        }
+        smallest.map(|(_, handle)| handle)
+    }
+
+    /// The type of an expression in the function.
+    pub fn expression_type(
+        &self,
+        handle: Handle<Expression>,
+    ) -> Option<&naga::proc::TypeResolution> {
+        Some(&self.typifier.as_ref()?[handle])
+    }
+}
+
+/// Resolve the symbol under the cursor, returning the range of the name that refers to it.
+pub fn resolve(cached: &CachedModule, offset: usize) -> Option<(Range<usize>, Symbol)> {
+    Resolver::new(cached).resolve(offset)
+}
+
+/// Resolves names in the source of a [CachedModule], parsing the source once for many lookups.
+pub struct Resolver<'a> {
+    cached: &'a CachedModule,
+    pub tokens: Vec<Token>,
+    pub declarations: Vec<syntax::Declaration>,
+    pub imports: Vec<syntax::Import>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(cached: &'a CachedModule) -> Self {
+        let tokens = tokenize(&cached.source);
+        let declarations = syntax::declarations(&cached.source, &tokens);
+        let imports = syntax::imports(&cached.source, &tokens);
+        Self {
+            cached,
+            tokens,
+            declarations,
+            imports,
+        }
+    }
+
+    /// Resolve the symbol at `offset`, returning the range of the name that refers to it.
+    pub fn resolve(&self, offset: usize) -> Option<(Range<usize>, Symbol)> {
+        let cached = self.cached;
+        let function = function_at(cached, &self.declarations, offset)
+            .map(|(function, _)| FunctionScope::new(&cached.module, function));
+        self.resolve_in(function.as_ref(), offset)
+    }
+
+    /// Like [Resolver::resolve], with the function declared around `offset` already looked up.
+    pub fn resolve_in(
+        &self,
+        function: Option<&FunctionScope>,
+        offset: usize,
+    ) -> Option<(Range<usize>, Symbol)> {
+        let cached = self.cached;
+        let path = path_at(&cached.source, &self.tokens, offset)?;
+        let (name, name_range) = path.segments[path.active].clone();
+
+        if path.is_member {
+            let scope = function?;
+            let handle = scope.expression_at(&cached.source_map, offset)?;
+            if let Expression::AccessIndex { base, index } = scope.fun.expressions[handle] {
+                let ty = scope.expression_type(base)?;
+                if let Some(ty) = struct_type(&cached.module, ty.clone()) {
+                    return Some((name_range, Symbol::Member(ty, index)));
+                }
+            }
+            return Some((name_range, Symbol::Expression(scope.function, handle)));
+        }
+
+        // only the last segment of a path names an item
+        if path.active + 1 != path.segments.len() {
+            return None;
+        }
+
+        if path.segments.len() == 1 {
+            // struct member declarations aren't in any arena we can search by position
+            let member = self
+                .declarations
+                .iter()
+                .filter(|d| d.kind == DeclarationKind::Struct)
+                .find_map(|d| {
+                    let index = d.children.iter().position(|m| m.name_range == name_range)?;
+                    match module_item_by_name(&cached.module, &d.name)? {
+                        Symbol::Type(ty) => Some(Symbol::Member(ty, index as u32)),
+                        _ => None,
+                    }
+                });
+            if let Some(symbol) = member {
+                return Some((name_range, symbol));
+            }
+            if let Some(scope) = function {
+                if let Some(symbol) = local_by_name(cached, scope.function, &name, offset) {
+                    return Some((name_range, symbol));
+                }
+            }
+        }
+
+        let full_path = resolve_import_path(&self.imports, &path.text());
+        module_item_by_name(&cached.module, &module_item_name(&full_path))
+            .map(|symbol| (path.range(), symbol))
+    }
+}
+
+pub fn stage_name(stage: ShaderStage) -> &'static str {
+    match stage {
+        ShaderStage::Vertex => "vertex",
+        ShaderStage::Fragment => "fragment",
+        ShaderStage::Compute => "compute",
+    }
+}
+
+/// The type of an expression, resolved with naga's typifier.
+pub fn expression_type(
+    module: &Module,
+    fun: &Function,
+    handle: Handle<Expression>,
+) -> Option<naga::proc::TypeResolution> {
+    let mut typifier = Typifier::new();
+    let ctx = ResolveContext::with_locals(module, &fun.local_variables, &fun.arguments);
+    typifier.grow(handle, &fun.expressions, &ctx).ok()?;
+    Some(typifier[handle].clone())
+}
+
+/// The struct behind a type resolution, looking through pointers.
+pub fn struct_type(module: &Module, ty: naga::proc::TypeResolution) -> Option<Handle<Type>> {
+    let handle = match ty {
+        naga::proc::TypeResolution::Handle(handle) => handle,
+        naga::proc::TypeResolution::Value(TypeInner::Pointer { base, .. }) => base,
+        naga::proc::TypeResolution::Value(_) => return None,
+    };
+    match module.types[handle].inner {
+        TypeInner::Struct { .. } => Some(handle),
+        TypeInner::Pointer { base, .. } => {
+            struct_type(module, naga::proc::TypeResolution::Handle(base))
+        }
+        _ => None,
+    }
+}
+
+/// Format a type the way it's written in WGSL, with imported names undecorated.
+pub fn type_name(module: &Module, ty: Handle<Type>) -> String {
+    undecorate(&ty.to_wgsl(&module.to_ctx()))
+}
+
+pub fn resolution_name(module: &Module, ty: &naga::proc::TypeResolution) -> String {
+    undecorate(&ty.to_wgsl(&module.to_ctx()))
+}
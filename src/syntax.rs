@@ -0,0 +1,764 @@
+use std::ops::Range;
+
+use naga::ShaderStage;
+
+use crate::{
+    lexer::{is_ident_continue, next_significant, tokenize, Token, TokenKind},
+    syntax_tree::SyntaxTree,
+};
+
+/// A name brought into scope by an `#import` directive.
+#[derive(Debug, Clone)]
+pub struct Import {
+    /// The name the item is referred to by in this file. This is the alias if there is one,
+    /// otherwise the last path segment.
+    pub name: String,
+    /// Full path of the imported item, e.g. `bevy_pbr::mesh_functions::get_model_matrix`.
+    /// This is a module path if the directive imports a whole module.
+    pub path: String,
+    /// Every segment of `path` and where it was written, which may be inside an import list.
+    pub segments: Vec<(String, Range<usize>)>,
+    pub alias: Option<(String, Range<usize>)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeclarationKind {
+    Function(Option<ShaderStage>),
+    Struct,
+    Member,
+    Argument,
+    Constant,
+    Override,
+    Global,
+    Alias,
+    /// `let`, `var`, or `const` declared inside a function body.
+    Local,
+}
+
+#[derive(Debug, Clone)]
+pub struct Declaration {
+    pub kind: DeclarationKind,
+    pub name: String,
+    pub name_range: Range<usize>,
+    /// Full range of the declaration, including attributes and the body.
+    pub range: Range<usize>,
+    /// Members of structs, or arguments followed by locals for functions.
+    pub children: Vec<Declaration>,
+}
+
+/// Whether the token at `index` is a `#` that starts a preprocessor directive.
+pub fn is_directive_start(source: &str, tokens: &[Token], index: usize) -> bool {
+    if !tokens[index].is_punct(source, "#") {
+        return false;
+    }
+    let line_start = source[..tokens[index].range.start]
+        .rfind('\n')
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    source[line_start..tokens[index].range.start]
+        .trim()
+        .is_empty()
+}
+
+/// Index of the last token of the directive starting at `index`.
+/// Import directives continue past the end of the line while braces are open.
+pub fn directive_end(source: &str, tokens: &[Token], index: usize) -> usize {
+    let mut depth = 0i32;
+    let mut end = index;
+    for (i, token) in tokens.iter().enumerate().skip(index) {
+        if token.kind == TokenKind::Whitespace && token.text(source).contains('\n') && depth <= 0 {
+            break;
+        }
+        if token.is_punct(source, "{") {
+            depth += 1;
+        } else if token.is_punct(source, "}") {
+            depth -= 1;
+        }
+        end = i;
+    }
+    end
+}
+
+/// Index of the token closing the bracket opened at `open`, or the last token if it's unclosed.
+pub fn matching_bracket(source: &str, tokens: &[Token], open: usize) -> usize {
+    let (open_text, close_text) = match tokens[open].text(source) {
+        "(" => ("(", ")"),
+        "[" => ("[", "]"),
+        _ => ("{", "}"),
+    };
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        if token.is_punct(source, open_text) {
+            depth += 1;
+        } else if token.is_punct(source, close_text) {
+            depth -= 1;
+            if depth == 0 {
+                return i;
+            }
+        }
+    }
+    tokens.len().saturating_sub(1)
+}
+
+/// Parse every `#import` directive in the source.
+///
+/// This follows the same rules as naga_oil's `parse_imports`, but keeps track of where each
+/// path segment was written.
+pub fn imports(source: &str, tokens: &[Token]) -> Vec<Import> {
+    let mut imports = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if is_directive_start(source, tokens, i) {
+            let end = directive_end(source, tokens, i);
+            let keyword = next_significant(tokens, i).filter(|&k| k <= end);
+            if let Some(keyword) = keyword.filter(|&k| tokens[k].is_ident(source, "import")) {
+                parse_import_tree(source, &tokens[keyword + 1..=end], &mut imports);
+            }
+            i = end + 1;
+        } else {
+            i += 1;
+        }
+    }
+    imports
+}
+
+fn parse_import_tree(source: &str, tokens: &[Token], imports: &mut Vec<Import>) {
+    // each stack entry is the path prefix that was open when a `{` was encountered
+    let mut stack: Vec<Vec<(String, Range<usize>)>> = Vec::new();
+    let mut current: Vec<(String, Range<usize>)> = Vec::new();
+    let mut alias = None;
+    let mut expect_alias = false;
+
+    let mut finish = |prefix: &[Vec<(String, Range<usize>)>],
+                      current: &mut Vec<(String, Range<usize>)>,
+                      alias: &mut Option<(String, Range<usize>)>| {
+        if current.is_empty() {
+            return;
+        }
+        let segments: Vec<_> = prefix
+            .iter()
+            .flatten()
+            .chain(current.iter())
+            .cloned()
+            .collect();
+        let path = segments
+            .iter()
+            .map(|(s, _)| s.as_str())
+            .collect::<Vec<_>>()
+            .join("::");
+        let alias = alias.take();
+        let name = match &alias {
+            Some((name, _)) => name.clone(),
+            None => current.last().unwrap().0.clone(),
+        };
+        imports.push(Import {
+            name,
+            path,
+            segments,
+            alias,
+        });
+        current.clear();
+    };
+
+    for token in tokens.iter().filter(|t| !t.is_trivia()) {
+        let text = token.text(source);
+        match token.kind {
+            TokenKind::Ident if expect_alias => {
+                alias = Some((text.to_owned(), token.range.clone()));
+                expect_alias = false;
+            }
+            TokenKind::Ident if text == "as" => expect_alias = true,
+            TokenKind::Ident | TokenKind::String => {
+                current.push((text.to_owned(), token.range.clone()));
+            }
+            TokenKind::Punct => match text {
+                "::" => {}
+                "{" => {
+                    stack.push(std::mem::take(&mut current));
+                }
+                "," => finish(&stack, &mut current, &mut alias),
+                "}" => {
+                    finish(&stack, &mut current, &mut alias);
+                    stack.pop();
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    finish(&stack, &mut current, &mut alias);
+}
+
+/// Join path segments the way naga_oil names modules, without quotes around file paths.
+pub fn join_path(segments: &[(String, Range<usize>)]) -> String {
+    segments
+        .iter()
+        .map(|(segment, _)| segment.trim_matches('"'))
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+/// Ranges of every preprocessor directive in the source.
+pub fn directives(source: &str, tokens: &[Token]) -> Vec<Range<usize>> {
+    let mut directives = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if is_directive_start(source, tokens, i) {
+            let end = directive_end(source, tokens, i);
+            directives.push(tokens[i].range.start..tokens[end].range.end);
+            i = end + 1;
+        } else {
+            i += 1;
+        }
+    }
+    directives
+}
+
+/// The module name given by `#define_import_path` and where it was written.
+pub fn define_import_path(source: &str, tokens: &[Token]) -> Option<(String, Range<usize>)> {
+    let mut i = 0;
+    while i < tokens.len() {
+        if is_directive_start(source, tokens, i) {
+            let end = directive_end(source, tokens, i);
+            let keyword = next_significant(tokens, i).filter(|&k| k <= end);
+            if keyword.is_some_and(|k| tokens[k].is_ident(source, "define_import_path")) {
+                let path: Vec<_> = (keyword.unwrap() + 1..=end)
+                    .filter(|&t| !tokens[t].is_trivia())
+                    .collect();
+                let (first, last) = (*path.first()?, *path.last()?);
+                let range = tokens[first].range.start..tokens[last].range.end;
+                return Some((source[range.clone()].to_owned(), range));
+            }
+            i = end + 1;
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+/// Module-scope declarations in the source, which doesn't need to be valid WGSL.
+pub fn declarations(source: &str, tokens: &[Token]) -> Vec<Declaration> {
+    SyntaxTree::from_tokens(source, tokens.to_vec()).declarations(source)
+}
+
+/// Skip an optional template list like `<uniform>` or `<storage, read_write>` starting after `index`.
+fn skip_template(source: &str, tokens: &[Token], index: usize) -> usize {
+    match next_significant(tokens, index) {
+        Some(open) if tokens[open].is_punct(source, "<") => {
+            let mut depth = 0;
+            for (i, token) in tokens.iter().enumerate().skip(open) {
+                if token.is_punct(source, "<") {
+                    depth += 1;
+                } else if token.is_punct(source, ">") {
+                    depth -= 1;
+                    if depth == 0 {
+                        return i;
+                    }
+                }
+            }
+            index
+        }
+        _ => index,
+    }
+}
+
+/// Index of the first `;` at the current nesting depth after `index`, or the last token.
+fn statement_end(source: &str, tokens: &[Token], index: usize) -> usize {
+    let mut depth = 0i32;
+    for (i, token) in tokens.iter().enumerate().skip(index) {
+        match token.text(source) {
+            "(" | "[" | "{" if token.kind == TokenKind::Punct => depth += 1,
+            ")" | "]" | "}" if token.kind == TokenKind::Punct => {
+                depth -= 1;
+                if depth < 0 {
+                    return i.saturating_sub(1);
+                }
+            }
+            ";" if token.kind == TokenKind::Punct && depth == 0 => return i,
+            _ => {}
+        }
+    }
+    tokens.len().saturating_sub(1)
+}
+
+/// Scan `let`, `var`, and `const` declarations inside a function body.
+pub fn scan_locals(source: &str, tokens: &[Token], open: usize, close: usize) -> Vec<Declaration> {
+    let mut locals = Vec::new();
+    for i in open + 1..close {
+        let token = &tokens[i];
+        if token.kind != TokenKind::Ident || !matches!(token.text(source), "let" | "var" | "const")
+        {
+            continue;
+        }
+        let name = match token.text(source) {
+            "var" => next_significant(tokens, skip_template(source, tokens, i)),
+            _ => next_significant(tokens, i),
+        };
+        if let Some(name) = name.filter(|&n| n < close && tokens[n].kind == TokenKind::Ident) {
+            let end = statement_end(source, tokens, name).min(close);
+            locals.push(Declaration {
+                kind: DeclarationKind::Local,
+                name: tokens[name].text(source).to_owned(),
+                name_range: tokens[name].range.clone(),
+                range: token.range.start..tokens[end].range.end,
+                children: Vec::new(),
+            });
+        }
+    }
+    locals
+}
+
+/// A source with a function taken out of it, see [stub_function].
+#[derive(Debug)]
+pub struct Stub {
+    pub source: String,
+    /// Functions that were stubbed or removed too, because they call a removed function.
+    pub dependents: Vec<String>,
+}
+
+/// Take the module-scope function whose body contains `offset` out of the source, keeping the
+/// offset of everything else in it.
+///
+/// This lets the rest of a module be checked when a function has an error. The body is replaced
+/// with one that only returns the zero value of the return type, or if the type has no zero value,
+/// the whole function is removed, and so are the bodies of the functions calling it. Returns [None]
+/// if `offset` isn't in a function body, or if the body has no room for the new one.
+pub fn stub_function(source: &str, tokens: &[Token], offset: usize) -> Option<Stub> {
+    let declarations = declarations(source, tokens);
+    let function = declarations
+        .iter()
+        .find(|d| matches!(d.kind, DeclarationKind::Function(_)) && d.range.contains(&offset))?;
+    let (_, open, close) = function_parts(source, tokens, function)?;
+    if offset < tokens[open].range.start || offset > tokens[close].range.end {
+        return None;
+    }
+
+    let (mut stub, removed) = stub_or_remove(source, tokens, &declarations, function);
+    let mut removed = Vec::from_iter(removed.then(|| function.name.clone()));
+    let mut dependents = Vec::new();
+    while let Some(name) = removed.pop() {
+        loop {
+            let tokens = tokenize(&stub);
+            let declarations = self::declarations(&stub, &tokens);
+            let Some(caller) = declarations
+                .iter()
+                .find(|d| calls(&stub, &tokens, d, &name))
+            else {
+                break;
+            };
+            let (caller_stub, caller_removed) =
+                stub_or_remove(&stub, &tokens, &declarations, caller);
+            if caller_stub == stub {
+                break;
+            }
+            stub = caller_stub;
+            dependents.push(caller.name.clone());
+            if caller_removed {
+                removed.push(caller.name.clone());
+            }
+        }
+    }
+    (stub != source).then_some(Stub {
+        source: stub,
+        dependents,
+    })
+}
+
+/// Replace the body of a function with one that only returns the zero value of its return type,
+/// or remove the whole function if there isn't one. Returns the new source and whether the
+/// function was removed.
+///
+/// The body is kept if the new one doesn't fit in its place.
+fn stub_or_remove(
+    source: &str,
+    tokens: &[Token],
+    declarations: &[Declaration],
+    function: &Declaration,
+) -> (String, bool) {
+    let Some((close_paren, open, close)) = function_parts(source, tokens, function) else {
+        return (blank(source, function.range.clone()), true);
+    };
+    let return_type = return_type(source, tokens, close_paren, open);
+    let statement = if return_type.is_empty() {
+        Vec::new()
+    } else if has_zero_value(source, tokens, declarations, &return_type, 0) {
+        let mut statement = vec!["return"];
+        statement.extend(return_type);
+        statement.extend(["(", ")", ";"]);
+        statement
+    } else {
+        return (blank(source, function.range.clone()), true);
+    };
+    let body = tokens[open].range.end..tokens[close].range.start;
+    match replace_body(source, body, &statement) {
+        Some(stub) => (stub, false),
+        None => (source.to_owned(), false),
+    }
+}
+
+/// Indices of the `)` closing the parameters of a function, and of the braces around its body.
+fn function_parts(
+    source: &str,
+    tokens: &[Token],
+    function: &Declaration,
+) -> Option<(usize, usize, usize)> {
+    let open_paren = (0..tokens.len()).find(|&n| {
+        tokens[n].range.start >= function.name_range.end && tokens[n].is_punct(source, "(")
+    })?;
+    let close_paren = matching_bracket(source, tokens, open_paren);
+    let open = (close_paren + 1..tokens.len()).find(|&n| tokens[n].is_punct(source, "{"))?;
+    Some((close_paren, open, matching_bracket(source, tokens, open)))
+}
+
+/// The return type written between the parameters and the body of a function, without
+/// attributes like `@location(0)`, or nothing if it doesn't return anything.
+fn return_type<'a>(
+    source: &'a str,
+    tokens: &[Token],
+    close_paren: usize,
+    open: usize,
+) -> Vec<&'a str> {
+    let mut return_type = Vec::new();
+    let mut arrow = false;
+    let mut i = close_paren + 1;
+    while i < open {
+        let token = &tokens[i];
+        if token.is_punct(source, "->") {
+            arrow = true;
+        } else if arrow && token.is_punct(source, "@") {
+            i = next_significant(tokens, i).unwrap_or(i);
+            if let Some(paren) =
+                next_significant(tokens, i).filter(|&n| tokens[n].is_punct(source, "("))
+            {
+                i = matching_bracket(source, tokens, paren);
+            }
+        } else if arrow && !token.is_trivia() {
+            return_type.push(token.text(source));
+        }
+        i += 1;
+    }
+    return_type
+}
+
+/// Whether `T()` makes a value of a type: scalars, vectors, matrices, fixed-size arrays of them,
+/// and structs of them, through any number of aliases.
+///
+/// Types from other modules are assumed not to have a zero value, since they aren't declared here.
+fn has_zero_value(
+    source: &str,
+    tokens: &[Token],
+    declarations: &[Declaration],
+    ty: &[&str],
+    depth: usize,
+) -> bool {
+    let Some((name, arguments)) = split_type(ty) else {
+        return false;
+    };
+    // declarations can only refer to each other in a cycle when the source is invalid
+    if depth > declarations.len() {
+        return false;
+    }
+    let has_zero_value = |ty: &[&str]| has_zero_value(source, tokens, declarations, ty, depth + 1);
+    match name {
+        "bool" | "i32" | "u32" | "f32" | "f16" => arguments.is_empty(),
+        "array" => arguments.len() == 2 && has_zero_value(arguments[0]),
+        _ if is_vector_or_matrix(name) => true,
+        _ => match declarations.iter().find(|d| d.name == name) {
+            Some(d) if d.kind == DeclarationKind::Struct => {
+                arguments.is_empty()
+                    && d.children
+                        .iter()
+                        .all(|member| has_zero_value(&declared_type(source, tokens, member)))
+            }
+            Some(d) if d.kind == DeclarationKind::Alias => {
+                arguments.is_empty() && has_zero_value(&declared_type(source, tokens, d))
+            }
+            _ => false,
+        },
+    }
+}
+
+/// Split a type like `array<f32, 4>` into its name and template arguments, or [None] if it's
+/// something else, like a path to a type in another module.
+fn split_type<'a, 'b>(ty: &'b [&'a str]) -> Option<(&'a str, Vec<&'b [&'a str]>)> {
+    let (&name, rest) = ty.split_first()?;
+    let inner = match rest {
+        [] => return Some((name, Vec::new())),
+        ["<", inner @ .., ">"] => inner,
+        _ => return None,
+    };
+    let mut arguments = Vec::new();
+    let (mut start, mut depth) = (0, 0);
+    for (i, &token) in inner.iter().enumerate() {
+        match token {
+            "<" | "(" => depth += 1,
+            ">" | ")" => depth -= 1,
+            "," if depth == 0 => {
+                arguments.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    // a trailing comma doesn't start another argument
+    if start < inner.len() {
+        arguments.push(&inner[start..]);
+    }
+    Some((name, arguments))
+}
+
+/// Whether a type name is a vector or matrix, like `vec3`, `vec4f`, or `mat4x4h`.
+fn is_vector_or_matrix(name: &str) -> bool {
+    let size = |c: &u8| (b'2'..=b'4').contains(c);
+    match name.as_bytes() {
+        [b'v', b'e', b'c', n, suffix @ ..] => {
+            size(n) && matches!(suffix, [] | [b'f' | b'h' | b'i' | b'u'])
+        }
+        [b'm', b'a', b't', c, b'x', r, suffix @ ..] => {
+            size(c) && size(r) && matches!(suffix, [] | [b'f' | b'h'])
+        }
+        _ => false,
+    }
+}
+
+/// The type after the name of a struct member or alias, like `vec4<f32>` in `color: vec4<f32>`.
+fn declared_type<'a>(source: &'a str, tokens: &[Token], declaration: &Declaration) -> Vec<&'a str> {
+    let mut ty = Vec::new();
+    let mut depth = 0;
+    let significant = tokens.iter().filter(|t| {
+        !t.is_trivia()
+            && t.range.start >= declaration.name_range.end
+            && t.range.end <= declaration.range.end
+    });
+    // the first token is the `:` or `=` after the name
+    for token in significant.skip(1) {
+        match token.text(source) {
+            "<" | "(" => depth += 1,
+            ">" | ")" => depth -= 1,
+            ";" | "," if depth == 0 => break,
+            _ => {}
+        }
+        ty.push(token.text(source));
+    }
+    ty
+}
+
+/// Whether the body of a function calls the function `name`.
+fn calls(source: &str, tokens: &[Token], function: &Declaration, name: &str) -> bool {
+    matches!(function.kind, DeclarationKind::Function(_))
+        && tokens.iter().enumerate().any(|(i, token)| {
+            token.range.start >= function.name_range.end
+                && token.range.end <= function.range.end
+                && token.is_ident(source, name)
+                && next_significant(tokens, i).is_some_and(|n| tokens[n].is_punct(source, "("))
+        })
+}
+
+/// Replace the source in `body` with the tokens of `statement`, keeping line breaks so that line
+/// numbers stay the same. Tokens are split across lines where one doesn't have room for them all.
+/// Returns [None] if the body doesn't have room for the statement.
+fn replace_body(source: &str, body: Range<usize>, statement: &[&str]) -> Option<String> {
+    let mut stub = blank(source, body.clone());
+    let mut lines = source[body.clone()]
+        .split_inclusive('\n')
+        .scan(body.start, |start, line| {
+            let text = *start..*start + line.trim_end_matches(['\n', '\r']).len();
+            *start += line.len();
+            Some(text)
+        });
+    let mut line = body.start..body.start;
+    // whether the last token placed on the line ends with a character that can't touch the next
+    let mut after_word = false;
+    for token in statement {
+        loop {
+            let separator = usize::from(after_word && token.starts_with(is_ident_continue));
+            if line.len() >= separator + token.len() {
+                let start = line.start + separator;
+                stub.replace_range(start..start + token.len(), token);
+                line.start = start + token.len();
+                break;
+            }
+            line = lines.next()?;
+            after_word = false;
+        }
+        after_word = token.ends_with(is_ident_continue);
+    }
+    Some(stub)
+}
+
+/// Replace `range` of the source with spaces, keeping line breaks and the offset of everything
+/// after it.
+fn blank(source: &str, range: Range<usize>) -> String {
+    let mut blank = String::with_capacity(source.len());
+    blank.push_str(&source[..range.start]);
+    for line in source[range.clone()].split_inclusive('\n') {
+        let text = line.trim_end_matches(['\n', '\r']);
+        blank.push_str(&" ".repeat(text.len()));
+        blank.push_str(&line[text.len()..]);
+    }
+    blank.push_str(&source[range.end..]);
+    blank
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stub the function containing `error` in `source`.
+    fn stub(source: &str, error: &str) -> Stub {
+        let offset = source.find(error).unwrap();
+        stub_function(source, &tokenize(source), offset).unwrap()
+    }
+
+    /// The body of `function` in the stubbed source, without blank space.
+    fn stubbed_body(source: &str, function: &str) -> String {
+        let stub = stub(source, "error");
+        assert_eq!(stub.source.len(), source.len());
+        assert_eq!(stub.source.lines().count(), source.lines().count());
+        let start = stub.source.find(&format!("fn {function}")).unwrap();
+        let body = &stub.source[start..];
+        let body = &body[body.find('{').unwrap() + 1..body.find('}').unwrap()];
+        body.split_whitespace().collect()
+    }
+
+    fn removed(source: &str) -> bool {
+        stub(source, "error").source.trim().is_empty()
+    }
+
+    #[test]
+    fn scalars_vectors_and_matrices_are_stubbed() {
+        let returns = |ty: &str| {
+            stubbed_body(
+                &format!("fn f() -> {ty} {{\n    return error; // the stub goes here\n}}\n"),
+                "f",
+            )
+        };
+        assert_eq!(returns("f32"), "returnf32();");
+        assert_eq!(returns("bool"), "returnbool();");
+        assert_eq!(returns("vec4<f32>"), "returnvec4<f32>();");
+        assert_eq!(returns("vec3u"), "returnvec3u();");
+        assert_eq!(returns("mat4x4<f32>"), "returnmat4x4<f32>();");
+        assert_eq!(returns("mat2x3h"), "returnmat2x3h();");
+    }
+
+    #[test]
+    fn fixed_size_arrays_are_stubbed() {
+        let source =
+            "fn f() -> array<vec2<f32>, 4> {\n    return error; // the stub goes here\n}\n";
+        assert_eq!(stubbed_body(source, "f"), "returnarray<vec2<f32>,4>();");
+        assert!(removed(
+            "fn f() -> array<f32> {\n    return error; // the stub goes here\n}\n"
+        ));
+    }
+
+    #[test]
+    fn structs_are_stubbed_if_their_members_are() {
+        let source = "struct S { a: f32, b: array<u32, 2>, }\n\
+                      fn f() -> S {\n    return error; // the stub goes here\n}\n";
+        assert_eq!(stubbed_body(source, "f"), "returnS();");
+
+        let source = "struct S { a: f32, b: atomic<u32>, }\n\
+                      fn f() -> S {\n    return error; // the stub goes here\n}\n";
+        assert!(!stub(source, "error").source.contains("fn f"));
+    }
+
+    #[test]
+    fn aliases_are_followed() {
+        let source =
+            "alias A = vec4f;\nfn f() -> A {\n    return error; // the stub goes here\n}\n";
+        assert_eq!(stubbed_body(source, "f"), "returnA();");
+
+        let source = "alias A = ptr<function, f32>;\nfn f() -> A {\n    return error; // the stub goes here\n}\n";
+        assert!(!stub(source, "error").source.contains("fn f"));
+    }
+
+    #[test]
+    fn types_without_zero_values_remove_the_function() {
+        for ty in [
+            "ptr<function, f32>",
+            "atomic<u32>",
+            "texture_2d<f32>",
+            "sampler",
+            "other::Type",
+            "Unknown",
+        ] {
+            assert!(
+                removed(&format!(
+                    "fn f() -> {ty} {{\n    return error; // the stub goes here\n}}\n"
+                )),
+                "{ty}"
+            );
+        }
+    }
+
+    #[test]
+    fn attributes_on_the_return_type_are_skipped() {
+        let source = "@vertex\nfn f() -> @builtin(position) vec4<f32> {\n    return error; // the stub goes here\n}\n";
+        assert_eq!(stubbed_body(source, "f"), "returnvec4<f32>();");
+        let source = "@fragment\nfn f() {\n    error();\n}\n";
+        assert_eq!(stubbed_body(source, "f"), "");
+    }
+
+    #[test]
+    fn functions_calling_a_removed_function_are_stubbed() {
+        let source = "fn f() -> texture_2d<f32> {\n    return error; // the stub goes here\n}\n\
+                      fn g() -> f32 {\n    let t = f();\n    return 1.0;\n}\n\
+                      fn h() -> sampler {\n    return s;\n}\n\
+                      fn i() -> texture_2d<f32> {\n    return f();\n}\n\
+                      fn j() {\n    let t = i();\n}\n";
+        let stub = stub(source, "error");
+        let stubbed = stub.source.split_whitespace().collect::<Vec<_>>().join(" ");
+        assert_eq!(
+            stubbed,
+            "fn g() -> f32 { return f32(); } fn h() -> sampler { return s; } fn j() { }"
+        );
+        let mut dependents = stub.dependents;
+        dependents.sort();
+        assert_eq!(dependents, ["g", "i", "j"]);
+    }
+
+    #[test]
+    fn statements_are_split_across_lines_of_the_body() {
+        let source = "fn f() -> vec4<f32> {\n    let a = error;\n    return a;\n}\n";
+        let stub = stub(source, "error").source;
+        assert_eq!(
+            stub,
+            "fn f() -> vec4<f32> {\nreturn vec4<f32>()\n;            \n}\n"
+        );
+    }
+
+    #[test]
+    fn one_line_bodies_are_stubbed_in_place() {
+        let source = "fn f() -> mat4x4<f32> { return error * mat4x4<f32>(); }\n";
+        let stub = stub(source, "error").source;
+        assert_eq!(stub.len(), source.len());
+        assert_eq!(
+            stub.split_whitespace().collect::<Vec<_>>(),
+            [
+                "fn",
+                "f()",
+                "->",
+                "mat4x4<f32>",
+                "{return",
+                "mat4x4<f32>();",
+                "}"
+            ]
+        );
+    }
+
+    #[test]
+    fn bodies_without_room_are_kept() {
+        let source = "fn f() -> mat4x4<f32> { return error; }\n\
+                      fn g() -> f32 {\n    return f()[0][0];\n}\n";
+        let offset = source.find("error").unwrap();
+        assert!(stub_function(source, &tokenize(source), offset).is_none());
+    }
+
+    #[test]
+    fn errors_outside_function_bodies_are_not_stubbed() {
+        let source = "const error = 1;\nfn f() -> f32 {\n    return 1.0;\n}\n";
+        let offset = source.find("error").unwrap();
+        assert!(stub_function(source, &tokenize(source), offset).is_none());
+    }
+}
@@ -0,0 +1,648 @@
+use std::ops::Range;
+
+use naga::ShaderStage;
+
+use crate::{
+    lexer::{tokenize, Token, TokenKind},
+    syntax::{directive_end, is_directive_start, scan_locals, Declaration, DeclarationKind},
+};
+
+/// Keywords that start a module-scope item. Items missing their `;` end before the next one.
+const ITEM_KEYWORDS: [&str; 11] = [
+    "fn",
+    "struct",
+    "var",
+    "const",
+    "override",
+    "alias",
+    "virtual",
+    "enable",
+    "requires",
+    "diagnostic",
+    "const_assert",
+];
+
+/// Keywords that can only start a module-scope item, so blocks missing their `}` end before them.
+const BLOCK_END_KEYWORDS: [&str; 2] = ["fn", "struct"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    /// The whole document.
+    Root,
+    /// A preprocessor directive like `#ifdef` or `#import`, up to the end of its line.
+    Directive,
+    /// `@name` or `@name(...)`.
+    Attribute,
+    Function,
+    /// The parenthesized arguments of a function.
+    ArgumentList,
+    Argument,
+    /// `-> type` after the arguments of a function, with its attributes.
+    ReturnType,
+    Struct,
+    /// The braced members of a struct.
+    MemberList,
+    Member,
+    /// `const` at module scope.
+    Constant,
+    Override,
+    /// `var` at module scope.
+    Global,
+    Alias,
+    /// `enable`, `requires`, or `diagnostic`.
+    GlobalDirective,
+    ConstAssert,
+    /// A braced list of statements.
+    Block,
+    /// A statement in a block, including the blocks it contains, like those of an `if`.
+    Statement,
+    /// `let`, `var`, or `const` in a block.
+    LocalDeclaration,
+    /// Tokens that couldn't be parsed, skipped to recover.
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub enum Child {
+    Node(Node),
+    /// The index of a token in [SyntaxTree::tokens].
+    Token(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub kind: NodeKind,
+    /// From the start of the first token to the end of the last.
+    pub range: Range<usize>,
+    pub children: Vec<Child>,
+}
+
+impl Node {
+    pub fn child_nodes(&self) -> impl Iterator<Item = &Node> {
+        self.children.iter().filter_map(|child| match child {
+            Child::Node(node) => Some(node),
+            Child::Token(_) => None,
+        })
+    }
+
+    /// Indices of the tokens directly in this node, not in its child nodes.
+    pub fn child_tokens(&self) -> impl Iterator<Item = usize> + '_ {
+        self.children.iter().filter_map(|child| match child {
+            Child::Node(_) => None,
+            Child::Token(index) => Some(*index),
+        })
+    }
+
+    /// This node and every node in it, parents before their children.
+    pub fn descendants(&self) -> Vec<&Node> {
+        let mut nodes = vec![self];
+        let mut i = 0;
+        while i < nodes.len() {
+            let node = nodes[i];
+            nodes.splice(i + 1..i + 1, node.child_nodes());
+            i += 1;
+        }
+        nodes
+    }
+}
+
+/// A lossless syntax tree of WGSL with naga_oil directives. Every token of the source is in it,
+/// and parts that don't parse are kept as [NodeKind::Error] nodes, so it can be built from code
+/// that's being edited.
+#[derive(Debug, Clone)]
+pub struct SyntaxTree {
+    pub tokens: Vec<Token>,
+    pub root: Node,
+}
+
+impl SyntaxTree {
+    pub fn parse(source: &str) -> Self {
+        Self::from_tokens(source, tokenize(source))
+    }
+
+    pub fn from_tokens(source: &str, tokens: Vec<Token>) -> Self {
+        let mut parser = Parser {
+            source,
+            tokens: &tokens,
+            pos: 0,
+            stack: vec![(NodeKind::Root, Vec::new(), 0)],
+        };
+        parser.module();
+        let root = parser.finish_root();
+        Self { tokens, root }
+    }
+
+    /// The name of a declaration, which is its first identifier that isn't a keyword or in a
+    /// template list like `<uniform>`.
+    pub fn name(&self, source: &str, node: &Node) -> Option<usize> {
+        let mut template_depth = 0;
+        node.child_tokens()
+            .filter(|&t| !self.tokens[t].is_trivia())
+            .find(|&t| {
+                let token = &self.tokens[t];
+                match token.text(source) {
+                    "<" => template_depth += 1,
+                    ">" => template_depth -= 1,
+                    "fn" | "struct" | "var" | "let" | "const" | "override" | "alias"
+                    | "virtual" => {}
+                    _ => return template_depth == 0 && token.kind == TokenKind::Ident,
+                }
+                false
+            })
+    }
+
+    /// The identifiers of the attributes in a node, like `vertex` for `@vertex`.
+    pub fn attributes<'a>(&self, source: &'a str, node: &Node) -> Vec<&'a str> {
+        node.child_nodes()
+            .filter(|n| n.kind == NodeKind::Attribute)
+            .filter_map(|attribute| {
+                attribute
+                    .child_tokens()
+                    .find(|&t| self.tokens[t].kind == TokenKind::Ident)
+            })
+            .map(|t| self.tokens[t].text(source))
+            .collect()
+    }
+
+    /// Module-scope declarations, with the members of structs and the arguments and locals of
+    /// functions.
+    pub fn declarations(&self, source: &str) -> Vec<Declaration> {
+        self.root
+            .child_nodes()
+            .filter_map(|node| {
+                let kind = match node.kind {
+                    NodeKind::Function => DeclarationKind::Function(
+                        self.attributes(source, node)
+                            .into_iter()
+                            .find_map(|attribute| match attribute {
+                                "vertex" => Some(ShaderStage::Vertex),
+                                "fragment" => Some(ShaderStage::Fragment),
+                                "compute" => Some(ShaderStage::Compute),
+                                _ => None,
+                            }),
+                    ),
+                    NodeKind::Struct => DeclarationKind::Struct,
+                    NodeKind::Constant => DeclarationKind::Constant,
+                    NodeKind::Override => DeclarationKind::Override,
+                    NodeKind::Global => DeclarationKind::Global,
+                    NodeKind::Alias => DeclarationKind::Alias,
+                    _ => return None,
+                };
+                let mut declaration = self.declaration(source, node, kind)?;
+                declaration.children = self.child_declarations(source, node);
+                Some(declaration)
+            })
+            .collect()
+    }
+
+    fn declaration(&self, source: &str, node: &Node, kind: DeclarationKind) -> Option<Declaration> {
+        let name = self.name(source, node)?;
+        Some(Declaration {
+            kind,
+            name: self.tokens[name].text(source).to_owned(),
+            name_range: self.tokens[name].range.clone(),
+            range: node.range.clone(),
+            children: Vec::new(),
+        })
+    }
+
+    /// Members of a struct, or arguments followed by locals of a function.
+    fn child_declarations(&self, source: &str, node: &Node) -> Vec<Declaration> {
+        let mut children = Vec::new();
+        for child in node.child_nodes() {
+            match child.kind {
+                NodeKind::ArgumentList | NodeKind::MemberList => {
+                    children.extend(child.child_nodes().filter_map(|n| match n.kind {
+                        NodeKind::Argument => {
+                            self.declaration(source, n, DeclarationKind::Argument)
+                        }
+                        NodeKind::Member => self.declaration(source, n, DeclarationKind::Member),
+                        _ => None,
+                    }));
+                }
+                NodeKind::Block => {
+                    if let (Some(open), Some(close)) = (first_token(child), last_token(child)) {
+                        children.extend(scan_locals(source, &self.tokens, open, close));
+                    }
+                }
+                _ => {}
+            }
+        }
+        children
+    }
+}
+
+/// The index of the first token in a node.
+pub fn first_token(node: &Node) -> Option<usize> {
+    node.children.iter().find_map(|child| match child {
+        Child::Node(node) => first_token(node),
+        Child::Token(index) => Some(*index),
+    })
+}
+
+/// The index of the last token in a node.
+pub fn last_token(node: &Node) -> Option<usize> {
+    node.children.iter().rev().find_map(|child| match child {
+        Child::Node(node) => last_token(node),
+        Child::Token(index) => Some(*index),
+    })
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: &'a [Token],
+    /// The index of the next token.
+    pos: usize,
+    /// The nodes being built, with their children so far and the offset they start at.
+    stack: Vec<(NodeKind, Vec<Child>, usize)>,
+}
+
+impl Parser<'_> {
+    /// The index of the next significant token.
+    fn nth(&self) -> Option<usize> {
+        (self.pos..self.tokens.len()).find(|&i| !self.tokens[i].is_trivia())
+    }
+
+    fn text(&self, index: usize) -> &str {
+        self.tokens[index].text(self.source)
+    }
+
+    /// Whether the next significant token is the punctuation or identifier `text`.
+    fn at(&self, text: &str) -> bool {
+        self.nth().is_some_and(|i| {
+            matches!(self.tokens[i].kind, TokenKind::Punct | TokenKind::Ident)
+                && self.text(i) == text
+        })
+    }
+
+    fn at_any(&self, texts: &[&str]) -> bool {
+        texts.iter().any(|text| self.at(text))
+    }
+
+    fn at_directive(&self) -> bool {
+        self.nth()
+            .is_some_and(|i| is_directive_start(self.source, self.tokens, i))
+    }
+
+    /// Whether the next significant token starts a module-scope item.
+    fn at_item(&self) -> bool {
+        self.at_any(&ITEM_KEYWORDS) || self.at("@") || self.at_directive()
+    }
+
+    /// Whether the next significant token can only start a module-scope item, so it ends a block
+    /// that's missing its `}`. The only attribute statements can have is `@diagnostic`.
+    fn at_block_end(&self) -> bool {
+        let attribute = self.at("@")
+            && self.nth().is_some_and(|i| {
+                (i + 1..self.tokens.len())
+                    .find(|&n| !self.tokens[n].is_trivia())
+                    .is_none_or(|n| self.text(n) != "diagnostic")
+            });
+        attribute || self.at_any(&BLOCK_END_KEYWORDS)
+    }
+
+    fn push(&mut self, child: Child) {
+        self.stack.last_mut().unwrap().1.push(child);
+    }
+
+    fn eat_trivia(&mut self) {
+        while self.pos < self.tokens.len() && self.tokens[self.pos].is_trivia() {
+            self.push(Child::Token(self.pos));
+            self.pos += 1;
+        }
+    }
+
+    /// Add the next significant token to the current node, with the trivia before it.
+    fn bump(&mut self) {
+        self.eat_trivia();
+        if self.pos < self.tokens.len() {
+            self.push(Child::Token(self.pos));
+            self.pos += 1;
+        }
+    }
+
+    /// A point in the current node that a node can be started at later, after the trivia before
+    /// the next token.
+    fn checkpoint(&mut self) -> usize {
+        self.eat_trivia();
+        self.stack.last().unwrap().1.len()
+    }
+
+    fn start(&mut self, kind: NodeKind) {
+        let checkpoint = self.checkpoint();
+        self.start_at(checkpoint, kind);
+    }
+
+    /// Start a node containing the children added to the current node since `checkpoint`.
+    fn start_at(&mut self, checkpoint: usize, kind: NodeKind) {
+        let offset = self
+            .tokens
+            .get(self.pos)
+            .map_or(self.source.len(), |t| t.range.start);
+        let children = self.stack.last_mut().unwrap().1.split_off(checkpoint);
+        self.stack.push((kind, children, offset));
+    }
+
+    fn finish(&mut self) {
+        let (kind, children, start) = self.stack.pop().unwrap();
+        let node = self.node(kind, children, start);
+        self.push(Child::Node(node));
+    }
+
+    fn finish_root(mut self) -> Node {
+        self.eat_trivia();
+        let (kind, children, start) = self.stack.pop().unwrap();
+        self.node(kind, children, start)
+    }
+
+    fn node(&self, kind: NodeKind, children: Vec<Child>, start: usize) -> Node {
+        let child_range = |child: &Child| match child {
+            Child::Node(node) => node.range.clone(),
+            Child::Token(index) => self.tokens[*index].range.clone(),
+        };
+        let range = match (children.first(), children.last()) {
+            (Some(first), Some(last)) => child_range(first).start..child_range(last).end,
+            _ => start..start,
+        };
+        Node {
+            kind,
+            range,
+            children,
+        }
+    }
+
+    fn module(&mut self) {
+        while self.nth().is_some() {
+            self.item();
+        }
+    }
+
+    fn item(&mut self) {
+        if self.at_directive() {
+            self.directive();
+            return;
+        }
+        let checkpoint = self.checkpoint();
+        self.attributes();
+        let Some(keyword) = self.nth() else {
+            self.start_at(checkpoint, NodeKind::Error);
+            self.finish();
+            return;
+        };
+        let is_fn_modifier = matches!(self.text(keyword), "virtual" | "override")
+            && (keyword + 1..self.tokens.len())
+                .find(|&i| !self.tokens[i].is_trivia())
+                .is_some_and(|i| self.text(i) == "fn");
+        let kind = match self.text(keyword) {
+            _ if self.tokens[keyword].kind != TokenKind::Ident => NodeKind::Error,
+            _ if is_fn_modifier => NodeKind::Function,
+            "fn" => NodeKind::Function,
+            "struct" => NodeKind::Struct,
+            "var" => NodeKind::Global,
+            "const" => NodeKind::Constant,
+            "override" => NodeKind::Override,
+            "alias" => NodeKind::Alias,
+            "enable" | "requires" | "diagnostic" => NodeKind::GlobalDirective,
+            "const_assert" => NodeKind::ConstAssert,
+            _ => NodeKind::Error,
+        };
+        self.start_at(checkpoint, kind);
+        match kind {
+            NodeKind::Function => self.function(),
+            NodeKind::Struct => self.structure(),
+            NodeKind::Error => self.error(),
+            _ => self.until_semicolon(&ITEM_KEYWORDS),
+        }
+        self.finish();
+    }
+
+    fn directive(&mut self) {
+        let Some(start) = self.nth() else {
+            return;
+        };
+        let end = directive_end(self.source, self.tokens, start);
+        self.start(NodeKind::Directive);
+        while self.pos <= end {
+            self.push(Child::Token(self.pos));
+            self.pos += 1;
+        }
+        self.finish();
+    }
+
+    fn attributes(&mut self) {
+        while self.at("@") {
+            self.start(NodeKind::Attribute);
+            self.bump();
+            if self
+                .nth()
+                .is_some_and(|i| self.tokens[i].kind == TokenKind::Ident)
+            {
+                self.bump();
+            }
+            if self.at("(") {
+                self.group();
+            }
+            self.finish();
+        }
+    }
+
+    /// Bump a bracketed group and everything in it, up to the matching bracket.
+    fn group(&mut self) {
+        let mut depth = 0;
+        while let Some(i) = self.nth() {
+            match self.text(i) {
+                "(" | "[" | "{" => depth += 1,
+                ")" | "]" | "}" => depth -= 1,
+                _ => {}
+            }
+            self.bump();
+            if depth <= 0 {
+                return;
+            }
+        }
+    }
+
+    /// Skip tokens that aren't an item, up to the next one.
+    fn error(&mut self) {
+        self.bump();
+        let mut depth = 0;
+        while self.nth().is_some() && (depth > 0 || !self.at_item()) {
+            if self.at_any(&["(", "[", "{"]) {
+                depth += 1;
+            } else if self.at_any(&[")", "]", "}"]) {
+                depth = 0.max(depth - 1);
+            }
+            self.bump();
+        }
+    }
+
+    /// Bump tokens up to and including a `;`, stopping early at a `}` or one of `stop`.
+    fn until_semicolon(&mut self, stop: &[&str]) {
+        self.bump();
+        while self.nth().is_some() {
+            if self.at_directive() {
+                self.directive();
+                continue;
+            }
+            if self.at(";") {
+                self.bump();
+                return;
+            }
+            if self.at("}") || self.at_any(stop) || self.at("@") {
+                return;
+            }
+            self.bump();
+        }
+    }
+
+    /// Bump the tokens of a type up to one of `stop` outside of brackets, along with any
+    /// attributes before it.
+    fn type_until(&mut self, stop: &[&str]) {
+        self.attributes();
+        let mut depth = 0;
+        while self.nth().is_some() {
+            if self.at_directive() {
+                self.directive();
+                continue;
+            }
+            if depth == 0 && (self.at_any(stop) || self.at_any(&BLOCK_END_KEYWORDS)) {
+                return;
+            }
+            if self.at_any(&["(", "[", "<"]) {
+                depth += 1;
+            } else if self.at_any(&[")", "]", ">"]) {
+                depth = 0.max(depth - 1);
+            }
+            self.bump();
+        }
+    }
+
+    fn function(&mut self) {
+        while self.at_any(&["virtual", "override"]) {
+            self.bump();
+        }
+        self.bump();
+        if self
+            .nth()
+            .is_some_and(|i| self.tokens[i].kind == TokenKind::Ident)
+        {
+            self.bump();
+        }
+        if self.at("(") {
+            self.list(NodeKind::ArgumentList, NodeKind::Argument, ")");
+        }
+        if self.at("->") {
+            self.start(NodeKind::ReturnType);
+            self.bump();
+            self.type_until(&["{", ";"]);
+            self.finish();
+        }
+        if self.at("{") {
+            self.block();
+        }
+    }
+
+    fn structure(&mut self) {
+        self.bump();
+        if self
+            .nth()
+            .is_some_and(|i| self.tokens[i].kind == TokenKind::Ident)
+        {
+            self.bump();
+        }
+        if self.at("{") {
+            self.list(NodeKind::MemberList, NodeKind::Member, "}");
+        }
+        if self.at(";") {
+            self.bump();
+        }
+    }
+
+    /// A bracketed list of arguments or members separated by commas.
+    fn list(&mut self, kind: NodeKind, item: NodeKind, close: &str) {
+        self.start(kind);
+        self.bump();
+        while self.nth().is_some() {
+            if self.at(close) {
+                self.bump();
+                break;
+            }
+            if self.at_directive() {
+                self.directive();
+            } else if self.at(",") {
+                self.bump();
+            } else if self.at_any(&["{", ";"]) || self.at_any(&BLOCK_END_KEYWORDS) {
+                // the list isn't closed
+                break;
+            } else {
+                self.start(item);
+                self.type_until(&[",", close, "{", ";"]);
+                self.finish();
+            }
+        }
+        self.finish();
+    }
+
+    fn block(&mut self) {
+        self.start(NodeKind::Block);
+        self.bump();
+        while self.nth().is_some() {
+            if self.at("}") {
+                self.bump();
+                break;
+            }
+            if self.at_block_end() {
+                // the block isn't closed
+                break;
+            }
+            if self.at_directive() {
+                self.directive();
+            } else if self.at("{") {
+                self.block();
+            } else if self.at_any(&["let", "var", "const"]) {
+                self.start(NodeKind::LocalDeclaration);
+                self.until_semicolon(&BLOCK_END_KEYWORDS);
+                self.finish();
+            } else {
+                self.statement();
+            }
+        }
+        self.finish();
+    }
+
+    fn statement(&mut self) {
+        self.start(NodeKind::Statement);
+        let mut depth = 0;
+        let mut first = true;
+        while self.nth().is_some() {
+            if self.at_directive() {
+                self.directive();
+                continue;
+            }
+            // `;` is only in brackets in the header of a `for`, and braces never are
+            if depth == 0 && self.at(";") {
+                self.bump();
+                break;
+            }
+            if self.at("}") || (!first && self.at_block_end()) {
+                break;
+            }
+            if self.at("{") {
+                self.block();
+                // `if` blocks continue with `else`
+                if self.at("else") {
+                    continue;
+                }
+                break;
+            }
+            if self.at_any(&["(", "["]) {
+                depth += 1;
+            } else if self.at_any(&[")", "]"]) {
+                depth = 0.max(depth - 1);
+            }
+            self.bump();
+            first = false;
+        }
+        self.finish();
+    }
+}
@@ -1,8 +1,12 @@
-use std::str::FromStr;
+use std::{
+    collections::HashMap,
+    ops::{self, ControlFlow},
+    str::FromStr,
+};
 
 use lsp_types::{
-    notification::PublishDiagnostics, Diagnostic, DiagnosticRelatedInformation, Location, Position,
-    PublishDiagnosticsParams, Range, Url,
+    notification::PublishDiagnostics, Diagnostic, DiagnosticRelatedInformation, Location,
+    MessageType, NumberOrString, Position, PublishDiagnosticsParams, Range, Url,
 };
 use naga::Module;
 use naga_oil::compose::{
@@ -10,183 +14,457 @@ use naga_oil::compose::{
     NagaModuleDescriptor,
 };
 
-use crate::server::{NotifyResult, WgslServerState};
+use crate::{
+    edit_map::EditMap,
+    line_index::{LineIndex, PositionEncoding},
+    server::{NotifyResult, WgslServerState},
+    source_map::{SourceMap, SPAN_SHIFT},
+    symbol_index::SymbolIndex,
+};
+
+/// The lint code of errors in imported modules.
+const IMPORT_ERROR: &str = "import-error";
 
 #[derive(Debug)]
 pub struct CachedModule {
     pub module: Module,
+    /// The document source the module was built from.
+    pub source: String,
+    /// Maps spans in `module` back to offsets in `source`.
+    pub source_map: SourceMap,
     /// This will either be a plain name or a filepath
     /// depending on if the module contains #define_import_path.
     pub module_name: String,
-    /// Module names of dependencies.
-    pub dependencies: Vec<String>,
+    /// Every name in `source` that refers to a symbol.
+    pub index: SymbolIndex,
+    /// Edits made to the document since the module was built, for using the module in place of
+    /// the current one while it doesn't build.
+    pub edits: EditMap,
 }
 
 impl WgslServerState {
-    /// Preprocess a document and add it to module lookup.
+    /// Preprocess a document and add it to module lookup and the dependency graph.
     ///
     /// Returns the cloned source, module name, and dependencies.
     pub fn preprocess(&mut self, uri: &Url) -> (String, String, Vec<String>) {
         let document = self.open_documents.get(uri).unwrap();
         let source = document.source();
 
-        // from bevy_render Shader::preprocess
-        let (module_name, imports, _) = get_preprocessor_data(&source);
+        let (module_name, dependencies) = preprocessor_data(&source);
         let module_name = module_name.unwrap_or_else(|| uri.as_str().to_owned());
-        let dependencies = imports
-            .into_iter()
-            .map(|def| {
-                if def.import.starts_with('\"') {
-                    def.import
-                        .chars()
-                        .skip(1)
-                        .take_while(|c| *c != '\"')
-                        .collect()
-                } else {
-                    def.import
-                }
-            })
-            .collect();
 
-        self.module_lookup.insert(module_name.clone(), uri.clone());
+        let project = self.project_mut(uri);
+        project
+            .module_lookup
+            .insert(module_name.clone(), uri.clone());
+        project
+            .dependency_graph
+            .update(uri, module_name.clone(), dependencies.clone());
 
         (source, module_name, dependencies)
     }
 
+    /// The cached module for a document, if it was built from the document's current source.
+    pub fn current_module(&self, uri: &Url) -> Option<&CachedModule> {
+        let source = self.open_documents.get(uri)?.source();
+        self.cached_modules
+            .get(uri)
+            .filter(|cached| cached.source == source)
+    }
+
+    /// Documents in the project of `uri` that import the module directly.
+    pub fn dependents(&self, uri: &Url, module_name: &str) -> Vec<Url> {
+        self.project(uri)
+            .dependency_graph
+            .importers(module_name)
+            .cloned()
+            .collect()
+    }
+
+    /// Reproduce the preprocessed source that the composer parsed when building the module of the
+    /// document at `uri`.
+    pub fn preprocessed_source(&self, uri: &Url, module_name: &str, source: &str) -> String {
+        let project = self.project(uri);
+        let (_, _, defines) = get_preprocessor_data(source);
+        let mut defs = project.settings.shader_defs.clone();
+        defs.extend(defines);
+        let sanitized_source = project
+            .composer
+            .module_sets
+            .get(module_name)
+            .map_or(source, |set| set.sanitized_source.as_str());
+        self.preprocessor
+            .preprocess(sanitized_source, &defs, false)
+            .map(|output| output.preprocessed_source)
+            .unwrap_or_default()
+    }
+
+    /// Byte ranges of the lines of a document's source that the preprocessor leaves out under the
+    /// current shader defs, without their indentation.
+    ///
+    /// This only takes preprocessing, so it works for sources that don't build.
+    /// Directives and imports are left out of the preprocessed source too, but they're not inactive.
+    pub fn inactive_lines(&self, uri: &Url, source: &str) -> Vec<ops::Range<usize>> {
+        let (_, _, defines) = get_preprocessor_data(source);
+        let mut defs = self.project(uri).settings.shader_defs.clone();
+        defs.extend(defines);
+        let preprocessed = self
+            .preprocessor
+            .preprocess(source, &defs, false)
+            .map(|output| output.preprocessed_source)
+            .unwrap_or_default();
+        if preprocessed.is_empty() {
+            // preprocessing failed
+            return Vec::new();
+        }
+
+        let mut ranges = Vec::new();
+        let mut offset = 0;
+        // unclosed braces of a multi-line import
+        let mut import_braces = 0;
+        // the preprocessor outputs a line for every line of the source
+        for (line, output) in source.split_inclusive('\n').zip(preprocessed.lines()) {
+            let start = offset;
+            offset += line.len();
+            let text = line.trim_end_matches(['\n', '\r']);
+            let trimmed = text.trim_start();
+
+            let directive = import_braces > 0 || trimmed.starts_with('#');
+            if import_braces > 0 || trimmed.starts_with("#import") {
+                import_braces += trimmed.matches('{').count();
+                import_braces = import_braces.saturating_sub(trimmed.matches('}').count());
+            }
+            if !directive && !trimmed.is_empty() && output.trim().is_empty() {
+                ranges.push(start + text.len() - trimmed.len()..start + text.len());
+            }
+        }
+        ranges
+    }
+
     /// Add a module to the composer and validate it.
     ///
     /// This will also walk the dependencies and make sure they're added first, as required by the composer.
     pub fn add_module(&mut self, uri: &Url) -> Result<(), ValidationError> {
         let (source, module_name, dependencies) = self.preprocess(uri);
-        dependencies
-            .iter()
-            .map(|dep| {
-                if let Some(uri) = self.module_lookup.get(dep).cloned() {
-                    self.add_module(&uri)
-                } else {
-                    Err(import_error(uri.clone(), &source, dep))
-                }
-            })
-            .find(|r| r.is_err())
-            .unwrap_or(Ok(()))?; // propagate the first error
+        let version = self.document_version(uri);
+        self.add_dependencies(uri, &source, &dependencies)?;
 
-        match self
-            .composer
-            .add_composable_module(ComposableModuleDescriptor {
-                as_name: Some(module_name.clone()),
-                file_path: uri.as_str(),
-                source: &source,
-                ..Default::default()
-            }) {
+        let composer = &mut self.project_mut(uri).composer;
+        match composer.add_composable_module(ComposableModuleDescriptor {
+            as_name: Some(module_name.clone()),
+            file_path: uri.as_str(),
+            source: &source,
+            ..Default::default()
+        }) {
             Ok(_) => {
-                self.notify::<PublishDiagnostics>(PublishDiagnosticsParams {
-                    uri: uri.clone(),
-                    diagnostics: Vec::new(),
-                    version: None,
-                });
-            }
-            Err(err) => {
-                if Url::from_str(err.source.path(&self.composer)).unwrap() != *uri {
-                    self.notify::<PublishDiagnostics>(PublishDiagnosticsParams {
+                let _ = self.publish_diagnostics(
+                    PublishDiagnosticsParams {
                         uri: uri.clone(),
-                        diagnostics: vec![Diagnostic {
-                            range: Range::new(Position::new(0, 0), Position::new(0, 0)),
-                            message: format!("Error in module: {module_name}"),
-                            ..Default::default()
-                        }],
+                        diagnostics: Vec::new(),
                         version: None,
-                    });
-                }
-                self.notify::<PublishDiagnostics>(composer_error_to_diagnostic(
-                    err,
-                    &self.composer,
-                ));
+                    },
+                    version,
+                );
+            }
+            Err(err) => {
+                let composer = &self.project(uri).composer;
+                let error_uri = Url::from_str(err.source.path(composer)).unwrap();
+                let error_version = if error_uri == *uri {
+                    version
+                } else {
+                    let _ = self.publish_diagnostics(
+                        PublishDiagnosticsParams {
+                            uri: uri.clone(),
+                            diagnostics: vec![Diagnostic {
+                                range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+                                message: format!("Error in module: {module_name}"),
+                                code: Some(NumberOrString::String(IMPORT_ERROR.to_owned())),
+                                ..Default::default()
+                            }],
+                            version: None,
+                        },
+                        version,
+                    );
+                    self.document_version(&error_uri)
+                };
+                let composer = &self.project(uri).composer;
+                let _ = self.publish_diagnostics(
+                    composer_error_to_diagnostic(err, composer, self.position_encoding),
+                    error_version,
+                );
             }
         };
 
         Ok(())
     }
+
+    /// Add the modules a document imports to the composer, returning every import that isn't
+    /// found, or else the first error.
+    ///
+    /// Every dependency is added even if one fails, so the others can still be used.
+    fn add_dependencies(
+        &mut self,
+        uri: &Url,
+        source: &str,
+        dependencies: &[String],
+    ) -> Result<(), ValidationError> {
+        let mut missing = Vec::new();
+        let mut result = Ok(());
+        for dep in dependencies {
+            match self.project(uri).module_lookup.get(dep).cloned() {
+                Some(dep_uri) => {
+                    let dep_result = self.add_module(&dep_uri);
+                    if result.is_ok() {
+                        result = dep_result;
+                    }
+                }
+                None => missing.push(dep.as_str()),
+            }
+        }
+        if !missing.is_empty() {
+            return Err(imports_not_found(
+                uri.clone(),
+                source,
+                &missing,
+                self.position_encoding,
+            ));
+        }
+        result
+    }
+
+    /// Publish diagnostics computed from `version` of their document, attaching the version so
+    /// the client can order them. They're dropped if the document has changed since.
+    ///
+    /// Diagnostics are reported at the level configured for their code, if any.
+    pub fn publish_diagnostics(
+        &self,
+        mut params: PublishDiagnosticsParams,
+        version: Option<i32>,
+    ) -> NotifyResult {
+        if self.document_version(&params.uri) != version {
+            return ControlFlow::Continue(());
+        }
+        params.version = version;
+        let lints = &self.project(&params.uri).settings.lints;
+        params.diagnostics.retain_mut(|diagnostic| {
+            let Some(NumberOrString::String(code)) = &diagnostic.code else {
+                return true;
+            };
+            let Some(level) = lints.get(code) else {
+                return true;
+            };
+            diagnostic.severity = level.severity();
+            diagnostic.severity.is_some()
+        });
+        self.notify::<PublishDiagnostics>(params)
+    }
+}
+
+/// The module name declared by `#define_import_path`, if any, and the names of imported modules.
+fn preprocessor_data(source: &str) -> (Option<String>, Vec<String>) {
+    // from bevy_render Shader::preprocess
+    let (module_name, imports, _) = get_preprocessor_data(source);
+    let dependencies = imports
+        .into_iter()
+        .map(|def| {
+            if def.import.starts_with('\"') {
+                def.import
+                    .chars()
+                    .skip(1)
+                    .take_while(|c| *c != '\"')
+                    .collect()
+            } else {
+                def.import
+            }
+        })
+        .collect();
+    (module_name, dependencies)
 }
 
 #[derive(Debug)]
 pub enum ValidationError {
-    ComposerError(ComposerError),
-    ImportNotFound(Url, Range, String),
+    ComposerError(Box<ComposerError>),
+    /// Imports of a document that aren't registered, by the range and name of each.
+    ImportsNotFound(Url, Vec<(Range, String)>),
 }
 
 impl From<ComposerError> for ValidationError {
     fn from(err: ComposerError) -> Self {
-        ValidationError::ComposerError(err)
+        ValidationError::ComposerError(Box::new(err))
     }
 }
 
 /// https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#textDocument_publishDiagnostics
 /// TODO: https://github.com/gfx-rs/wgpu/issues/5295
+///
+/// The module is built right away, but it's validated on the [ValidationWorker](crate::validation_worker::ValidationWorker),
+/// which publishes the diagnostics when it's done.
 pub fn validate_document(st: &mut WgslServerState, uri: Url) -> NotifyResult {
     st.should_validate = true;
-    let diagnostics = match validate_document_inner(st, uri.clone()) {
-        Ok(_) => PublishDiagnosticsParams {
-            uri: uri.clone(),
-            diagnostics: Vec::new(),
-            version: None,
-        },
-        Err(err) => match err {
-            ValidationError::ComposerError(err) => composer_error_to_diagnostic(err, &st.composer),
-            ValidationError::ImportNotFound(uri, range, name) => PublishDiagnosticsParams {
-                uri,
-                diagnostics: vec![Diagnostic {
-                    range,
-                    message: format!("Import not found: {}", name),
-                    ..Default::default()
-                }],
-                version: None,
-            },
-        },
+    let version = st.document_version(&uri);
+    let (error_uri, imports) = match validate_document_inner(st, uri.clone()) {
+        Ok(_) => {
+            st.submit_validation(&uri, version);
+            return ControlFlow::Continue(());
+        }
+        // the worker builds the document from scratch, finding every error rather than just this one
+        Err(ValidationError::ComposerError(err)) => {
+            st.submit_validation(&uri, version);
+            return st.log(
+                MessageType::LOG,
+                &format!("Failed to build module {uri}: {}", err.inner),
+            );
+        }
+        Err(ValidationError::ImportsNotFound(error_uri, imports)) => (error_uri, imports),
     };
+    let diagnostics = PublishDiagnosticsParams {
+        uri: error_uri,
+        diagnostics: imports
+            .into_iter()
+            .map(|(range, name)| Diagnostic {
+                range,
+                message: format!("Import not found: {}", name),
+                code: Some(NumberOrString::String(IMPORT_ERROR.to_owned())),
+                ..Default::default()
+            })
+            .collect(),
+        version: None,
+    };
+    // validating the last module built would only report stale errors
+    st.cancel_validation(&uri);
+    st.publish_validation_diagnostics(&uri, version, vec![diagnostics])
+}
+
+impl WgslServerState {
+    /// Publish the errors found validating `version` of a document, clearing its diagnostics if
+    /// there are none.
+    ///
+    /// Errors in modules the document imports are published for those modules, and the document
+    /// gets an error pointing at the import.
+    pub fn publish_validation_diagnostics(
+        &self,
+        uri: &Url,
+        version: Option<i32>,
+        errors: Vec<PublishDiagnosticsParams>,
+    ) -> NotifyResult {
+        let mut diagnostics: HashMap<Url, Vec<Diagnostic>> =
+            HashMap::from([(uri.clone(), Vec::new())]);
+        for error in errors {
+            if error.uri != *uri {
+                let document = diagnostics.get_mut(uri).unwrap();
+                // a module with several errors only needs one on its import
+                if let Some(diagnostic) = self.import_diagnostic(uri, &error) {
+                    if !document.contains(&diagnostic) {
+                        document.push(diagnostic);
+                    }
+                }
+            }
+            diagnostics
+                .entry(error.uri)
+                .or_default()
+                .extend(error.diagnostics);
+        }
+
+        for (diagnostics_uri, diagnostics) in diagnostics {
+            let diagnostics_version = if diagnostics_uri == *uri {
+                version
+            } else {
+                self.document_version(&diagnostics_uri)
+            };
+            let params = PublishDiagnosticsParams {
+                uri: diagnostics_uri,
+                diagnostics,
+                version: None,
+            };
+            if let ControlFlow::Break(result) =
+                self.publish_diagnostics(params, diagnostics_version)
+            {
+                return ControlFlow::Break(result);
+            }
+        }
+        ControlFlow::Continue(())
+    }
 
-    if diagnostics.uri != uri {
-        let module_name = st
+    /// An error on the import of the module that `error` is in.
+    fn import_diagnostic(&self, uri: &Url, error: &PublishDiagnosticsParams) -> Option<Diagnostic> {
+        let (module_name, _) = self
+            .project(uri)
             .module_lookup
             .iter()
-            .find(|(_, &ref u)| *u == diagnostics.uri)
-            .unwrap()
-            .0;
-        let document = st.open_documents.get(&uri).unwrap();
-        let source = document.source();
-        let start = source.find(module_name).unwrap_or(0);
-        st.notify::<PublishDiagnostics>(PublishDiagnosticsParams {
-            uri: uri.clone(),
-            diagnostics: vec![Diagnostic {
-                range: calc_range(&source, start, start + module_name.len()),
-                message: format!("Error in module: {module_name}"),
-                ..Default::default()
-            }],
-            version: None,
-        });
+            .find(|(_, u)| **u == error.uri)?;
+        let source = self.open_documents.get(uri)?.source();
+        let start = source.find(module_name.as_str()).unwrap_or(0);
+        Some(Diagnostic {
+            range: self
+                .line_index(&source)
+                .range(start, start + module_name.len()),
+            message: format!("Error in module: {module_name}"),
+            code: Some(NumberOrString::String(IMPORT_ERROR.to_owned())),
+            // keep the permutation the error came from
+            source: error.diagnostics.first().and_then(|d| d.source.clone()),
+            ..Default::default()
+        })
+    }
+}
+
+/// Validate a changed document, then everything importing it under its old or new module name.
+pub fn validate_document_and_dependents(
+    st: &mut WgslServerState,
+    uri: Url,
+    old_module: Option<String>,
+) -> NotifyResult {
+    if let ControlFlow::Break(result) = validate_document(st, uri.clone()) {
+        return ControlFlow::Break(result);
+    }
+    let mut modules: Vec<String> = old_module.into_iter().collect();
+    let project = st.project_index(&uri);
+    if let Some(module) = st.projects[project].dependency_graph.module(&uri) {
+        if !modules.iter().any(|m| m == module) {
+            modules.push(module.to_owned());
+        }
     }
+    validate_dependents(st, project, &modules)
+}
 
-    st.notify::<PublishDiagnostics>(diagnostics)
+/// Re-validate every document of a project that imports one of the modules, directly or through
+/// other modules.
+///
+/// Documents open in the client go first, nearest importers first within each group.
+pub fn validate_dependents(
+    st: &mut WgslServerState,
+    project: usize,
+    module_names: &[String],
+) -> NotifyResult {
+    let mut dependents = st.projects[project]
+        .dependency_graph
+        .transitive_importers(module_names.iter().map(String::as_str));
+    // stable, so the order by distance is kept
+    dependents.sort_by_key(|uri| st.document_version(uri).is_none());
+    for uri in dependents {
+        if let ControlFlow::Break(result) = validate_document(st, uri) {
+            return ControlFlow::Break(result);
+        }
+    }
+    ControlFlow::Continue(())
 }
 
-fn validate_document_inner(st: &mut WgslServerState, uri: Url) -> Result<(), ValidationError> {
+/// Build and cache the module for a document without validating it or publishing diagnostics.
+pub fn validate_document_inner(st: &mut WgslServerState, uri: Url) -> Result<(), ValidationError> {
     let old_module_name = st.cached_modules.get(&uri).map(|m| m.module_name.clone());
     if let Some(old_module_name) = &old_module_name {
-        st.module_lookup.remove(old_module_name);
+        let project = st.project_mut(&uri);
+        project.module_lookup.remove(old_module_name);
         // this will remove all dependents as well
-        st.composer.remove_composable_module(old_module_name);
+        project.composer.remove_composable_module(old_module_name);
     }
 
     let (source, module_name, dependencies) = st.preprocess(&uri);
     let source = source.as_str();
-    for dep in &dependencies {
-        if let Some(uri) = st.module_lookup.get(dep).cloned() {
-            st.add_module(&uri)?;
-        } else {
-            return Err(import_error(uri, source, dep));
-        }
-    }
+    st.add_dependencies(&uri, source, &dependencies)?;
 
-    st.composer
+    let project = st.project_mut(&uri);
+    project
+        .composer
         .add_composable_module(ComposableModuleDescriptor {
             as_name: Some(module_name.clone()),
             file_path: uri.as_str(),
@@ -194,69 +472,63 @@ fn validate_document_inner(st: &mut WgslServerState, uri: Url) -> Result<(), Val
             ..Default::default()
         })?;
 
-    let module = st.composer.make_naga_module(NagaModuleDescriptor {
+    let module = project.composer.make_naga_module(NagaModuleDescriptor {
         source,
         file_path: uri.as_str(),
+        shader_defs: project.settings.shader_defs.clone(),
         ..Default::default()
     })?;
 
-    st.composer.validate = true;
-    // Use composer since it's the only one that knows the correct span positions to map the error
-    let validator_result = st.composer.make_naga_module(NagaModuleDescriptor {
+    let source_map = SourceMap::new(
         source,
-        file_path: uri.as_str(),
-        ..Default::default()
-    }); // Don't return early here so that we can still cache the possibly invalid module
-    st.composer.validate = false;
-
-    st.cached_modules.insert(
-        uri.clone(),
-        CachedModule {
-            module,
-            module_name,
-            dependencies,
-        },
+        &st.preprocessed_source(&uri, &module_name, source),
+        &module,
     );
-
-    validator_result?;
+    let mut cached = CachedModule {
+        module,
+        source: source.to_owned(),
+        source_map,
+        module_name,
+        index: SymbolIndex::default(),
+        edits: EditMap::default(),
+    };
+    cached.index = SymbolIndex::new(&cached);
+    st.cached_modules.insert(uri.clone(), cached);
 
     Ok(())
 }
 
-pub fn calc_position(source: &str, position: usize) -> Position {
-    let prefix = &source[..position];
-    let line_number = prefix.matches('\n').count() as u32;
-    let line_start = prefix.rfind('\n').map(|i| i + 1).unwrap_or(0);
-    let line_position = source[line_start..position].chars().count() as u32;
-
-    Position::new(line_number, line_position)
-}
-
-fn calc_range(source: &str, start: usize, end: usize) -> Range {
-    Range::new(calc_position(source, start), calc_position(source, end))
+fn imports_not_found(
+    uri: Url,
+    source: &str,
+    names: &[&str],
+    encoding: PositionEncoding,
+) -> ValidationError {
+    let index = LineIndex::new(source, encoding);
+    let imports = names
+        .iter()
+        .map(|name| {
+            let start = source.find(name).unwrap_or(0);
+            (index.range(start, start + name.len()), name.to_string())
+        })
+        .collect();
+    ValidationError::ImportsNotFound(uri, imports)
 }
 
-fn import_error(uri: Url, source: &str, name: &str) -> ValidationError {
-    let start = source.find(name).unwrap_or(0);
-    ValidationError::ImportNotFound(
-        uri,
-        calc_range(source, start, start + name.len()),
-        name.to_string(),
-    )
-}
-
-fn composer_error_to_diagnostic(
+/// Use the composer to map errors, since it's the only one that knows the correct span positions.
+pub fn composer_error_to_diagnostic(
     err: ComposerError,
     composer: &Composer,
+    encoding: PositionEncoding,
 ) -> PublishDiagnosticsParams {
     let source = err.source.source(composer);
+    let index = LineIndex::new(&source, encoding);
     let source_offset = err.source.offset();
 
     // https://github.com/bevyengine/naga_oil/issues/76
-    // 21 is the SPAN_SHIFT
     let map_span = |rng: core::ops::Range<usize>| -> core::ops::Range<usize> {
-        ((rng.start & ((1 << 21) - 1)).saturating_sub(source_offset))
-            ..((rng.end & ((1 << 21) - 1)).saturating_sub(source_offset))
+        ((rng.start & ((1 << SPAN_SHIFT) - 1)).saturating_sub(source_offset))
+            ..((rng.end & ((1 << SPAN_SHIFT) - 1)).saturating_sub(source_offset))
     };
 
     let uri = Url::from_str(err.source.path(composer)).unwrap();
@@ -272,17 +544,16 @@ fn composer_error_to_diagnostic(
 
     let simple_diagnostic = |range: core::ops::Range<usize>| -> Diagnostic {
         Diagnostic {
-            range: calc_range(&source, range.start, range.end),
+            range: index.range(range.start, range.end),
             message: message.clone(),
             ..Default::default()
         }
     };
 
     let diagnostic_with_labels = |labels: Vec<(core::ops::Range<usize>, String)>| -> Diagnostic {
-        let widest_label = labels
-            .iter()
-            .max_by(|a, b| a.0.len().cmp(&b.0.len()))
-            .unwrap();
+        let Some(widest_label) = labels.iter().max_by(|a, b| a.0.len().cmp(&b.0.len())) else {
+            return empty_diagnostic();
+        };
         let contained_label = labels.iter().find(|(rng, _)| {
             !rng.eq(&widest_label.0)
                 && widest_label.0.start <= rng.start
@@ -290,16 +561,13 @@ fn composer_error_to_diagnostic(
         });
         let (primary_rng, _) = contained_label.unwrap_or(widest_label);
         Diagnostic {
-            range: calc_range(&source, primary_rng.start, primary_rng.end),
+            range: index.range(primary_rng.start, primary_rng.end),
             message: message.clone(),
             related_information: Some(
                 labels
                     .into_iter()
                     .map(|(rng, extra)| DiagnosticRelatedInformation {
-                        location: Location::new(
-                            uri.clone(),
-                            calc_range(&source, rng.start, rng.end),
-                        ),
+                        location: Location::new(uri.clone(), index.range(rng.start, rng.end)),
                         message: extra,
                     })
                     .collect(),
@@ -359,7 +627,39 @@ fn composer_error_to_diagnostic(
 
     PublishDiagnosticsParams {
         uri,
-        diagnostics: vec![diagnostic],
+        diagnostics: vec![Diagnostic {
+            code: Some(NumberOrString::String(error_code(&err.inner).to_owned())),
+            ..diagnostic
+        }],
         version: None,
     }
 }
+
+/// The code of the lint an error is reported under.
+fn error_code(err: &ComposerErrorInner) -> &'static str {
+    match err {
+        ComposerErrorInner::ImportParseError(..)
+        | ComposerErrorInner::WgslParseError(..)
+        | ComposerErrorInner::GlslParseError(..)
+        | ComposerErrorInner::DecorationInSource(..)
+        | ComposerErrorInner::InvalidIdentifier { .. } => "parse-error",
+        ComposerErrorInner::ImportNotFound(..) | ComposerErrorInner::NoModuleName => IMPORT_ERROR,
+        ComposerErrorInner::NotEnoughEndIfs(..)
+        | ComposerErrorInner::TooManyEndIfs(..)
+        | ComposerErrorInner::ElseWithoutCondition(..)
+        | ComposerErrorInner::UnknownShaderDefOperator { .. }
+        | ComposerErrorInner::UnknownShaderDef { .. }
+        | ComposerErrorInner::InvalidShaderDefComparisonValue { .. }
+        | ComposerErrorInner::InconsistentShaderDefValue { .. }
+        | ComposerErrorInner::InvalidShaderDefDefinitionValue { .. }
+        | ComposerErrorInner::DefineInModule(..)
+        | ComposerErrorInner::GlslInvalidVersion(..) => "preprocessor-error",
+        ComposerErrorInner::HeaderValidationError(..)
+        | ComposerErrorInner::ShaderValidationError(..)
+        | ComposerErrorInner::RedirectError(..)
+        | ComposerErrorInner::OverrideNotVirtual { .. } => "validation-error",
+        ComposerErrorInner::WgslBackError(..) | ComposerErrorInner::GlslBackError(..) => {
+            "internal-error"
+        }
+    }
+}
@@ -0,0 +1,621 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    ops::ControlFlow,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+};
+
+use async_lsp::ClientSocket;
+use lsp_types::{NumberOrString, PublishDiagnosticsParams, Url};
+use naga::{
+    valid::{
+        Capabilities, ConstExpressionError, ExpressionError, GlobalVariableError, LiteralError,
+        TypeError, ValidationError, ValidationFlags, Validator, VaryingError,
+    },
+    WithSpan,
+};
+use naga_oil::compose::{
+    ComposableModuleDescriptor, Composer, ComposerError, ComposerErrorInner, ErrSource,
+    NagaModuleDescriptor,
+};
+use tokio::task::AbortHandle;
+
+use crate::{
+    document::OpenDocument,
+    lexer::tokenize,
+    line_index::{LineIndex, PositionEncoding},
+    project::Project,
+    server::{NotifyResult, ShaderDefs, WgslServerState},
+    settings::CapabilitiesProfile,
+    source_map::SPAN_SHIFT,
+    syntax,
+    validate::{composer_error_to_diagnostic, validate_document, validate_document_and_dependents},
+};
+
+/// The most errors reported for each permutation, since finding every one takes another build.
+const MAX_ERRORS: usize = 16;
+
+/// Runs naga validation on its own thread so it doesn't block the main loop.
+///
+/// Modules are built on the main loop, since every language feature needs them, and then sent
+/// here to be validated. The thread keeps a validating composer of its own, mirroring the modules
+/// of the server's composer that validated documents import.
+#[derive(Debug)]
+pub struct ValidationWorker {
+    jobs: Sender<ValidationJob>,
+    /// Debounce timers of documents that were edited but not validated yet.
+    pending: HashMap<Url, AbortHandle>,
+    /// Cancellation flags of jobs that were sent to the worker and haven't finished.
+    in_flight: HashMap<Url, Arc<AtomicBool>>,
+}
+
+struct ValidationJob {
+    uri: Url,
+    version: Option<i32>,
+    source: String,
+    /// Modules the document imports directly or transitively as `(name, uri, source)`, with
+    /// dependencies before the modules importing them.
+    dependencies: Vec<(String, Url, String)>,
+    /// The shader defs to validate the document with, named if they're a configured permutation.
+    permutations: Vec<(Option<String>, ShaderDefs)>,
+    validation_flags: ValidationFlags,
+    capabilities: CapabilitiesProfile,
+    encoding: PositionEncoding,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Emitted once no edits have been made to a document for the validation delay.
+pub struct ValidateAfterEdit {
+    uri: Url,
+    version: Option<i32>,
+}
+
+/// Emitted by the worker when it's done validating a document.
+pub struct ValidationFinished {
+    uri: Url,
+    version: Option<i32>,
+    /// An error for every permutation that failed.
+    errors: Vec<PublishDiagnosticsParams>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ValidationWorker {
+    pub fn spawn(client: ClientSocket) -> Self {
+        let (jobs, receiver) = mpsc::channel();
+        thread::spawn(move || run(client, receiver));
+        Self {
+            jobs,
+            pending: HashMap::new(),
+            in_flight: HashMap::new(),
+        }
+    }
+}
+
+impl WgslServerState {
+    /// Validate a document once it hasn't been edited for the validation delay.
+    ///
+    /// Validation of an older version of the document is cancelled.
+    pub fn schedule_validation(&mut self, uri: Url) {
+        self.cancel_validation(&uri);
+        let client = self.client.clone();
+        let delay = self.project(&uri).settings.validation_delay;
+        let event = ValidateAfterEdit {
+            uri: uri.clone(),
+            version: self.document_version(&uri),
+        };
+        let timer = tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            // this only fails if the main loop stopped
+            let _ = client.emit(event);
+        });
+        if let Some(previous) = self
+            .validation_worker
+            .pending
+            .insert(uri, timer.abort_handle())
+        {
+            previous.abort();
+        }
+    }
+
+    /// Build a document's module right away for a request that needs it, if it has never built
+    /// and isn't about to.
+    ///
+    /// Otherwise requests use the last module that built, so that edits only rebuild it once the
+    /// validation delay has passed rather than on every keystroke.
+    pub fn ensure_module(&mut self, uri: &Url) {
+        if self.cached_modules.contains_key(uri) || self.validation_worker.pending.contains_key(uri)
+        {
+            return;
+        }
+        let _ = validate_document(self, uri.clone());
+    }
+
+    /// Send the module last built from a document to the worker for validation.
+    ///
+    /// Diagnostics are published when it's done, unless the document has changed since.
+    pub fn submit_validation(&mut self, uri: &Url, version: Option<i32>) {
+        let Some(source) = self.open_documents.get(uri).map(OpenDocument::source) else {
+            return;
+        };
+        self.cancel_validation(uri);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let settings = &self.project(uri).settings;
+        let job = ValidationJob {
+            uri: uri.clone(),
+            version,
+            source,
+            dependencies: self.dependency_sources(uri),
+            permutations: self.permutations(uri),
+            validation_flags: settings.validation_flags,
+            capabilities: settings.capabilities,
+            encoding: self.position_encoding,
+            cancelled: cancelled.clone(),
+        };
+        self.validation_worker
+            .in_flight
+            .insert(uri.clone(), cancelled);
+        // the worker only stops if the main loop did
+        let _ = self.validation_worker.jobs.send(job);
+    }
+
+    /// Cancel validation of a document that was sent to the worker but hasn't finished.
+    pub fn cancel_validation(&mut self, uri: &Url) {
+        if let Some(cancelled) = self.validation_worker.in_flight.remove(uri) {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// The shader defs of every permutation configured for a document's project on top of the
+    /// base shader defs, or just the base shader defs if there are no permutations.
+    fn permutations(&self, uri: &Url) -> Vec<(Option<String>, ShaderDefs)> {
+        let settings = &self.project(uri).settings;
+        if settings.shader_def_permutations.is_empty() {
+            return vec![(None, settings.shader_defs.clone())];
+        }
+        settings
+            .shader_def_permutations
+            .iter()
+            .map(|(name, defs)| {
+                let mut shader_defs = settings.shader_defs.clone();
+                shader_defs.extend(defs.iter().map(|(def, value)| (def.clone(), *value)));
+                (Some(name.clone()), shader_defs)
+            })
+            .collect()
+    }
+
+    /// Sources of every module a document imports directly or transitively as
+    /// `(name, uri, source)`, with dependencies before the modules importing them.
+    fn dependency_sources(&self, uri: &Url) -> Vec<(String, Url, String)> {
+        fn visit(
+            st: &WgslServerState,
+            project: &Project,
+            uri: &Url,
+            visited: &mut Vec<String>,
+            sources: &mut Vec<(String, Url, String)>,
+        ) {
+            for dependency in project.dependency_graph.imports(uri) {
+                if visited.contains(dependency) {
+                    continue;
+                }
+                visited.push(dependency.clone());
+                let Some(dependency_uri) = project.module_lookup.get(dependency) else {
+                    continue;
+                };
+                visit(st, project, dependency_uri, visited, sources);
+                if let Some(document) = st.open_documents.get(dependency_uri) {
+                    sources.push((
+                        dependency.clone(),
+                        dependency_uri.clone(),
+                        document.source(),
+                    ));
+                }
+            }
+        }
+
+        let mut sources = Vec::new();
+        visit(self, self.project(uri), uri, &mut Vec::new(), &mut sources);
+        sources
+    }
+}
+
+/// Validate the document once edits have settled, if it hasn't changed again in the meantime.
+pub fn validate_after_edit(st: &mut WgslServerState, event: ValidateAfterEdit) -> NotifyResult {
+    st.validation_worker.pending.remove(&event.uri);
+    if st.document_version(&event.uri) != event.version {
+        return ControlFlow::Continue(());
+    }
+    let old_module = st
+        .project(&event.uri)
+        .dependency_graph
+        .module(&event.uri)
+        .map(str::to_owned);
+    let result = validate_document_and_dependents(st, event.uri, old_module);
+    // highlighting was computed from the module as it was before the edits
+    st.refresh_semantic_tokens();
+    result
+}
+
+/// Publish the worker's diagnostics for a document, unless the job was cancelled.
+pub fn validation_finished(st: &mut WgslServerState, event: ValidationFinished) -> NotifyResult {
+    if event.cancelled.load(Ordering::Relaxed) {
+        return ControlFlow::Continue(());
+    }
+    st.validation_worker.in_flight.remove(&event.uri);
+    st.publish_validation_diagnostics(&event.uri, event.version, event.errors)
+}
+
+fn run(client: ClientSocket, receiver: Receiver<ValidationJob>) {
+    let mut composer = Composer::default();
+    // the source each module in the composer was added from
+    let mut added: HashMap<String, String> = HashMap::new();
+    let mut queue = VecDeque::new();
+    loop {
+        if queue.is_empty() {
+            match receiver.recv() {
+                Ok(job) => queue.push_back(job),
+                Err(_) => return,
+            }
+        }
+        queue.extend(receiver.try_iter());
+        let job = queue.pop_front().unwrap();
+        if job.cancelled.load(Ordering::Relaxed) {
+            continue;
+        }
+        if composer.capabilities != job.capabilities.capabilities() {
+            // modules are checked against the capabilities when they're added
+            composer = Composer::default().with_capabilities(job.capabilities.capabilities());
+            added.clear();
+        }
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            validate(&mut composer, &mut added, &job)
+        }));
+        let errors = match result {
+            Ok(Some(errors)) => errors,
+            Ok(None) => continue,
+            Err(_) => {
+                // the composer may be half way through adding a module, so start over
+                composer = Composer::default().with_capabilities(job.capabilities.capabilities());
+                added.clear();
+                continue;
+            }
+        };
+        let finished = ValidationFinished {
+            uri: job.uri,
+            version: job.version,
+            errors,
+            cancelled: job.cancelled,
+        };
+        if client.emit(finished).is_err() {
+            return;
+        }
+    }
+}
+
+/// Validate the document of a job with every permutation of shader defs, returning the errors,
+/// or [None] if the job was cancelled part way through.
+///
+/// After an error in a function, its body is stubbed out and the document is validated again, so
+/// errors in other functions and modules are found too.
+fn validate(
+    composer: &mut Composer,
+    added: &mut HashMap<String, String>,
+    job: &ValidationJob,
+) -> Option<Vec<PublishDiagnosticsParams>> {
+    let mut sources: HashMap<Url, String> = job
+        .dependencies
+        .iter()
+        .map(|(_, uri, source)| (uri.clone(), source.clone()))
+        .chain([(job.uri.clone(), job.source.clone())])
+        .collect();
+
+    // errors adding dependencies don't depend on the shader defs, so they're only reported once
+    let mut errors = Vec::new();
+    while let Err(err) = add_dependencies(composer, added, job, &sources)? {
+        let mut error = error_to_diagnostic(err, composer, job);
+        let stubbed = stub_error(&mut sources, &mut error, job.encoding);
+        errors.push(error);
+        if !stubbed || errors.len() >= MAX_ERRORS {
+            // the document can't be built without the dependency
+            return Some(errors);
+        }
+    }
+
+    for (name, shader_defs) in &job.permutations {
+        let mut sources = sources.clone();
+        let mut permutation_errors = Vec::new();
+        while permutation_errors.len() < MAX_ERRORS {
+            // stubbing a function of a dependency means adding it again
+            let err = match add_dependencies(composer, added, job, &sources)? {
+                Ok(()) => validation_error(composer, job, &sources[&job.uri], shader_defs),
+                Err(err) => Some(err),
+            };
+            let Some(err) = err else {
+                break;
+            };
+            let mut error = error_to_diagnostic(err, composer, job);
+            let stubbed = stub_error(&mut sources, &mut error, job.encoding);
+            permutation_errors.push(error);
+            if !stubbed {
+                break;
+            }
+        }
+        for error in &mut permutation_errors {
+            for diagnostic in &mut error.diagnostics {
+                diagnostic.source = name.clone();
+            }
+        }
+        errors.extend(permutation_errors);
+    }
+    Some(errors)
+}
+
+/// Add the dependencies of a job to the composer from `sources`, returning the first error, or
+/// [None] if the job was cancelled part way through.
+fn add_dependencies(
+    composer: &mut Composer,
+    added: &mut HashMap<String, String>,
+    job: &ValidationJob,
+    sources: &HashMap<Url, String>,
+) -> Option<Result<(), ComposerError>> {
+    for (name, uri, _) in &job.dependencies {
+        if job.cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+        let source = &sources[uri];
+        // adding a module removes the modules importing it, so they're checked again
+        if added.get(name) == Some(source) && composer.contains_module(name) {
+            continue;
+        }
+        added.remove(name);
+        let result = composer.add_composable_module(ComposableModuleDescriptor {
+            as_name: Some(name.clone()),
+            file_path: uri.as_str(),
+            source,
+            ..Default::default()
+        });
+        if let Err(err) = result {
+            return Some(Err(err));
+        }
+        added.insert(name.clone(), source.clone());
+    }
+    Some(Ok(()))
+}
+
+/// Stub out the function an error is in, so that validating again finds the next error. Returns
+/// whether there was a function to stub.
+///
+/// If functions calling it had to be stubbed too, the error says that they weren't checked.
+fn stub_error(
+    sources: &mut HashMap<Url, String>,
+    error: &mut PublishDiagnosticsParams,
+    encoding: PositionEncoding,
+) -> bool {
+    let (Some(source), Some(diagnostic)) =
+        (sources.get_mut(&error.uri), error.diagnostics.first_mut())
+    else {
+        return false;
+    };
+    let offset = LineIndex::new(source, encoding).offset(diagnostic.range.start);
+    match syntax::stub_function(source, &tokenize(source), offset) {
+        Some(stub) => {
+            *source = stub.source;
+            if !stub.dependents.is_empty() {
+                let dependents = stub.dependents.iter().map(|name| format!("`{name}`"));
+                diagnostic.message += &format!(
+                    "\n\nNot checked, since they call a function with this error: {}",
+                    dependents.collect::<Vec<_>>().join(", ")
+                );
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+/// Build and validate the document of a job from `source` with the configured validation flags,
+/// returning the first error.
+///
+/// The composer always validates with every flag, so if it finds an error, the module is built
+/// again without validation and checked with just the configured flags.
+fn validation_error(
+    composer: &mut Composer,
+    job: &ValidationJob,
+    source: &str,
+    shader_defs: &ShaderDefs,
+) -> Option<ComposerError> {
+    let descriptor = || NagaModuleDescriptor {
+        source,
+        file_path: job.uri.as_str(),
+        shader_defs: shader_defs.clone(),
+        ..Default::default()
+    };
+    let mut err = composer.make_naga_module(descriptor()).err()?;
+    let ComposerErrorInner::ShaderValidationError(composer_error) = &mut err.inner else {
+        return Some(err);
+    };
+    if job.validation_flags == ValidationFlags::all() {
+        return Some(err);
+    }
+
+    composer.validate = false;
+    let module = composer.make_naga_module(descriptor());
+    composer.validate = true;
+    let Ok(module) = module else {
+        return Some(err);
+    };
+    let error = Validator::new(job.validation_flags, job.capabilities.capabilities())
+        .validate(&module)
+        .err()?;
+    // the composer's error knows which module its spans are in, so its spans can only be swapped
+    // for ones in the same module
+    let module_index = |error: &WithSpan<_>| {
+        error
+            .spans()
+            .last()
+            .and_then(|(span, _)| span.to_range())
+            .map(|range| range.start >> SPAN_SHIFT)
+    };
+    let index = module_index(&error);
+    if index == module_index(composer_error) {
+        *composer_error = error;
+        return Some(err);
+    }
+    // where the source of another module starts isn't known, so the error is reported for the
+    // module as a whole
+    let source = match index.and_then(|index| composer.module_index.get(&index)) {
+        Some(name) => ErrSource::Module {
+            name: name.clone(),
+            offset: 0,
+            defs: shader_defs.clone(),
+        },
+        None => ErrSource::Constructing {
+            path: job.uri.to_string(),
+            source: source.to_owned(),
+            offset: 0,
+        },
+    };
+    Some(ComposerError {
+        inner: ComposerErrorInner::ShaderValidationError(WithSpan::new(error.into_inner())),
+        source,
+    })
+}
+
+/// Convert an error to a diagnostic, explaining which capabilities it needs if they're missing
+/// from the configured profile.
+fn error_to_diagnostic(
+    err: ComposerError,
+    composer: &Composer,
+    job: &ValidationJob,
+) -> PublishDiagnosticsParams {
+    let missing = match &err.inner {
+        ComposerErrorInner::HeaderValidationError(error)
+        | ComposerErrorInner::ShaderValidationError(error) => {
+            required_capabilities(error) - job.capabilities.capabilities()
+        }
+        _ => Capabilities::empty(),
+    };
+    let mut error = composer_error_to_diagnostic(err, composer, job.encoding);
+    if missing.is_empty() {
+        return error;
+    }
+
+    let names: Vec<String> = missing
+        .iter_names()
+        .map(|(name, _)| format!("`{name}`"))
+        .collect();
+    let explanation = match names.as_slice() {
+        [name] => format!(
+            "The {name} capability isn't enabled by {}",
+            job.capabilities
+        ),
+        names => format!(
+            "The {} capabilities aren't enabled by {}",
+            names.join(", "),
+            job.capabilities
+        ),
+    };
+    for diagnostic in &mut error.diagnostics {
+        diagnostic.message = format!("{explanation}\n\n{}", diagnostic.message);
+        diagnostic.code = Some(NumberOrString::String("missing-capability".to_owned()));
+    }
+    error
+}
+
+/// The capabilities a validation error says are needed, found anywhere in its chain of sources.
+fn required_capabilities(error: &WithSpan<ValidationError>) -> Capabilities {
+    let mut required = Capabilities::empty();
+    let mut source: Option<&(dyn Error + 'static)> = Some(error.as_inner());
+    while let Some(error) = source {
+        if let Some(TypeError::MissingCapability(capabilities)) = error.downcast_ref() {
+            required |= *capabilities;
+        } else if let Some(ExpressionError::MissingCapabilities(capabilities)) =
+            error.downcast_ref()
+        {
+            required |= *capabilities;
+        } else if let Some(VaryingError::UnsupportedCapability(capabilities)) = error.downcast_ref()
+        {
+            required |= *capabilities;
+        } else if let Some(GlobalVariableError::UnsupportedCapability(capabilities)) =
+            error.downcast_ref()
+        {
+            required |= *capabilities;
+        } else if is_width_error(error) {
+            // naga doesn't export the error for scalar widths, but the only width that needs a
+            // capability is that of `f64`, and WGSL can't spell the other invalid widths
+            required |= Capabilities::FLOAT64;
+        }
+        source = error.source();
+    }
+    required
+}
+
+/// Whether an error is about the width of a scalar type or literal.
+fn is_width_error(error: &(dyn Error + 'static)) -> bool {
+    matches!(error.downcast_ref(), Some(TypeError::WidthError(_)))
+        || matches!(
+            error.downcast_ref(),
+            Some(ExpressionError::Literal(LiteralError::Width(_)))
+        )
+        || matches!(
+            error.downcast_ref(),
+            Some(
+                ConstExpressionError::Literal(LiteralError::Width(_))
+                    | ConstExpressionError::Width(_)
+            )
+        )
+        || matches!(error.downcast_ref(), Some(LiteralError::Width(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn required(source: &str) -> Capabilities {
+        let module = naga::front::wgsl::parse_str(source).unwrap();
+        let error = Validator::new(ValidationFlags::all(), Capabilities::empty())
+            .validate(&module)
+            .unwrap_err();
+        required_capabilities(&error)
+    }
+
+    #[test]
+    fn f64_needs_float64() {
+        assert_eq!(
+            required("fn f() { var x: f64 = 1.0lf; }"),
+            Capabilities::FLOAT64
+        );
+    }
+
+    #[test]
+    fn push_constants_need_push_constant() {
+        assert_eq!(
+            required("var<push_constant> p: f32;\nfn f() -> f32 { return p; }"),
+            Capabilities::PUSH_CONSTANT
+        );
+    }
+
+    #[test]
+    fn builtins_need_their_capability() {
+        assert_eq!(
+            required(
+                "@fragment\nfn f(@builtin(primitive_index) i: u32) -> @location(0) vec4<f32> {\n    return vec4(f32(i));\n}"
+            ),
+            Capabilities::PRIMITIVE_INDEX
+        );
+    }
+
+    #[test]
+    fn other_errors_need_nothing() {
+        assert_eq!(
+            required("fn f() -> f32 { return 1; }"),
+            Capabilities::empty()
+        );
+    }
+}